@@ -0,0 +1,168 @@
+//! Abbreviations, defined by `*[HTML]: HyperText Markup Language` lines in a markdown or by
+//! `[abbreviations]` in the config, and expanded into `<abbr title="...">` in the content.
+
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use crate::html;
+
+/// Abbreviation => its expansion.
+pub type Abbreviations = BTreeMap<String, String>;
+
+/// Deserialize `[abbreviations]` in the config, rejecting blank abbreviations, which would
+/// match everywhere.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Abbreviations, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let abbreviations = <Abbreviations as serde::Deserialize>::deserialize(deserializer)?;
+    if abbreviations.keys().any(|abbr| abbr.trim().is_empty()) {
+        return Err(serde::de::Error::custom(
+            "an abbreviation must not be empty",
+        ));
+    }
+    Ok(abbreviations)
+}
+
+/// Elements whose text is not expanded. Headings are excluded so that tables of contents and
+/// heading links stay plain.
+const SKIPPED: &[&str] = &[
+    "abbr", "code", "pre", "kbd", "samp", "script", "style", "h1", "h2", "h3", "h4", "h5", "h6",
+];
+
+/// Split `markdown` into the markdown without definition lines, and the definitions. Lines in
+/// code blocks are kept.
+pub fn split(markdown: &str) -> (String, Abbreviations) {
+    static DEFINITION: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\*\[([^\]]+)\]:\s*(.*?)\s*$").unwrap());
+
+    let mut abbreviations = Abbreviations::new();
+    if !markdown.contains("*[") {
+        return (markdown.to_string(), abbreviations);
+    }
+    let mut out = String::with_capacity(markdown.len());
+    let mut fence = None::<String>;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(f) = fence.as_ref() {
+            if trimmed.starts_with(f.as_str()) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(trimmed[..3].to_string());
+        } else if let Some(caps) = DEFINITION.captures(line.trim_end()) {
+            abbreviations.insert(caps[1].to_string(), caps[2].to_string());
+            continue;
+        }
+        out.push_str(line);
+    }
+    (out, abbreviations)
+}
+
+/// Wrap whole-word occurrences of `abbreviations` in the text of `html` with `<abbr>`.
+pub fn expand(html: &str, abbreviations: &Abbreviations) -> String {
+    static TAG: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9]*)?[^>]*>").unwrap());
+
+    if abbreviations.is_empty() {
+        return html.to_string();
+    }
+    // The text of HTML is escaped. Longer ones first, e.g. `HTML5` before `HTML`.
+    let mut escaped = abbreviations
+        .iter()
+        .map(|(abbr, title)| (html::escape(abbr), html::escape(title)))
+        .collect::<Vec<_>>();
+    escaped.sort_by_key(|(abbr, _)| std::cmp::Reverse(abbr.len()));
+    let regex = Regex::new(
+        &escaped
+            .iter()
+            .map(|(abbr, _)| regex::escape(abbr))
+            .collect::<Vec<_>>()
+            .join("|"),
+    )
+    .unwrap();
+    let titles = escaped.into_iter().collect::<BTreeMap<_, _>>();
+
+    let mut out = String::with_capacity(html.len());
+    let mut skipped = 0_usize;
+    let mut last = 0;
+    for tag in TAG.captures_iter(html) {
+        let m = tag.get(0).unwrap();
+        let text = &html[last..m.start()];
+        if skipped == 0 {
+            push_expanded(&mut out, text, &regex, &titles);
+        } else {
+            out.push_str(text);
+        }
+        out.push_str(m.as_str());
+        last = m.end();
+        if let Some(name) = tag.get(2) {
+            if SKIPPED.contains(&name.as_str().to_ascii_lowercase().as_str()) {
+                if tag[1].is_empty() {
+                    skipped += 1;
+                } else {
+                    skipped = skipped.saturating_sub(1);
+                }
+            }
+        }
+    }
+    if skipped == 0 {
+        push_expanded(&mut out, &html[last..], &regex, &titles);
+    } else {
+        out.push_str(&html[last..]);
+    }
+    out
+}
+
+fn push_expanded(out: &mut String, text: &str, regex: &Regex, titles: &BTreeMap<String, String>) {
+    let mut last = 0;
+    for m in regex.find_iter(text) {
+        // Whole words only, e.g. not `API` in `APIs`.
+        if text[..m.start()]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric)
+            || text[m.end()..]
+                .chars()
+                .next()
+                .is_some_and(char::is_alphanumeric)
+        {
+            continue;
+        }
+        out.push_str(&text[last..m.start()]);
+        out.push_str(&format!(
+            r#"<abbr title="{}">{}</abbr>"#,
+            titles[m.as_str()],
+            m.as_str()
+        ));
+        last = m.end();
+    }
+    out.push_str(&text[last..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbr_test() {
+        let (markdown, abbreviations) = split(
+            "HTML and AT&T.\n\n*[HTML]: HyperText Markup Language\n*[AT&T]: American \"Telephone\"\n\n```\n*[CSS]: kept\n```\n",
+        );
+        assert_eq!(markdown, "HTML and AT&T.\n\n\n```\n*[CSS]: kept\n```\n");
+        assert_eq!(abbreviations.len(), 2);
+
+        let mut abbreviations = abbreviations;
+        abbreviations.insert("HTML5".to_string(), "HTML, version 5".to_string());
+        assert_eq!(
+            expand(
+                r#"<h2 id="html">HTML</h2>
+<p>HTML5, HTMLs and <a href="/html">HTML</a> by AT&amp;T. <code>HTML</code></p>"#,
+                &abbreviations
+            ),
+            r#"<h2 id="html">HTML</h2>
+<p><abbr title="HTML, version 5">HTML5</abbr>, HTMLs and <a href="/html"><abbr title="HyperText Markup Language">HTML</abbr></a> by <abbr title="American &quot;Telephone&quot;">AT&amp;T</abbr>. <code>HTML</code></p>"#
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! A read-only JSON API of published articles, served by `site daemon --api-addr`.
+
+use anyhow::{Context as _, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read as _, Write as _};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::site::Article;
+
+/// Fields which are omitted in `/api/articles`, since they are large.
+const BODY_FIELDS: &[&str] = &["content", "toc_html"];
+
+/// How long a client may take to send a request, so that a slow one doesn't hold a thread.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of threads which handle connections. More connections wait to be accepted.
+const WORKERS: usize = 4;
+
+/// The maximum length of the request line and of each header line.
+const MAX_LINE: usize = 8 * 1024;
+
+/// The maximum number of header lines.
+const MAX_HEADERS: usize = 100;
+
+/// Articles of the latest build, newest first, as JSON objects.
+#[derive(Debug, Default)]
+pub struct Api {
+    articles: RwLock<Vec<Value>>,
+}
+
+impl Api {
+    pub fn update(&self, articles: &[Article]) {
+        let articles = articles
+            .iter()
+            .map(|article| serde_json::to_value(article).unwrap())
+            .collect();
+        *self.articles.write().unwrap() = articles;
+    }
+
+    /// Serve the API on `listener`, forever, in a new thread. Connections are handled by
+    /// `WORKERS` threads; while all of them are busy, new ones are not accepted.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..WORKERS {
+            let api = self.clone();
+            let receiver = receiver.clone();
+            std::thread::spawn(move || loop {
+                let Ok(stream) = receiver.lock().unwrap().recv() else {
+                    return;
+                };
+                if let Err(e) = api.handle(stream) {
+                    log::warn!("api: {e:#}");
+                }
+            });
+        }
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if sender.send(stream).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => log::warn!("api: {e:#}"),
+                }
+            }
+        });
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_write_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        let (status, body) = if !read_line(&mut reader, &mut request_line)? {
+            (414, json!({ "error": "request line too long" }))
+        } else if !read_headers(&mut reader)? {
+            (431, json!({ "error": "request header fields too large" }))
+        } else {
+            match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["GET", target, _] => self.respond(target),
+                _ => (405, json!({ "error": "method not allowed" })),
+            }
+        };
+        let body = serde_json::to_string(&body)?;
+        let reason = match status {
+            200 => "OK",
+            404 => "Not Found",
+            414 => "URI Too Long",
+            431 => "Request Header Fields Too Large",
+            _ => "Method Not Allowed",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        if matches!(status, 414 | 431) {
+            // Discard some of the rest of the request, so that closing with unread data doesn't
+            // reset the connection before the client reads the response.
+            stream.shutdown(std::net::Shutdown::Write)?;
+            std::io::copy(&mut (&stream).take(MAX_LINE as u64), &mut std::io::sink()).ok();
+        }
+        Ok(())
+    }
+
+    /// The status and the body for the request target, such as `/api/articles?tag=rust`.
+    fn respond(&self, target: &str) -> (u16, Value) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let path = crate::text::percent_decode(path);
+        let articles = self.articles.read().unwrap();
+        let not_found = (404, json!({ "error": "not found" }));
+        match path.trim_end_matches('/') {
+            "/api/articles" => {
+                let tag = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("tag="))
+                    .map(crate::text::percent_decode);
+                let list = articles
+                    .iter()
+                    .filter(|article| {
+                        tag.as_ref().is_none_or(|tag| {
+                            article["tags"].as_array().unwrap().contains(&json!(tag))
+                        })
+                    })
+                    .map(|article| {
+                        let mut article = article.clone();
+                        for field in BODY_FIELDS {
+                            article.as_object_mut().unwrap().remove(*field);
+                        }
+                        article
+                    })
+                    .collect::<Vec<_>>();
+                (200, Value::Array(list))
+            }
+            "/api/tags" => {
+                let mut tags = BTreeMap::<&str, usize>::new();
+                for article in articles.iter() {
+                    for tag in article["tags"].as_array().unwrap() {
+                        *tags.entry(tag.as_str().unwrap()).or_default() += 1;
+                    }
+                }
+                (200, json!(tags))
+            }
+            path => match path.strip_prefix("/api/articles/") {
+                // By the slug, or by the URL such as `notes/foo`.
+                Some(slug) => articles
+                    .iter()
+                    .find(|article| {
+                        article["slug"] == slug
+                            || article["url"].as_str().unwrap().trim_end_matches('/') == slug
+                    })
+                    .map_or(not_found, |article| (200, article.clone())),
+                None => not_found,
+            },
+        }
+    }
+}
+
+/// Read a line into `line`, up to `MAX_LINE` bytes. Returns false if it's longer.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<bool> {
+    let n = reader
+        .take(MAX_LINE as u64 + 1)
+        .read_line(line)
+        .context("can not read a request")?;
+    Ok(n <= MAX_LINE)
+}
+
+/// Read the headers up to the blank line, so that the client sees the response, not a reset
+/// connection, when it's closed. Returns false if there are too many or too long ones.
+fn read_headers(reader: &mut impl BufRead) -> Result<bool> {
+    let mut header = String::new();
+    for _ in 0..=MAX_HEADERS {
+        header.clear();
+        if !read_line(reader, &mut header)? {
+            return Ok(false);
+        }
+        if header.trim_end().is_empty() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respond_test() {
+        let api = Api {
+            articles: RwLock::new(vec![
+                json!({ "slug": "b", "url": "notes/b/", "tags": ["rust"], "content": "<p>B</p>" }),
+                json!({ "slug": "a", "url": "a/", "tags": ["rust", "web"], "content": "<p>A</p>" }),
+            ]),
+        };
+        assert_eq!(
+            api.respond("/api/articles?tag=web"),
+            (
+                200,
+                json!([{ "slug": "a", "url": "a/", "tags": ["rust", "web"] }])
+            )
+        );
+        assert_eq!(api.respond("/api/articles").1.as_array().unwrap().len(), 2);
+        assert_eq!(
+            api.respond("/api/tags"),
+            (200, json!({ "rust": 2, "web": 1 }))
+        );
+        assert_eq!(
+            api.respond("/api/articles/notes/b").1["content"],
+            "<p>B</p>"
+        );
+        assert_eq!(api.respond("/api/articles/a").1["slug"], "a");
+        assert_eq!(api.respond("/api/articles/c").0, 404);
+        assert_eq!(api.respond("/index.html").0, 404);
+    }
+
+    #[test]
+    fn serve_test() {
+        let api = Arc::new(Api::default());
+        api.update(&[]);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        api.serve(listener);
+
+        // A client which doesn't send anything doesn't block others.
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /api/tags HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with("\r\n\r\n{}"), "{response}");
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET /api/tags HTTP/1.1\r\nX: {}\r\n\r\n",
+            "x".repeat(MAX_LINE)
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"),
+            "{response}"
+        );
+    }
+
+    #[test]
+    fn read_headers_test() {
+        let mut line = String::new();
+        assert!(read_line(&mut "GET / HTTP/1.1\r\n".as_bytes(), &mut line).unwrap());
+        assert!(!read_line(&mut "x".repeat(MAX_LINE + 1).as_bytes(), &mut line).unwrap());
+        assert!(read_headers(&mut "Host: a\r\n\r\n".as_bytes()).unwrap());
+        // A closed connection ends the headers.
+        assert!(read_headers(&mut "Host: a\r\n".as_bytes()).unwrap());
+        assert!(!read_headers(&mut "X: a\r\n".repeat(MAX_HEADERS + 1).as_bytes()).unwrap());
+    }
+}
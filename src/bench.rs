@@ -0,0 +1,90 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+/// Markdown blocks which synthesized articles are made of.
+const BLOCKS: &[&str] = &[
+    "## Section\n\nLorem ipsum dolor sit amet, *consectetur* adipiscing elit, sed do **eiusmod** tempor incididunt ut labore et dolore magna aliqua. See [the docs](https://example.com/docs).\n\n",
+    "- Ut enim ad minim veniam\n- Quis nostrud `exercitation` ullamco\n- Laboris nisi ut aliquip\n\n",
+    "```rust\nfn main() {\n    println!(\"Hello, world!\");\n}\n```\n\n",
+    "> Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur.[^1]\n\n[^1]: Excepteur sint occaecat.\n\n",
+    "| a | b | c |\n| - | - | - |\n| 1 | 2 | 3 |\n| 4 | 5 | 6 |\n\n",
+    "### Subsection\n\n日本語の文章も\n含めます。Sed ut perspiciatis unde omnis iste natus error sit voluptatem.\n\n",
+];
+
+/// Write `articles` markdowns of about `size` bytes each into `root_dir/src`, and minimal
+/// templates into `root_dir/template`.
+pub fn synthesize(root_dir: &Path, articles: usize, size: usize) -> Result<()> {
+    let src_dir = root_dir.join("src");
+    let template_dir = root_dir.join("template");
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&template_dir)?;
+    std::fs::write(
+        template_dir.join("article.jinja"),
+        "<!DOCTYPE html>\n<title>{{ entry.title }}</title>\n<h1>{{ entry.title }}</h1>\n{{ entry.content }}\n",
+    )?;
+    std::fs::write(
+        template_dir.join("page.jinja"),
+        "<!DOCTYPE html>\n{% for a in articles %}<a href=\"/{{ a.url }}\">{{ a.title }}</a>\n{% endfor %}",
+    )?;
+    std::fs::write(
+        src_dir.join("index.md"),
+        "# Index\n\n<!--\npage = true\n-->\n",
+    )?;
+    for i in 0..articles {
+        let mut s = format!(
+            "# Article {i}\n\n<!--\ndate = \"{}\"\ntags = [\"tag{}\"]\n-->\n\n",
+            chrono::NaiveDate::from_ymd_opt(2000, 1, 1).unwrap() + chrono::Days::new(i as u64),
+            i % 10
+        );
+        for block in BLOCKS.iter().cycle() {
+            if s.len() >= size {
+                break;
+            }
+            s.push_str(block);
+        }
+        std::fs::write(src_dir.join(format!("article-{i}.md")), s)?;
+    }
+    Ok(())
+}
+
+/// The time taken by a phase of a build.
+#[derive(Debug)]
+pub struct Phase {
+    pub name: &'static str,
+    pub duration: Duration,
+    /// The number of entries processed.
+    pub entries: usize,
+    /// The number of bytes processed, e.g. markdown for parsing, HTML for writing.
+    pub bytes: usize,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.duration.as_secs_f64();
+        write!(
+            f,
+            "{:10} {:>8.3} s {:>10.0} entries/s {:>8.1} MB/s",
+            self.name,
+            secs,
+            self.entries as f64 / secs,
+            self.bytes as f64 / 1_000_000.0 / secs
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthesize_test() {
+        let dir = crate::testing::TempDir::new("bench");
+        synthesize(&dir, 3, 1000).unwrap();
+        let s = std::fs::read_to_string(dir.join("src/article-2.md")).unwrap();
+        assert!(s.starts_with("# Article 2\n\n<!--\ndate = \"2000-01-03\""));
+        assert!(s.len() >= 1000);
+        assert!(dir.join("src/index.md").exists());
+        assert!(dir.join("template/article.jinja").exists());
+    }
+}
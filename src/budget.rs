@@ -0,0 +1,142 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::io::Write as _;
+use std::path::Path;
+
+use crate::diagnostic::Diagnostic;
+
+const IMAGES: &[&str] = &["avif", "gif", "jpeg", "jpg", "png", "svg", "webp"];
+
+/// `[budgets]`: limits of the sizes of output files in bytes, checked after a build.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    /// The size of each HTML file.
+    pub max_html: Option<u64>,
+    /// The total size of CSS files.
+    pub max_css: Option<u64>,
+    /// The size of each image.
+    pub max_image: Option<u64>,
+    /// Measure HTML and CSS gzipped, as they are usually served. Images are measured as they
+    /// are, since they are already compressed.
+    #[serde(default)]
+    pub compressed: bool,
+}
+
+impl BudgetConfig {
+    /// Check the files in `out_dir`. The files of the problems are relative to `out_dir`.
+    pub fn check(&self, out_dir: &Path) -> Result<Vec<Diagnostic>> {
+        let mut problems = Vec::new();
+        let mut css = 0;
+        let mut files = 0;
+        for entry in walkdir::WalkDir::new(out_dir).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path().strip_prefix(out_dir)?;
+            let ext = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let (max, size) = match ext.as_str() {
+                "html" => (self.max_html, self.size(entry.path(), true)?),
+                "css" => {
+                    css += self.size(entry.path(), true)?;
+                    continue;
+                }
+                ext if IMAGES.contains(&ext) => (self.max_image, self.size(entry.path(), false)?),
+                _ => continue,
+            };
+            files += 1;
+            if let Some(max) = max.filter(|max| size > *max) {
+                problems.push(Diagnostic::warning(
+                    Some(crate::site::slash_path(path)),
+                    None,
+                    format!("{} exceeds the budget of {}", self.describe(size), kib(max)),
+                ));
+            }
+        }
+        if let Some(max) = self.max_css.filter(|max| css > *max) {
+            problems.push(Diagnostic::warning(
+                None,
+                None,
+                format!(
+                    "CSS files: {} in total exceeds the budget of {}",
+                    self.describe(css),
+                    kib(max)
+                ),
+            ));
+        }
+        log::info!(
+            "{:32} => {} file(s) checked, {} over budget, CSS {}",
+            "Budgets",
+            files,
+            problems.len(),
+            self.describe(css)
+        );
+        Ok(problems)
+    }
+
+    /// The size of `path`, gzipped if `compress` and `compressed`.
+    fn size(&self, path: &Path, compress: bool) -> Result<u64> {
+        if !(compress && self.compressed) {
+            return Ok(std::fs::metadata(path)?.len());
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&std::fs::read(path)?)?;
+        Ok(encoder.finish()?.len() as u64)
+    }
+
+    fn describe(&self, size: u64) -> String {
+        if self.compressed {
+            format!("{} gzipped", kib(size))
+        } else {
+            kib(size)
+        }
+    }
+}
+
+fn kib(size: u64) -> String {
+    format!("{:.1} KiB", size as f64 / 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_test() {
+        let dir = crate::testing::TempDir::new("budget");
+        std::fs::create_dir_all(dir.join("about")).unwrap();
+        std::fs::write(dir.join("index.html"), "a".repeat(2000)).unwrap();
+        std::fs::write(dir.join("about/index.html"), "a".repeat(100)).unwrap();
+        std::fs::write(dir.join("a.css"), "b".repeat(600)).unwrap();
+        std::fs::write(dir.join("b.css"), "b".repeat(600)).unwrap();
+        std::fs::write(dir.join("cover.png"), [0; 3000]).unwrap();
+
+        let mut budgets = BudgetConfig {
+            max_html: Some(1024),
+            max_css: Some(1024),
+            max_image: Some(4096),
+            compressed: false,
+        };
+        let problems = budgets
+            .check(&dir)
+            .unwrap()
+            .iter()
+            .map(Diagnostic::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                "index.html: 2.0 KiB exceeds the budget of 1.0 KiB",
+                "CSS files: 1.2 KiB in total exceeds the budget of 1.0 KiB",
+            ]
+        );
+
+        // Repeated characters are compressed well.
+        budgets.compressed = true;
+        assert_eq!(budgets.check(&dir).unwrap(), vec![]);
+    }
+}
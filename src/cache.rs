@@ -0,0 +1,97 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A content-addressed cache of rendered HTML, persisted across builds.
+#[derive(Debug)]
+pub struct RenderCache {
+    dir: PathBuf,
+    /// Where assets referenced by markdowns are, whose hashes are a part of keys.
+    src_dir: PathBuf,
+}
+
+impl RenderCache {
+    pub fn new(dir: impl Into<PathBuf>, src_dir: impl Into<PathBuf>) -> RenderCache {
+        RenderCache {
+            dir: dir.into(),
+            src_dir: src_dir.into(),
+        }
+    }
+
+    /// A hash of `assets`, relative to `src`, and their contents, for a key, so that editing an
+    /// asset, such as an image, invalidates the markdowns referencing it. Missing ones count as
+    /// empty.
+    pub fn asset_hash(&self, assets: &[PathBuf]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        for asset in assets {
+            let content = std::fs::read(self.src_dir.join(asset)).unwrap_or_default();
+            hasher.update(asset.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(Sha256::digest(content));
+        }
+        hasher.finalize().to_vec()
+    }
+
+    /// Return the cached value for `key`, or compute it with `f` and cache it. The version of
+    /// **Site** is a part of the key, since the renderer may change.
+    pub fn get_or_insert_with(&self, key: &[&[u8]], f: impl FnOnce() -> String) -> String {
+        let mut hasher = Sha256::new().chain_update(env!("CARGO_PKG_VERSION"));
+        for part in key {
+            // Prefix lengths so that the boundaries of parts matter.
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        let hash = format!("{:x}", hasher.finalize());
+        let path = self.dir.join(&hash[..2]).join(&hash[2..]);
+        if let Ok(value) = std::fs::read_to_string(&path) {
+            log::debug!("cache hit: {hash}");
+            return value;
+        }
+        let value = f();
+        if let Err(e) = write_atomically(&path, &value) {
+            log::warn!("can not write cache: {}: {}", path.display(), e);
+        }
+        value
+    }
+}
+
+/// Write to a temporary file, and rename it, so that a reader never sees a partial file.
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let tmp = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_cache_test() {
+        let dir = crate::testing::TempDir::new("cache");
+        let cache = RenderCache::new(dir.to_path_buf(), dir.to_path_buf());
+        assert_eq!(
+            cache.get_or_insert_with(&[b"a", b"b"], || "x".to_string()),
+            "x"
+        );
+        // A hit doesn't call `f`.
+        assert_eq!(
+            cache.get_or_insert_with(&[b"a", b"b"], || unreachable!()),
+            "x"
+        );
+        assert_eq!(cache.get_or_insert_with(&[b"ab"], || "y".to_string()), "y");
+
+        let assets = [PathBuf::from("a.png")];
+        let empty = cache.asset_hash(&assets);
+        std::fs::write(dir.join("a.png"), "a").unwrap();
+        assert_ne!(cache.asset_hash(&assets), empty);
+    }
+}
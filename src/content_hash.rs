@@ -0,0 +1,181 @@
+//! `update_date_from_hash`: keep "last updated" honest by recording the hash of the content of
+//! each article, and noticing changes made without `update_date`.
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// What to do when the content of an article changes without `update_date`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HashMode {
+    /// Warn, until `update_date` is given.
+    Warn,
+    /// Use the date of the change as `update_date`, recorded in the state file. The source is
+    /// not changed.
+    Stamp,
+}
+
+/// The state file, which should be committed, so that it's kept across builds, e.g. in CI.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct HashState {
+    /// The path of a markdown, relative to `src` => its record.
+    articles: BTreeMap<String, Record>,
+    /// Articles checked in this build.
+    #[serde(skip)]
+    checked: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+struct Record {
+    hash: String,
+    /// The date when a change was stamped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    update_date: Option<chrono::NaiveDate>,
+    /// `update_date` of the article when `hash` was recorded. Changing it sets a new baseline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explicit_update_date: Option<chrono::NaiveDate>,
+}
+
+/// The result of `HashState::check`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// New, unchanged, or having a new `update_date`.
+    None,
+    /// Changed without `update_date`, which should be warned.
+    Unstamped,
+    /// Changed, now or before, and stamped with the date.
+    Stamped(chrono::NaiveDate),
+}
+
+impl HashState {
+    pub const FILE: &'static str = "content-hashes.json";
+
+    pub fn read(path: &Path) -> Result<HashState> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s)
+                .with_context(|| format!("invalid state file: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// Compare `content` of the article at `path`, whose `update_date` is `update_date`, with
+    /// the recorded one, and record it.
+    pub fn check(
+        &mut self,
+        path: &str,
+        content: &str,
+        update_date: Option<chrono::NaiveDate>,
+        mode: HashMode,
+        today: chrono::NaiveDate,
+    ) -> Change {
+        let hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+        self.checked.insert(path.to_string());
+        let record = self
+            .articles
+            .entry(path.to_string())
+            .or_insert_with(|| Record {
+                hash: hash.clone(),
+                update_date: None,
+                explicit_update_date: update_date,
+            });
+        if update_date.is_some() && update_date != record.explicit_update_date {
+            // A new explicit `update_date` wins, and is a new baseline. Changes after it are
+            // noticed as usual.
+            *record = Record {
+                hash,
+                update_date: None,
+                explicit_update_date: update_date,
+            };
+            return Change::None;
+        }
+        match (record.hash == hash, mode) {
+            (true, _) => record.update_date.map_or(Change::None, Change::Stamped),
+            // Not recorded, so that it's warned until `update_date` is given.
+            (false, HashMode::Warn) => Change::Unstamped,
+            (false, HashMode::Stamp) => {
+                *record = Record {
+                    hash,
+                    update_date: Some(today),
+                    explicit_update_date: record.explicit_update_date,
+                };
+                Change::Stamped(today)
+            }
+        }
+    }
+
+    /// Forget articles which have not been checked, e.g. removed ones.
+    pub fn retain_checked(&mut self) {
+        let checked = &self.checked;
+        self.articles.retain(|path, _| checked.contains(path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_test() {
+        let day = |d| chrono::NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let mut state = HashState::default();
+        assert_eq!(
+            state.check("a.md", "v1", None, HashMode::Stamp, day(1)),
+            Change::None
+        );
+        assert_eq!(
+            state.check("a.md", "v2", None, HashMode::Stamp, day(2)),
+            Change::Stamped(day(2))
+        );
+        // The stamp is kept.
+        assert_eq!(
+            state.check("a.md", "v2", None, HashMode::Stamp, day(3)),
+            Change::Stamped(day(2))
+        );
+        assert_eq!(
+            state.check("a.md", "v3", Some(day(4)), HashMode::Stamp, day(4)),
+            Change::None
+        );
+        // A change after the explicit `update_date` is stamped.
+        assert_eq!(
+            state.check("a.md", "v4", Some(day(4)), HashMode::Stamp, day(5)),
+            Change::Stamped(day(5))
+        );
+
+        assert_eq!(
+            state.check("b.md", "v1", None, HashMode::Warn, day(1)),
+            Change::None
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                state.check("b.md", "v2", None, HashMode::Warn, day(2)),
+                Change::Unstamped
+            );
+        }
+        assert_eq!(
+            state.check("b.md", "v2", Some(day(3)), HashMode::Warn, day(3)),
+            Change::None
+        );
+        assert_eq!(
+            state.check("b.md", "v2", Some(day(3)), HashMode::Warn, day(4)),
+            Change::None
+        );
+        // Without a new `update_date`, a change is warned even if the article has one.
+        assert_eq!(
+            state.check("b.md", "v3", Some(day(3)), HashMode::Warn, day(4)),
+            Change::Unstamped
+        );
+        assert_eq!(
+            state.check("b.md", "v3", Some(day(4)), HashMode::Warn, day(4)),
+            Change::None
+        );
+    }
+}
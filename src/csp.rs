@@ -0,0 +1,115 @@
+use base64::Engine as _;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+/// The hash sources of inline `<script>` and `<style>` elements, such as `'sha256-...'`.
+#[derive(Default, Debug)]
+pub struct InlineHashes {
+    pub scripts: BTreeSet<String>,
+    pub styles: BTreeSet<String>,
+}
+
+impl InlineHashes {
+    /// Hash inline scripts and styles in `html`. Scripts with `src` are not inline.
+    pub fn add_html(&mut self, html: &str) {
+        static SCRIPT: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script\s*>").unwrap());
+        static STYLE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(?is)<style\b[^>]*>(.*?)</style\s*>").unwrap());
+        static SRC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\ssrc\s*=").unwrap());
+
+        for caps in SCRIPT.captures_iter(html) {
+            if !SRC.is_match(&caps[1]) && !caps[2].is_empty() {
+                self.scripts.insert(hash_source(&caps[2]));
+            }
+        }
+        for caps in STYLE.captures_iter(html) {
+            if !caps[1].is_empty() {
+                self.styles.insert(hash_source(&caps[1]));
+            }
+        }
+    }
+}
+
+fn hash_source(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    format!(
+        "'sha256-{}'",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    )
+}
+
+/// Build a Content-Security-Policy from `directives`, adding `hashes` to `script-src` and
+/// `style-src`. A missing `script-src` or `style-src` starts from `default-src`, since it no
+/// longer falls back to `default-src` once it's given.
+pub fn policy(directives: &BTreeMap<String, String>, hashes: &InlineHashes) -> String {
+    let mut directives = directives.clone();
+    let default_src = directives
+        .get("default-src")
+        .cloned()
+        .unwrap_or_else(|| "'self'".to_string());
+    directives
+        .entry("default-src".to_string())
+        .or_insert_with(|| default_src.clone());
+    for (name, hashes) in [
+        ("script-src", &hashes.scripts),
+        ("style-src", &hashes.styles),
+    ] {
+        if hashes.is_empty() {
+            continue;
+        }
+        let value = directives
+            .entry(name.to_string())
+            .or_insert_with(|| default_src.clone());
+        for hash in hashes {
+            value.push(' ');
+            value.push_str(hash);
+        }
+    }
+    directives
+        .iter()
+        .map(|(name, value)| format!("{name} {value}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A `_headers` file, understood by Netlify and Cloudflare Pages.
+pub fn headers_file(policy: &str) -> String {
+    format!("/*\n  Content-Security-Policy: {policy}\n")
+}
+
+/// An nginx snippet, to be included in a `server` or `location` block.
+pub fn nginx_snippet(policy: &str) -> String {
+    format!("add_header Content-Security-Policy \"{policy}\" always;\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_test() {
+        let mut hashes = InlineHashes::default();
+        hashes.add_html(
+            r#"<script src="a.js"></script><script>alert(1)</script><style>p{}</style><script>alert(1)</script>"#,
+        );
+        assert_eq!(hashes.scripts.len(), 1);
+        assert_eq!(hashes.styles.len(), 1);
+        // echo -n 'alert(1)' | openssl dgst -sha256 -binary | base64
+        assert!(hashes
+            .scripts
+            .contains("'sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI='"));
+
+        let directives = BTreeMap::from([("img-src".to_string(), "'self' data:".to_string())]);
+        let policy = policy(&directives, &hashes);
+        assert!(policy.starts_with(
+            "default-src 'self'; img-src 'self' data:; script-src 'self' 'sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI='; style-src 'self' 'sha256-"
+        ));
+        assert_eq!(
+            nginx_snippet("default-src 'self'"),
+            "add_header Content-Security-Policy \"default-src 'self'\" always;\n"
+        );
+    }
+}
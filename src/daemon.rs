@@ -0,0 +1,231 @@
+//! `site daemon`: pull the content repository, rebuild, and swap the output periodically, so
+//! that a server can publish a site without external CI.
+
+use anyhow::{anyhow, Context as _, Error, Result};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::Api;
+use crate::github_pages::git;
+use crate::site::Article;
+
+/// The number of builds kept, including the current one, so that a reader in the middle of
+/// fetching pages still sees the previous build.
+const KEPT_BUILDS: usize = 2;
+
+/// A duration such as "30s", "10m", "1h" or "1d". A number without a unit is in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let number = number
+            .parse::<u64>()
+            .map_err(|_| anyhow!("invalid interval: {s}"))?;
+        let seconds = match unit {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => {
+                return Err(anyhow!(
+                    "invalid interval: {s}, e.g. \"30s\", \"10m\" or \"1h\""
+                ))
+            }
+        };
+        anyhow::ensure!(number > 0, "interval must be positive: {s}");
+        Ok(Interval(Duration::from_secs(number * seconds)))
+    }
+}
+
+#[derive(Debug)]
+pub struct DaemonOptions {
+    pub root_dir: PathBuf,
+    /// A symlink to the latest build. Builds are written next to it, into `<out-dir>.builds`.
+    pub out_dir: PathBuf,
+    pub interval: Interval,
+    /// Run `git pull --ff-only` in `root_dir` before each build.
+    pub pull: bool,
+    /// Serve a read-only JSON API of the published articles at this address.
+    pub api_addr: Option<SocketAddr>,
+}
+
+/// Pull, build with `build`, which writes a site into the given directory and returns the
+/// published articles, and publish it, every `interval`, forever. A failure is logged, keeping
+/// the current output.
+pub fn run(options: &DaemonOptions, build: impl Fn(&Path) -> Result<Vec<Article>>) -> Result<()> {
+    anyhow::ensure!(
+        !options.out_dir.exists() || options.out_dir.is_symlink(),
+        "{} exists, but it's not a symlink made by site daemon. Remove it, or use another --out-dir",
+        options.out_dir.display()
+    );
+    let api = Arc::new(Api::default());
+    if let Some(api_addr) = options.api_addr {
+        let listener = std::net::TcpListener::bind(api_addr)
+            .with_context(|| format!("can not listen on {api_addr}"))?;
+        log::info!("{:32} => http://{}/api/articles", "API", api_addr);
+        api.clone().serve(listener);
+    }
+    loop {
+        match rebuild(options, &build) {
+            Ok((build_dir, articles)) => {
+                log::info!("{:32} => {}", "Published", build_dir.display());
+                api.update(&articles);
+            }
+            Err(e) => log::error!("rebuild failed, keeping the current output: {e:#}"),
+        }
+        std::thread::sleep(options.interval.0);
+    }
+}
+
+/// Pull, build into a new directory, and publish it. Returns the new directory and the
+/// published articles.
+pub fn rebuild<T>(
+    options: &DaemonOptions,
+    build: impl Fn(&Path) -> Result<T>,
+) -> Result<(PathBuf, T)> {
+    if options.pull {
+        git(&options.root_dir, &["pull", "--ff-only", "--quiet"], &[])?;
+    }
+    let builds_dir = builds_dir(&options.out_dir)?;
+    std::fs::create_dir_all(&builds_dir)?;
+    let build_dir = new_build_dir(&builds_dir)?;
+    let built = match build(&build_dir) {
+        Ok(built) => built,
+        Err(e) => {
+            if build_dir.exists() {
+                std::fs::remove_dir_all(&build_dir)?;
+            }
+            return Err(e);
+        }
+    };
+    publish(&options.out_dir, &build_dir)?;
+    remove_old_builds(&builds_dir)?;
+    Ok((build_dir, built))
+}
+
+fn builds_dir(out_dir: &Path) -> Result<PathBuf> {
+    let name = out_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid out dir: {}", out_dir.display()))?;
+    Ok(out_dir.with_file_name(format!("{}.builds", name.to_string_lossy())))
+}
+
+/// A new directory in `builds_dir`, named by the time so that names sort by time. The time
+/// is in UTC, which doesn't go back, e.g. at the end of daylight saving time.
+fn new_build_dir(builds_dir: &Path) -> Result<PathBuf> {
+    let name = chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f").to_string();
+    let mut build_dir = builds_dir.join(&name);
+    let mut n = 1;
+    // Builds within a millisecond, e.g. failing ones, must not share a directory.
+    while build_dir.exists() {
+        n += 1;
+        build_dir = builds_dir.join(format!("{name}-{n}"));
+    }
+    std::fs::create_dir(&build_dir)?;
+    Ok(build_dir)
+}
+
+/// Point the symlink `out_dir` to `build_dir`, atomically replacing the old one by renaming.
+fn publish(out_dir: &Path, build_dir: &Path) -> Result<()> {
+    let tmp_link = out_dir.with_file_name(format!(
+        ".{}.tmp",
+        out_dir.file_name().unwrap().to_string_lossy()
+    ));
+    if tmp_link.is_symlink() {
+        std::fs::remove_file(&tmp_link)?;
+    }
+    symlink(&build_dir.canonicalize()?, &tmp_link)
+        .with_context(|| format!("can not create a symlink: {}", tmp_link.display()))?;
+    std::fs::rename(&tmp_link, out_dir)
+        .with_context(|| format!("can not replace: {}", out_dir.display()))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+/// Remove builds except for the newest ones. Build names sort by time.
+fn remove_old_builds(builds_dir: &Path) -> Result<()> {
+    let mut builds = std::fs::read_dir(builds_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    builds.sort();
+    let old = builds.len().saturating_sub(KEPT_BUILDS);
+    for build in &builds[..old] {
+        std::fs::remove_dir_all(build)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_test() {
+        assert_eq!(
+            "10m".parse::<Interval>().unwrap(),
+            Interval(Duration::from_secs(600))
+        );
+        assert_eq!(
+            "90".parse::<Interval>().unwrap(),
+            Interval(Duration::from_secs(90))
+        );
+        assert_eq!(
+            "1h".parse::<Interval>().unwrap(),
+            Interval(Duration::from_secs(3600))
+        );
+        assert!("0s".parse::<Interval>().is_err());
+        assert!("10 minutes".parse::<Interval>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rebuild_test() {
+        let dir = crate::testing::TempDir::new("daemon");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = DaemonOptions {
+            root_dir: dir.to_path_buf(),
+            out_dir: dir.join("out"),
+            interval: Interval(Duration::from_secs(1)),
+            pull: false,
+            api_addr: None,
+        };
+        let build = |content: &'static str| {
+            move |out_dir: &Path| {
+                std::fs::create_dir_all(out_dir)?;
+                std::fs::write(out_dir.join("index.html"), content)?;
+                Ok(())
+            }
+        };
+        let read = || std::fs::read_to_string(dir.join("out/index.html")).unwrap();
+        rebuild(&options, build("1")).unwrap();
+        assert_eq!(read(), "1");
+        rebuild(&options, build("2")).unwrap();
+        rebuild(&options, build("3")).unwrap();
+        assert_eq!(read(), "3");
+        assert_eq!(
+            std::fs::read_dir(dir.join("out.builds")).unwrap().count(),
+            KEPT_BUILDS
+        );
+
+        // A failed build keeps the output.
+        assert!(rebuild(&options, |_: &Path| Err::<(), _>(anyhow!("broken"))).is_err());
+        assert_eq!(read(), "3");
+    }
+}
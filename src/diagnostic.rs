@@ -0,0 +1,93 @@
+use anyhow::{anyhow, Error, Result};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in the content, such as an empty title.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The path of the file, such as `src/foo.md`.
+    pub file: Option<String>,
+    /// 1-based.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(file: Option<String>, line: Option<usize>, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            file,
+            line,
+            message,
+        }
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.file.as_ref(), self.line) {
+            (Some(file), Some(line)) => write!(f, "{file}:{line}: {}", self.message),
+            (Some(file), None) => write!(f, "{file}: {}", self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// How diagnostics are printed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum MessageFormat {
+    /// As log messages on stderr.
+    #[default]
+    Human,
+    /// As one JSON object per line on stdout, for editors and CI.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(anyhow!(
+                "unknown message format: {s} (expected `human` or `json`)"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_test() {
+        let diagnostic = Diagnostic::warning(
+            Some("src/a.md".to_string()),
+            Some(3),
+            "`Javascript` should be `JavaScript`".to_string(),
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "src/a.md:3: `Javascript` should be `JavaScript`"
+        );
+        assert_eq!(
+            diagnostic.json(),
+            r#"{"severity":"warning","file":"src/a.md","line":3,"message":"`Javascript` should be `JavaScript`"}"#
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::Result;
+use std::io::{Seek, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::html::escape;
+
+pub struct Chapter {
+    pub title: String,
+    /// XHTML body.
+    pub content: String,
+}
+
+pub struct Image {
+    /// A path relative to the book's root. e.g. "images/1.png"
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    fn media_type(&self) -> &'static str {
+        match self.path.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("gif") => "image/gif",
+            Some("svg") => "image/svg+xml",
+            Some("webp") => "image/webp",
+            _ => "application/octet-stream",
+        }
+    }
+}
+
+pub struct Book {
+    pub identifier: String,
+    pub title: String,
+    pub language: String,
+    pub author: Option<String>,
+    pub chapters: Vec<Chapter>,
+    pub images: Vec<Image>,
+}
+
+impl Book {
+    pub fn write(&self, w: impl Write + Seek) -> Result<()> {
+        let mut zip = ZipWriter::new(w);
+        // "mimetype" must be the first entry, and must not be compressed.
+        zip.start_file(
+            "mimetype",
+            SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(b"application/epub+zip")?;
+
+        let options = SimpleFileOptions::default();
+        zip.start_file("META-INF/container.xml", options)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+        zip.start_file("OEBPS/content.opf", options)?;
+        zip.write_all(self.package_document().as_bytes())?;
+        zip.start_file("OEBPS/nav.xhtml", options)?;
+        zip.write_all(self.navigation_document().as_bytes())?;
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            zip.start_file(format!("OEBPS/{}", chapter_file_name(i)), options)?;
+            zip.write_all(self.xhtml(&chapter.title, &chapter.content).as_bytes())?;
+        }
+        for image in &self.images {
+            zip.start_file(format!("OEBPS/{}", image.path), options)?;
+            zip.write_all(&image.data)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    fn package_document(&self) -> String {
+        let mut manifest = String::new();
+        let mut spine = String::new();
+        for i in 0..self.chapters.len() {
+            manifest.push_str(&format!(
+                "    <item id=\"chapter-{i}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                chapter_file_name(i)
+            ));
+            spine.push_str(&format!("    <itemref idref=\"chapter-{i}\"/>\n"));
+        }
+        for (i, image) in self.images.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"image-{i}\" href=\"{}\" media-type=\"{}\"/>\n",
+                escape(&image.path),
+                image.media_type()
+            ));
+        }
+        let creator = self
+            .author
+            .as_ref()
+            .map(|author| format!("    <dc:creator>{}</dc:creator>\n", escape(author)))
+            .unwrap_or_default();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+{creator}    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+            identifier = escape(&self.identifier),
+            title = escape(&self.title),
+            language = escape(&self.language),
+            modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        )
+    }
+
+    fn navigation_document(&self) -> String {
+        let mut toc = String::new();
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            toc.push_str(&format!(
+                "    <li><a href=\"{}\">{}</a></li>\n",
+                chapter_file_name(i),
+                escape(&chapter.title)
+            ));
+        }
+        self.xhtml(
+            &self.title,
+            &format!("<nav epub:type=\"toc\" id=\"toc\">\n  <ol>\n{toc}  </ol>\n</nav>\n"),
+        )
+    }
+
+    fn xhtml(&self, title: &str, body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}">
+<head>
+<title>{title}</title>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+            language = escape(&self.language),
+            title = escape(title),
+        )
+    }
+}
+
+fn chapter_file_name(i: usize) -> String {
+    format!("chapter-{i}.xhtml")
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_test() {
+        let book = Book {
+            identifier: "urn:site:test".to_string(),
+            title: "A & B".to_string(),
+            language: "en".to_string(),
+            author: None,
+            chapters: vec![Chapter {
+                title: "One".to_string(),
+                content: "<p>Hello</p>\n".to_string(),
+            }],
+            images: vec![Image {
+                path: "images/0.png".to_string(),
+                data: vec![0, 1, 2],
+            }],
+        };
+        let mut buf = std::io::Cursor::new(Vec::new());
+        book.write(&mut buf).unwrap();
+        let buf = buf.into_inner();
+        // The first local file header must be an uncompressed "mimetype".
+        assert_eq!(&buf[30..38], b"mimetype");
+        assert_eq!(&buf[38..58], b"application/epub+zip");
+
+        let opf = book.package_document();
+        assert!(opf.contains("<dc:title>A &amp; B</dc:title>"));
+        assert!(opf.contains(r#"href="images/0.png" media-type="image/png""#));
+        assert!(opf.contains(r#"<itemref idref="chapter-0"/>"#));
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context as _, Result};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+use image::ExtendedColorType;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::static_files::ManifestIcon;
+
+/// PNG icons generated from the source image, and their sizes.
+const PNGS: &[(&str, u32)] = &[
+    ("favicon-16x16.png", 16),
+    ("favicon-32x32.png", 32),
+    ("apple-touch-icon.png", 180),
+    ("icon-192.png", 192),
+    ("icon-512.png", 512),
+];
+
+/// Sizes in `favicon.ico`.
+const ICO_SIZES: &[u32] = &[16, 32, 48];
+
+/// `[favicon]`: an image, from which favicons are generated.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FaviconConfig {
+    /// A PNG or JPEG image, relative to `src`, preferably a square of 512x512 or larger.
+    pub source: String,
+    /// Inject `<link>` elements into `<head>`. Otherwise, templates can use `favicon`.
+    #[serde(default)]
+    pub inject: bool,
+}
+
+impl FaviconConfig {
+    /// `<link>` elements for the generated favicons.
+    pub fn links(&self) -> String {
+        r#"<link rel="icon" href="/favicon.ico" sizes="48x48">
+<link rel="icon" type="image/png" sizes="32x32" href="/favicon-32x32.png">
+<link rel="icon" type="image/png" sizes="16x16" href="/favicon-16x16.png">
+<link rel="apple-touch-icon" sizes="180x180" href="/apple-touch-icon.png">
+"#
+        .to_string()
+    }
+
+    /// Icons for the web app manifest.
+    pub fn manifest_icons(&self) -> Vec<ManifestIcon> {
+        [192, 512]
+            .into_iter()
+            .map(|size| ManifestIcon {
+                src: format!("/icon-{size}.png"),
+                sizes: format!("{size}x{size}"),
+                mime_type: Some("image/png".to_string()),
+            })
+            .collect()
+    }
+
+    /// Generate `favicon.ico` and PNG icons into `out_dir`.
+    pub fn generate(&self, src_dir: &Path, out_dir: &Path) -> Result<()> {
+        let source = src_dir.join(&self.source);
+        let image = image::open(&source)
+            .with_context(|| format!("can not read favicon source: {}", source.display()))?;
+        if image.width() < 512 || image.height() < 512 {
+            log::warn!(
+                "favicon source is smaller than 512x512, so icons are upscaled: {}",
+                source.display()
+            );
+        }
+        // Non-square images are cropped at the center.
+        let icon = |size| {
+            image
+                .resize_to_fill(size, size, FilterType::Lanczos3)
+                .to_rgba8()
+        };
+
+        for (name, size) in PNGS {
+            let out_file = out_dir.join(name);
+            log::debug!("{:32} => {}", self.source, out_file.display());
+            icon(*size).save(&out_file)?;
+        }
+        let icons = ICO_SIZES.iter().map(|size| icon(*size)).collect::<Vec<_>>();
+        let frames = icons
+            .iter()
+            .map(|icon| {
+                IcoFrame::as_png(icon, icon.width(), icon.height(), ExtendedColorType::Rgba8)
+            })
+            .collect::<image::ImageResult<Vec<_>>>()?;
+        let out_file = out_dir.join("favicon.ico");
+        log::info!("{:32} => {}", self.source, out_file.display());
+        IcoEncoder::new(std::io::BufWriter::new(std::fs::File::create(&out_file)?))
+            .encode_images(&frames)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_test() {
+        let dir = crate::testing::TempDir::new("favicon");
+        let (src_dir, out_dir) = (dir.join("src"), dir.join("out"));
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::create_dir_all(&out_dir).unwrap();
+        image::RgbaImage::from_pixel(600, 512, image::Rgba([255, 0, 0, 255]))
+            .save(src_dir.join("logo.png"))
+            .unwrap();
+
+        let config = FaviconConfig {
+            source: "logo.png".to_string(),
+            inject: false,
+        };
+        config.generate(&src_dir, &out_dir).unwrap();
+        assert_eq!(
+            imagesize::size(out_dir.join("apple-touch-icon.png")).unwrap(),
+            imagesize::ImageSize {
+                width: 180,
+                height: 180
+            }
+        );
+        let ico = std::fs::read(out_dir.join("favicon.ico")).unwrap();
+        // The reserved field, the type (1 = ICO), and the number of images.
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 3, 0]);
+    }
+}
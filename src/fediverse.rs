@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::html::escape;
+
+/// `[fediverse]`: the author's account, such as a Mastodon account.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FediverseConfig {
+    /// e.g. "@hayato@mastodon.social"
+    pub creator: String,
+    /// Write `.well-known/webfinger`, so that the account can be found by the site's domain.
+    #[serde(default)]
+    pub webfinger: bool,
+}
+
+impl FediverseConfig {
+    /// The user name and the host of `creator`.
+    fn account(&self) -> Result<(&str, &str)> {
+        self.creator
+            .strip_prefix('@')
+            .unwrap_or(&self.creator)
+            .split_once('@')
+            .filter(|(user, host)| !user.is_empty() && !host.is_empty())
+            .ok_or_else(|| {
+                anyhow!(
+                    "invalid fediverse creator: {} (expected `@user@host`)",
+                    self.creator
+                )
+            })
+    }
+
+    /// `<meta name="fediverse:creator">`, which attributes link previews to the account.
+    pub fn meta(&self) -> Result<String> {
+        let (user, host) = self.account()?;
+        Ok(format!(
+            "<meta name=\"fediverse:creator\" content=\"@{}@{}\">\n",
+            escape(user),
+            escape(host)
+        ))
+    }
+
+    /// A static WebFinger response which points to the account, assuming Mastodon's URLs.
+    pub fn webfinger(&self) -> Result<String> {
+        let (user, host) = self.account()?;
+        let profile = format!("https://{host}/@{user}");
+        let actor = format!("https://{host}/users/{user}");
+        let json = serde_json::json!({
+            "subject": format!("acct:{user}@{host}"),
+            "aliases": [&profile, &actor],
+            "links": [
+                {
+                    "rel": "http://webfinger.net/rel/profile-page",
+                    "type": "text/html",
+                    "href": profile,
+                },
+                {
+                    "rel": "self",
+                    "type": "application/activity+json",
+                    "href": actor,
+                },
+            ],
+        });
+        Ok(serde_json::to_string_pretty(&json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fediverse_test() {
+        let config = FediverseConfig {
+            creator: "@me@mastodon.social".to_string(),
+            webfinger: true,
+        };
+        assert_eq!(
+            config.meta().unwrap(),
+            "<meta name=\"fediverse:creator\" content=\"@me@mastodon.social\">\n"
+        );
+        let webfinger =
+            serde_json::from_str::<serde_json::Value>(&config.webfinger().unwrap()).unwrap();
+        assert_eq!(webfinger["subject"], "acct:me@mastodon.social");
+        assert_eq!(
+            webfinger["links"][1]["href"],
+            "https://mastodon.social/users/me"
+        );
+
+        let config = FediverseConfig {
+            creator: "me".to_string(),
+            webfinger: false,
+        };
+        assert!(config.meta().is_err());
+    }
+}
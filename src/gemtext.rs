@@ -0,0 +1,209 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd};
+
+/// Convert markdown into gemtext (text/gemini).
+///
+/// Gemtext doesn't have inline links, so links in a block are listed as link lines after the
+/// block.
+pub fn from_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    // The current line, which is flushed at the end of each block.
+    let mut line = String::new();
+    let mut links = Vec::<(String, String)>::new();
+    // The link being read: (url, text).
+    let mut link = None::<(String, String)>;
+    let mut in_code_block = false;
+    // The alt text of an image is only used for its link line.
+    let mut in_image = false;
+    let mut quote_depth = 0;
+    let mut list_depth = 0;
+
+    let opts = pulldown_cmark::Options::ENABLE_FOOTNOTES
+        | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+        | pulldown_cmark::Options::ENABLE_TABLES
+        | pulldown_cmark::Options::ENABLE_TASKLISTS;
+
+    let flush = |out: &mut String, line: &mut String, links: &mut Vec<(String, String)>| {
+        let text = line.trim();
+        if !text.is_empty() {
+            out.push_str(text);
+            out.push('\n');
+        }
+        line.clear();
+        for (url, text) in links.drain(..) {
+            if text.is_empty() {
+                out.push_str(&format!("=> {url}\n"));
+            } else {
+                out.push_str(&format!("=> {url} {text}\n"));
+            }
+        }
+    };
+
+    for event in Parser::new_ext(s, opts) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                flush(&mut out, &mut line, &mut links);
+                let level = match level {
+                    HeadingLevel::H1 => "#",
+                    HeadingLevel::H2 => "##",
+                    _ => "###",
+                };
+                line.push_str(level);
+                line.push(' ');
+            }
+            Event::Start(Tag::Paragraph) if list_depth == 0 && quote_depth > 0 => {
+                line.push_str("> ");
+            }
+            Event::Start(Tag::Item) => {
+                flush(&mut out, &mut line, &mut links);
+                line.push_str("* ");
+            }
+            Event::Start(Tag::List(_)) => {
+                flush(&mut out, &mut line, &mut links);
+                list_depth += 1;
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                flush(&mut out, &mut line, &mut links);
+                quote_depth += 1;
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                flush(&mut out, &mut line, &mut links);
+                in_code_block = true;
+                match kind {
+                    CodeBlockKind::Fenced(lang) => out.push_str(&format!("```{lang}\n")),
+                    CodeBlockKind::Indented => out.push_str("```\n"),
+                }
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                link = Some((dest_url.to_string(), String::new()));
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                in_image = true;
+                link = Some((dest_url.to_string(), String::new()));
+            }
+            Event::Start(Tag::TableRow | Tag::TableHead) => {
+                flush(&mut out, &mut line, &mut links);
+            }
+            Event::Start(Tag::TableCell) if !line.is_empty() => line.push_str(" | "),
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                flush(&mut out, &mut line, &mut links);
+                line.push_str(&format!("[{label}]: "));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str("```\n\n");
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                in_image = false;
+                if let Some(link) = link.take() {
+                    links.push(link);
+                }
+            }
+            Event::End(TagEnd::List(_)) => {
+                flush(&mut out, &mut line, &mut links);
+                list_depth -= 1;
+                if list_depth == 0 {
+                    out.push('\n');
+                }
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                quote_depth -= 1;
+            }
+            Event::End(TagEnd::Table) => {
+                flush(&mut out, &mut line, &mut links);
+                out.push('\n');
+            }
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::FootnoteDefinition
+                | TagEnd::HtmlBlock,
+            ) if list_depth == 0 => {
+                flush(&mut out, &mut line, &mut links);
+                out.push('\n');
+            }
+            Event::Text(text) if in_code_block => out.push_str(&text),
+            Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                if !in_image {
+                    line.push_str(&text);
+                }
+                if let Some((_, link_text)) = link.as_mut() {
+                    link_text.push_str(&text);
+                }
+            }
+            Event::DisplayMath(text) => line.push_str(&text),
+            Event::SoftBreak => {
+                line.push(' ');
+                if let Some((_, link_text)) = link.as_mut() {
+                    link_text.push(' ');
+                }
+            }
+            Event::HardBreak => {
+                flush(&mut out, &mut line, &mut links);
+                if quote_depth > 0 {
+                    line.push_str("> ");
+                }
+            }
+            Event::FootnoteReference(label) => line.push_str(&format!("[{label}]")),
+            Event::TaskListMarker(checked) => line.push_str(if checked { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+    flush(&mut out, &mut line, &mut links);
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_markdown_test() {
+        let s = r"# Title
+
+Hello *world*, see [the docs](https://example.com/docs)
+and [this](/foo/).
+
+#### Deep
+
+- a
+- [b](b/)
+
+![A picture](a.png)
+
+> quoted
+
+```rust
+fn main() {}
+```
+";
+        assert_eq!(
+            from_markdown(s),
+            r"# Title
+
+Hello world, see the docs and this.
+=> https://example.com/docs the docs
+=> /foo/ this
+
+### Deep
+
+* a
+* b
+=> b/ b
+
+=> a.png A picture
+
+> quoted
+
+```rust
+fn main() {}
+```
+"
+        );
+    }
+}
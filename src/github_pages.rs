@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Context as _, Result};
+use serde::Deserialize;
+use std::path::Path;
+use std::process::Command;
+
+/// `[github_pages]`: hosting on GitHub Pages.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GithubPagesConfig {
+    /// A custom domain, such as "example.com", which is written as `CNAME`.
+    pub cname: Option<String>,
+    /// The branch to publish to.
+    #[serde(default = "GithubPagesConfig::default_branch")]
+    pub branch: String,
+    #[serde(default = "GithubPagesConfig::default_remote")]
+    pub remote: String,
+}
+
+impl GithubPagesConfig {
+    fn default_branch() -> String {
+        "gh-pages".to_string()
+    }
+
+    fn default_remote() -> String {
+        "origin".to_string()
+    }
+
+    /// Files which GitHub Pages needs in `out_dir`. `.nojekyll` disables Jekyll, which would
+    /// ignore files starting with `_`.
+    pub fn files(&self) -> Vec<(&'static str, String)> {
+        let mut files = vec![(".nojekyll", String::new())];
+        if let Some(cname) = self.cname.as_ref() {
+            files.push(("CNAME", format!("{cname}\n")));
+        }
+        files
+    }
+
+    /// A GitHub Actions workflow which builds the site and publishes `out` with GitHub Pages'
+    /// artifact deployment, as an alternative to the `gh-pages` branch.
+    pub fn workflow() -> &'static str {
+        include_str!("github_pages_workflow.yml")
+    }
+
+    /// Commit `out_dir` as the whole tree of `branch` in the git repository at `root_dir`, on top
+    /// of its previous commit, without touching the working tree, then push it.
+    pub fn deploy(&self, root_dir: &Path, out_dir: &Path, message: &str, push: bool) -> Result<()> {
+        let out_dir = out_dir
+            .canonicalize()
+            .with_context(|| format!("can not find out dir: {}", out_dir.display()))?;
+        for (name, _) in self.files() {
+            anyhow::ensure!(
+                out_dir.join(name).exists(),
+                "{name} is missing in {}. Run `site build` first",
+                out_dir.display()
+            );
+        }
+        let git_dir = git(root_dir, &["rev-parse", "--absolute-git-dir"], &[])?;
+        let index_file = Path::new(&git_dir).join("site-deploy-index");
+        let env = [
+            ("GIT_INDEX_FILE", index_file.as_path()),
+            ("GIT_WORK_TREE", out_dir.as_path()),
+        ];
+        let _ = std::fs::remove_file(&index_file);
+        git(root_dir, &["add", "--all", "--force"], &env)?;
+        let tree = git(root_dir, &["write-tree"], &env)?;
+        std::fs::remove_file(&index_file)?;
+
+        let branch_ref = format!("refs/heads/{}", self.branch);
+        let parent = git(
+            root_dir,
+            &["rev-parse", "--verify", "--quiet", &branch_ref],
+            &[],
+        )
+        .ok();
+        let parent_tree = git(
+            root_dir,
+            &["rev-parse", &format!("{branch_ref}^{{tree}}")],
+            &[],
+        );
+        if parent_tree.is_ok_and(|parent_tree| parent_tree == tree) {
+            log::info!("{} is up to date", self.branch);
+        } else {
+            let mut args = vec!["commit-tree", &tree, "-m", message];
+            if let Some(parent) = parent.as_ref() {
+                args.extend(["-p", parent]);
+            }
+            let commit = git(root_dir, &args, &[])?;
+            git(root_dir, &["update-ref", &branch_ref, &commit], &[])?;
+            log::info!("Committed {} to {}", out_dir.display(), self.branch);
+        }
+        if push {
+            git(root_dir, &["push", &self.remote, &branch_ref], &[])?;
+            log::info!("Pushed {} to {}", self.branch, self.remote);
+        }
+        Ok(())
+    }
+}
+
+/// Run git in `dir`, and return its trimmed stdout.
+pub(crate) fn git(dir: &Path, args: &[&str], env: &[(&str, &Path)]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .envs(env.iter().copied())
+        .output()
+        .context("can not run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deploy_test() {
+        let dir = crate::testing::TempDir::new("deploy");
+        let out_dir = dir.join("out");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let config = toml::from_str::<GithubPagesConfig>(r#"cname = "example.com""#).unwrap();
+        for (name, content) in config.files() {
+            std::fs::write(out_dir.join(name), content).unwrap();
+        }
+        std::fs::write(out_dir.join("index.html"), "hello").unwrap();
+        git(&dir, &["init", "--quiet"], &[]).unwrap();
+        // `commit-tree` needs an identity.
+        git(&dir, &["config", "user.name", "site"], &[]).unwrap();
+        git(&dir, &["config", "user.email", "site@example.com"], &[]).unwrap();
+        let deploy = |message| config.deploy(&dir, &out_dir, message, false).unwrap();
+
+        deploy("first");
+        std::fs::write(out_dir.join("index.html"), "hello, world").unwrap();
+        deploy("second");
+        deploy("nothing");
+        assert_eq!(
+            git(&dir, &["log", "--format=%s", "gh-pages"], &[]).unwrap(),
+            "second\nfirst"
+        );
+        assert_eq!(
+            git(&dir, &["ls-tree", "--name-only", "gh-pages"], &[]).unwrap(),
+            ".nojekyll\nCNAME\nindex.html"
+        );
+        assert_eq!(
+            git(&dir, &["show", "gh-pages:CNAME"], &[]).unwrap(),
+            "example.com"
+        );
+    }
+}
@@ -1,7 +1,23 @@
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::LazyLock;
 
+/// Escape the given text so that it can be embedded in HTML or XML, including attribute values.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
 // Convert the given string to a valid HTML element ID
 fn normalize_id(content: &str) -> String {
     let ret = content
@@ -40,9 +56,124 @@ fn id_from_content(content: &str) -> String {
     normalize_id(content.as_ref())
 }
 
-pub fn build_header_links(html: &str) -> String {
+/// Prefix root-relative URLs in `href`, `src`, `action` and `poster` attributes, such as
+/// `/about/`, with `prefix`, such as `/blog`, for a site hosted in a subdirectory. URLs which
+/// already have the prefix, such as ones made with `{{ path_prefix }}`, are kept.
+pub fn prefix_urls(html: &str, prefix: &str) -> String {
+    static URL_ATTR: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"\b(href|src|action|poster)=(["'])/([^/"'][^"']*)?(["'])"#).unwrap()
+    });
+    URL_ATTR
+        .replace_all(html, |caps: &regex::Captures<'_>| {
+            let path = caps.get(3).map_or("", |m| m.as_str());
+            let prefixed = path
+                .strip_prefix(prefix.trim_start_matches('/'))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']));
+            if prefixed {
+                return caps[0].to_string();
+            }
+            format!("{}={}{prefix}/{path}{}", &caps[1], &caps[2], &caps[4])
+        })
+        .into_owned()
+}
+
+/// Replace root-relative URLs in `href` and `src` attributes with their fingerprinted ones in
+/// `fingerprints`, such as "/style.css" => "/style.0123abcd.css".
+pub fn fingerprint_urls(html: &str, fingerprints: &BTreeMap<String, String>) -> String {
+    static URL_ATTR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"\b(href|src)=(["'])(/[^"'?#]*)"#).unwrap());
+    URL_ATTR
+        .replace_all(html, |caps: &regex::Captures<'_>| {
+            let url = fingerprints.get(&caps[3]).map_or(&caps[3], String::as_str);
+            format!("{}={}{url}", &caps[1], &caps[2])
+        })
+        .into_owned()
+}
+
+/// Minify HTML conservatively: remove comments, except conditional ones, and collapse runs of
+/// whitespace into a space, or a newline if they have one, outside of `<pre>`, `<textarea>`,
+/// `<script>` and `<style>`.
+pub fn minify(html: &str) -> String {
+    static VERBATIM: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?is)<!--.*?-->|<pre\b.*?</pre>|<textarea\b.*?</textarea>|<script\b.*?</script>|<style\b.*?</style>").unwrap()
+    });
+    static SPACES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s{2,}|\n").unwrap());
+    fn collapse(text: &str) -> std::borrow::Cow<'_, str> {
+        SPACES.replace_all(
+            text,
+            |caps: &regex::Captures<'_>| {
+                if caps[0].contains('\n') {
+                    "\n"
+                } else {
+                    " "
+                }
+            },
+        )
+    }
+    let mut out = String::with_capacity(html.len());
+    // Text between verbatim parts, which are not removed comments.
+    let mut text = String::new();
+    let mut last = 0;
+    for m in VERBATIM.find_iter(html) {
+        text.push_str(&html[last..m.start()]);
+        last = m.end();
+        if m.as_str().starts_with("<!--") && !m.as_str().starts_with("<!--[if") {
+            continue;
+        }
+        out.push_str(&collapse(&text));
+        text.clear();
+        out.push_str(m.as_str());
+    }
+    text.push_str(&html[last..]);
+    out.push_str(&collapse(&text));
+    out
+}
+
+/// Assigns unique ids in a document. One allocator is carried through all passes over the
+/// document, so that an id is assigned exactly once, and passes agree on suffixes.
+#[derive(Debug, Default)]
+pub struct IdAllocator {
+    /// The number of ids allocated for each raw id.
+    counts: HashMap<String, usize>,
+    used: HashSet<String>,
+}
+
+impl IdAllocator {
+    /// An allocator for `html`, where the ids which it already has, such as ones given by
+    /// heading attributes, are taken.
+    pub fn from_html(html: &str) -> IdAllocator {
+        static ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\sid="([^"]*)""#).unwrap());
+
+        IdAllocator {
+            counts: HashMap::new(),
+            used: ID
+                .captures_iter(html)
+                .map(|caps| caps[1].to_string())
+                .collect(),
+        }
+    }
+
+    /// A unique id for `raw_id`: `raw_id` itself the first time, then `raw_id-1`, `raw_id-2`,
+    /// and so on.
+    pub fn allocate(&mut self, raw_id: &str) -> String {
+        let count = self.counts.entry(raw_id.to_string()).or_insert(0);
+        let id = loop {
+            let id = match *count {
+                0 => raw_id.to_string(),
+                n => format!("{raw_id}-{n}"),
+            };
+            *count += 1;
+            if !self.used.contains(&id) {
+                break id;
+            }
+        };
+        self.used.insert(id.clone());
+        id
+    }
+}
+
+pub fn build_header_links(html: &str, ids: &mut IdAllocator) -> String {
     let regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
-    let mut id_counter = HashMap::new();
 
     regex
         .replace_all(html, |caps: &regex::Captures<'_>| {
@@ -50,16 +181,12 @@ pub fn build_header_links(html: &str) -> String {
                 .parse()
                 .expect("Regex should ensure we only ever get numbers here");
 
-            wrap_header_with_link(level, &caps[2], &mut id_counter)
+            wrap_header_with_link(level, &caps[2], ids)
         })
         .into_owned()
 }
 
-fn wrap_header_with_link(
-    level: usize,
-    content: &str,
-    id_counter: &mut HashMap<String, usize>,
-) -> String {
+fn wrap_header_with_link(level: usize, content: &str, ids: &mut IdAllocator) -> String {
     static ANCHOR_REGEX: LazyLock<Regex> =
         LazyLock::new(|| Regex::new(r#"<a name="(?P<id>.*?)"></a>"#).unwrap());
 
@@ -72,16 +199,64 @@ fn wrap_header_with_link(
         )
     };
 
-    let id_count = id_counter.entry(raw_id.to_owned()).or_insert(0);
+    let id = ids.allocate(&raw_id);
 
-    let id = match *id_count {
-        0 => raw_id,
-        other => format!("{raw_id}-{other}"),
-    };
+    format!(r##"<h{level} id="{id}"><a class="self-link" href="#{id}">{text}</a></h{level}>"##,)
+}
 
-    *id_count += 1;
+/// Prefix the `id`s in `html`, such as ones of headings and footnotes, with `{prefix}-`, and
+/// `href="#..."` links to them, so that the content of several articles can be put on one page
+/// without collisions.
+pub fn prefix_ids(html: &str, prefix: &str) -> String {
+    static ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(\s)id="([^"]*)""#).unwrap());
+    static FRAGMENT: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r##"(\s)href="#([^"]*)""##).unwrap());
 
-    format!(r##"<h{level} id="{id}"><a class="self-link" href="#{id}">{text}</a></h{level}>"##,)
+    let ids = ID
+        .captures_iter(html)
+        .map(|caps| caps[2].to_string())
+        .collect::<HashSet<_>>();
+    if ids.is_empty() {
+        return html.to_string();
+    }
+    let html = ID.replace_all(html, |caps: &regex::Captures<'_>| {
+        format!(r#"{}id="{prefix}-{}""#, &caps[1], &caps[2])
+    });
+    FRAGMENT
+        .replace_all(&html, |caps: &regex::Captures<'_>| {
+            if ids.contains(&caps[2]) {
+                format!(r##"{}href="#{prefix}-{}""##, &caps[1], &caps[2])
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .into_owned()
+}
+
+/// Move headings down by `by` levels, such as `<h2>` to `<h3>`, up to `<h6>`, e.g. to put the
+/// content of an article under another heading.
+pub fn shift_headings(html: &str, by: usize) -> String {
+    static HEADING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<(/?)h([1-6])\b").unwrap());
+
+    HEADING
+        .replace_all(html, |caps: &regex::Captures<'_>| {
+            let level = caps[2].parse::<usize>().unwrap() + by;
+            format!("<{}h{}", &caps[1], level.min(6))
+        })
+        .into_owned()
+}
+
+/// Remove the self-link anchors of headings made by [`build_header_links`], keeping their
+/// text, e.g. for feeds, where they are meaningless. HTML escaped in XML is also handled.
+pub fn strip_self_links(s: &str) -> String {
+    static SELF_LINK: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r##"<a class="self-link" href="#[^"]*">(.*?)</a>|&lt;a class=&quot;self-link&quot; href=&quot;#.*?&quot;&gt;(.*?)&lt;/a&gt;"##).unwrap()
+    });
+    SELF_LINK
+        .replace_all(s, |caps: &regex::Captures<'_>| {
+            caps.get(1).or(caps.get(2)).unwrap().as_str().to_string()
+        })
+        .into_owned()
 }
 
 #[cfg(test)]
@@ -96,4 +271,93 @@ mod tests {
         assert_eq!(id_from_content("あいう abc えお def"), "abc-def");
         assert_eq!(id_from_content("a<a href=xxx>hello</a>b"), "a-hello-b");
     }
+
+    #[test]
+    fn prefix_urls_test() {
+        assert_eq!(
+            prefix_urls(
+                r##"<a href="/">home</a> <a href="/about/">about</a> <img src='/img/a.png'>
+<a href="//cdn.example/x">cdn</a> <a href="https://example.com/">abs</a> <a href="#top">top</a>
+<a href="/blog/about/">prefixed</a> <a href="/blog">prefixed</a> <a href="/blogs/">blogs</a>"##,
+                "/blog"
+            ),
+            r##"<a href="/blog/">home</a> <a href="/blog/about/">about</a> <img src='/blog/img/a.png'>
+<a href="//cdn.example/x">cdn</a> <a href="https://example.com/">abs</a> <a href="#top">top</a>
+<a href="/blog/about/">prefixed</a> <a href="/blog">prefixed</a> <a href="/blog/blogs/">blogs</a>"##
+        );
+    }
+
+    #[test]
+    fn minify_test() {
+        assert_eq!(
+            minify(
+                "<html>\n  <head>\n    <!-- comment -->\n    <style>a  {}</style>\n  </head>\n  <body>\n    <p>a   <b>b</b>\n    c</p>\n    <pre>  x\n\n  y</pre>\n  <!--[if IE]>ie<![endif]-->\n  </body>\n</html>\n"
+            ),
+            "<html>\n<head>\n<style>a  {}</style>\n</head>\n<body>\n<p>a <b>b</b>\nc</p>\n<pre>  x\n\n  y</pre>\n<!--[if IE]>ie<![endif]-->\n</body>\n</html>\n"
+        );
+        let fingerprints =
+            BTreeMap::from([("/style.css".to_string(), "/style.0123abcd.css".to_string())]);
+        assert_eq!(
+            fingerprint_urls(
+                r#"<link href="/style.css"><link href='/style.css?v=1'><a href="/about/">"#,
+                &fingerprints
+            ),
+            r#"<link href="/style.0123abcd.css"><link href='/style.0123abcd.css?v=1'><a href="/about/">"#
+        );
+    }
+
+    #[test]
+    fn strip_self_links_test() {
+        let html = build_header_links(
+            "<h2>A <em>b</em></h2>\n<p><a href=\"#a\">c</a></p>",
+            &mut IdAllocator::default(),
+        );
+        assert_eq!(
+            strip_self_links(&html),
+            "<h2 id=\"a-b\">A <em>b</em></h2>\n<p><a href=\"#a\">c</a></p>"
+        );
+        assert_eq!(
+            strip_self_links(&escape(&html)),
+            escape("<h2 id=\"a-b\">A <em>b</em></h2>\n<p><a href=\"#a\">c</a></p>")
+        );
+    }
+
+    #[test]
+    fn escape_test() {
+        assert_eq!(escape("abc"), "abc");
+        assert_eq!(
+            escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn prefix_ids_test() {
+        assert_eq!(
+            prefix_ids(
+                r##"<h2 id="usage"><a class="self-link" href="#usage">Usage</a></h2>
+<p>See<sup class="footnote-reference"><a href="#1">1</a></sup> <a href="#top">top</a>.</p>
+<div class="footnote-definition" id="1" data-id="x"></div>"##,
+                "hello"
+            ),
+            r##"<h2 id="hello-usage"><a class="self-link" href="#hello-usage">Usage</a></h2>
+<p>See<sup class="footnote-reference"><a href="#hello-1">1</a></sup> <a href="#top">top</a>.</p>
+<div class="footnote-definition" id="hello-1" data-id="x"></div>"##
+        );
+    }
+
+    #[test]
+    fn id_allocator_test() {
+        let mut ids = IdAllocator::from_html(r#"<h2 id="a-1">A</h2>"#);
+        assert_eq!(ids.allocate("a"), "a");
+        assert_eq!(ids.allocate("a"), "a-2");
+        assert_eq!(ids.allocate("b"), "b");
+        // Later passes continue the same counts.
+        let html = build_header_links("<h2>A</h2>\n<h3>B</h3>", &mut ids);
+        assert_eq!(
+            html,
+            r##"<h2 id="a-3"><a class="self-link" href="#a-3">A</a></h2>
+<h3 id="b-1"><a class="self-link" href="#b-1">B</a></h3>"##
+        );
+    }
 }
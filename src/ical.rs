@@ -0,0 +1,170 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// The start or the end of an event: a local date and time, such as `2024-05-01T19:00:00`, or
+/// a date for an all-day event, such as `2024-05-01`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EventTime {
+    DateTime(NaiveDateTime),
+    Date(NaiveDate),
+}
+
+impl EventTime {
+    /// An all-day event starts at midnight.
+    pub fn datetime(&self) -> NaiveDateTime {
+        match self {
+            EventTime::DateTime(datetime) => *datetime,
+            EventTime::Date(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+        }
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.datetime().date()
+    }
+}
+
+pub struct Event<'a> {
+    /// A globally unique ID, such as the URL of the article.
+    pub uid: &'a str,
+    pub summary: &'a str,
+    pub description: Option<&'a str>,
+    pub location: Option<&'a str>,
+    pub url: Option<&'a str>,
+    pub start: EventTime,
+    /// Inclusive. e.g. the last day of an all-day event.
+    pub end: Option<EventTime>,
+    /// When the event was last modified.
+    pub stamp: NaiveDate,
+}
+
+/// Build an iCalendar (RFC 5545) of `events`. Times are floating, i.e. in the local time of
+/// each reader.
+pub fn calendar(name: &str, events: &[Event]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//hayatoito//site//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", escape(event.uid)));
+        lines.push(format!("DTSTAMP:{}T000000Z", event.stamp.format("%Y%m%d")));
+        lines.push(property("DTSTART", &event.start));
+        match event.end {
+            // DTEND of an all-day event is exclusive.
+            Some(EventTime::Date(date)) => lines.push(property(
+                "DTEND",
+                &EventTime::Date(date + chrono::Days::new(1)),
+            )),
+            Some(end) => lines.push(property("DTEND", &end)),
+            None => {}
+        }
+        lines.push(format!("SUMMARY:{}", escape(event.summary)));
+        if let Some(description) = event.description {
+            lines.push(format!("DESCRIPTION:{}", escape(description)));
+        }
+        if let Some(location) = event.location {
+            lines.push(format!("LOCATION:{}", escape(location)));
+        }
+        if let Some(url) = event.url {
+            lines.push(format!("URL:{url}"));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+fn property(name: &str, time: &EventTime) -> String {
+    match time {
+        EventTime::DateTime(datetime) => format!("{name}:{}", datetime.format("%Y%m%dT%H%M%S")),
+        EventTime::Date(date) => format!("{name};VALUE=DATE:{}", date.format("%Y%m%d")),
+    }
+}
+
+/// Escape a TEXT value.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line into lines of at most 75 octets, ending with CRLF.
+fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + 2);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calendar_test() {
+        let time = |s: &str| toml::Value::from(s).try_into::<EventTime>().unwrap();
+        let events = [
+            Event {
+                uid: "https://example.com/meetup-1/",
+                summary: "Meetup #1",
+                description: Some("Talks, and pizza; drinks"),
+                location: Some("Tokyo"),
+                url: Some("https://example.com/meetup-1/"),
+                start: time("2024-05-01T19:00:00"),
+                end: Some(time("2024-05-01T21:00:00")),
+                stamp: NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+            },
+            Event {
+                uid: "conf",
+                summary: &"A long name of a conference ".repeat(3),
+                description: None,
+                location: None,
+                url: None,
+                start: time("2024-06-01"),
+                end: Some(time("2024-06-02")),
+                stamp: NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+            },
+        ];
+        assert_eq!(
+            calendar("Meetups", &events),
+            "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//hayatoito//site//EN\r
+CALSCALE:GREGORIAN\r
+X-WR-CALNAME:Meetups\r
+BEGIN:VEVENT\r
+UID:https://example.com/meetup-1/\r
+DTSTAMP:20240401T000000Z\r
+DTSTART:20240501T190000\r
+DTEND:20240501T210000\r
+SUMMARY:Meetup #1\r
+DESCRIPTION:Talks\\, and pizza\\; drinks\r
+LOCATION:Tokyo\r
+URL:https://example.com/meetup-1/\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:conf\r
+DTSTAMP:20240402T000000Z\r
+DTSTART;VALUE=DATE:20240601\r
+DTEND;VALUE=DATE:20240603\r
+SUMMARY:A long name of a conference A long name of a conference A long name\r
+  of a conference \r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        );
+    }
+}
@@ -0,0 +1,1146 @@
+//! Import content from other static site generators.
+
+use anyhow::{anyhow, Context as _, Error, Result};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use crate::xml::Element;
+
+/// A static site generator, or a blog service's export, to import from.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ImportSource {
+    Hugo,
+    Jekyll,
+    Zola,
+    /// A WordPress export (WXR) file.
+    Wordpress,
+    /// A Blogger export (Atom) file.
+    Blogger,
+}
+
+impl FromStr for ImportSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hugo" => Ok(ImportSource::Hugo),
+            "jekyll" => Ok(ImportSource::Jekyll),
+            "zola" => Ok(ImportSource::Zola),
+            "wordpress" => Ok(ImportSource::Wordpress),
+            "blogger" => Ok(ImportSource::Blogger),
+            _ => Err(anyhow!(
+                "unknown import source: {s} (expected `hugo`, `jekyll`, `zola`, `wordpress` or `blogger`)"
+            )),
+        }
+    }
+}
+
+/// What to write into a file in `src`.
+#[derive(Debug)]
+enum Action {
+    /// Convert a markdown, as a page if `page`, or as a draft if `draft`.
+    Convert {
+        source: PathBuf,
+        page: bool,
+        draft: bool,
+    },
+    Copy(PathBuf),
+    /// A converted post in an export.
+    Write(String),
+    /// An image referenced by a post in an export.
+    Download(String),
+}
+
+impl Action {
+    fn source(&self) -> String {
+        match self {
+            Action::Convert { source, .. } | Action::Copy(source) => source.display().to_string(),
+            Action::Write(_) => "(export)".to_string(),
+            Action::Download(url) => url.clone(),
+        }
+    }
+}
+
+/// Convert the site in `path`, or the export file at `path`, into markdowns with TOML metadata
+/// in `src_dir`, and copy other files, such as images, as is. Returns the written files.
+/// Nothing is written if any of them already exists, or if two files are written to the same
+/// path.
+pub fn import(from: ImportSource, path: &Path, src_dir: &Path) -> Result<Vec<PathBuf>> {
+    let files = match from {
+        ImportSource::Hugo | ImportSource::Zola => bundle_layout(path)?,
+        ImportSource::Jekyll => jekyll_layout(path)?,
+        ImportSource::Wordpress | ImportSource::Blogger => {
+            let s = std::fs::read_to_string(path)
+                .with_context(|| format!("can not read: {}", path.display()))?;
+            let root = crate::xml::parse(&s)
+                .with_context(|| format!("can not parse: {}", path.display()))?;
+            let posts = if from == ImportSource::Wordpress {
+                wordpress_posts(&root)?
+            } else {
+                blogger_posts(&root)?
+            };
+            export_layout(posts)
+        }
+    };
+    // e.g. Jekyll's `2020-01-02-foo.md` and `2021-03-04-foo.md` are both `foo.md`.
+    let mut sources = BTreeMap::<&Path, &Action>::new();
+    for (dest, action) in &files {
+        if let Some(other) = sources.insert(dest, action) {
+            return Err(anyhow!(
+                "{} and {} are both imported into {}",
+                other.source(),
+                action.source(),
+                src_dir.join(dest).display()
+            ));
+        }
+        let dest = src_dir.join(dest);
+        anyhow::ensure!(!dest.exists(), "{} already exists", dest.display());
+    }
+    let mut written = Vec::new();
+    for (dest, action) in files {
+        anyhow::ensure!(
+            is_relative(&dest.to_string_lossy()),
+            "{} is imported outside of src: {}",
+            action.source(),
+            dest.display()
+        );
+        let dest = src_dir.join(dest);
+        std::fs::create_dir_all(dest.parent().unwrap())?;
+        match &action {
+            Action::Convert {
+                source,
+                page,
+                draft,
+            } => {
+                let s = std::fs::read_to_string(source)
+                    .with_context(|| format!("can not read: {}", source.display()))?;
+                let markdown = convert(&s, source, *page, *draft)
+                    .with_context(|| format!("can not convert: {}", source.display()))?;
+                std::fs::write(&dest, markdown)?;
+            }
+            Action::Copy(source) => {
+                std::fs::copy(source, &dest)?;
+            }
+            Action::Write(markdown) => std::fs::write(&dest, markdown)?,
+            Action::Download(url) => {
+                // A broken image shouldn't stop the import, as old posts often have ones.
+                if let Err(e) = crate::remote::download(url, &dest) {
+                    log::warn!("can not download {url}: {e:#}");
+                    continue;
+                }
+            }
+        }
+        log::info!("{:32} => {}", action.source(), dest.display());
+        written.push(dest);
+    }
+    Ok(written)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "md" || ext == "markdown")
+}
+
+fn walk(dir: &Path) -> impl Iterator<Item = Result<PathBuf>> {
+    walkdir::WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.file_type().is_file() => Some(Ok(entry.into_path())),
+            Ok(_) => None,
+            Err(e) => Some(Err(e.into())),
+        })
+}
+
+/// Hugo's and Zola's layout: markdowns in `content/`, where `_index.md` is a section and
+/// `foo/index.md` is a page bundle with its assets, and static files in `static/`.
+fn bundle_layout(dir: &Path) -> Result<Vec<(PathBuf, Action)>> {
+    let content_dir = dir.join("content");
+    anyhow::ensure!(
+        content_dir.is_dir(),
+        "can not find content dir: {}",
+        content_dir.display()
+    );
+    let mut files = Vec::new();
+    for path in walk(&content_dir) {
+        let path = path?;
+        let rel = path.strip_prefix(&content_dir)?.to_path_buf();
+        let (dest, action) = if !is_markdown(&path) {
+            (rel, Action::Copy(path))
+        } else if rel.file_stem().is_some_and(|stem| stem == "_index") {
+            (
+                rel.with_file_name("index.md"),
+                Action::Convert {
+                    source: path,
+                    page: true,
+                    draft: false,
+                },
+            )
+        } else if rel.file_stem().is_some_and(|stem| stem == "index")
+            && rel.parent() != Some(Path::new(""))
+        {
+            // foo/index.md is served at foo/, as foo.md is, so that relative links to its
+            // assets in foo/ keep working.
+            (
+                rel.parent().unwrap().with_extension("md"),
+                Action::Convert {
+                    source: path,
+                    page: false,
+                    draft: false,
+                },
+            )
+        } else {
+            (
+                rel.with_extension("md"),
+                Action::Convert {
+                    source: path,
+                    page: false,
+                    draft: false,
+                },
+            )
+        };
+        files.push((dest, action));
+    }
+    let static_dir = dir.join("static");
+    if static_dir.is_dir() {
+        for path in walk(&static_dir) {
+            let path = path?;
+            let rel = path.strip_prefix(&static_dir)?.to_path_buf();
+            files.push((rel, Action::Copy(path)));
+        }
+    }
+    Ok(files)
+}
+
+/// Jekyll's layout: posts in `_posts/`, named `YYYY-MM-DD-slug.md`, drafts in `_drafts/`, and
+/// pages and static files in other directories, except ones starting with `_` or `.`.
+fn jekyll_layout(dir: &Path) -> Result<Vec<(PathBuf, Action)>> {
+    static POST_NAME: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}-(.+)$").unwrap());
+    const IGNORED: &[&str] = &["Gemfile", "Gemfile.lock", "node_modules", "vendor"];
+
+    let mut files = Vec::new();
+    for (posts_dir, draft) in [("_posts", false), ("_drafts", true)] {
+        let posts_dir = dir.join(posts_dir);
+        if !posts_dir.is_dir() {
+            continue;
+        }
+        for path in walk(&posts_dir) {
+            let path = path?;
+            if !is_markdown(&path) {
+                continue;
+            }
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            let slug = POST_NAME
+                .captures(&stem)
+                .map_or(stem.to_string(), |caps| caps[1].to_string());
+            files.push((
+                PathBuf::from(format!("{slug}.md")),
+                Action::Convert {
+                    source: path,
+                    page: false,
+                    draft,
+                },
+            ));
+        }
+    }
+    let entries = walkdir::WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0
+                || !(name.starts_with(['_', '.']) || IGNORED.contains(&name.as_ref()))
+        });
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.into_path();
+        let rel = path.strip_prefix(dir)?.to_path_buf();
+        if is_markdown(&path) {
+            let action = Action::Convert {
+                source: path,
+                page: true,
+                draft: false,
+            };
+            files.push((rel.with_extension("md"), action));
+        } else {
+            files.push((rel, Action::Copy(path)));
+        }
+    }
+    Ok(files)
+}
+
+/// Split front matter, fenced by `---` (YAML) or `+++` (TOML), or a JSON object, from the
+/// content.
+fn split_front_matter(s: &str) -> Result<(toml::Table, &str)> {
+    let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+    for (fence, yaml) in [("---", true), ("+++", false)] {
+        let Some(rest) = s.strip_prefix(fence).and_then(|rest| {
+            rest.strip_prefix('\n')
+                .or_else(|| rest.strip_prefix("\r\n"))
+        }) else {
+            continue;
+        };
+        let (front_matter, content) = match rest.find(&format!("\n{fence}")) {
+            Some(end) => {
+                let content = &rest[end + 1 + fence.len()..];
+                (
+                    &rest[..end + 1],
+                    content.split_once('\n').map_or("", |(_, c)| c),
+                )
+            }
+            None => return Err(anyhow!("front matter is not closed by \"{fence}\"")),
+        };
+        let table = if yaml {
+            parse_yaml(front_matter)?
+        } else {
+            toml::from_str(front_matter)?
+        };
+        return Ok((table, content));
+    }
+    if s.starts_with('{') {
+        let mut stream = serde_json::Deserializer::from_str(s).into_iter::<toml::Table>();
+        if let Some(table) = stream.next() {
+            return Ok((table?, &s[stream.byte_offset()..]));
+        }
+    }
+    Ok((toml::Table::new(), s))
+}
+
+/// Parse YAML front matter: mappings, sequences, and plain, quoted, or block (`|` and `>`)
+/// scalars. Anchors, tags and flow mappings are not supported.
+fn parse_yaml(s: &str) -> Result<toml::Table> {
+    let lines = s.lines().collect::<Vec<_>>();
+    let mut i = 0;
+    match yaml_block(&lines, &mut i, 0)? {
+        Some(toml::Value::Table(table)) => Ok(table),
+        None => Ok(toml::Table::new()),
+        Some(_) => Err(anyhow!("front matter is not a mapping")),
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Skip blank lines and comments, and return the indent of the next line, if any.
+fn next_indent(lines: &[&str], i: &mut usize) -> Option<usize> {
+    while *i < lines.len() {
+        let trimmed = lines[*i].trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            return Some(indent_of(lines[*i]));
+        }
+        *i += 1;
+    }
+    None
+}
+
+/// Parse a mapping or a sequence whose lines are indented by at least `min_indent`.
+fn yaml_block(lines: &[&str], i: &mut usize, min_indent: usize) -> Result<Option<toml::Value>> {
+    let indent = match next_indent(lines, i) {
+        Some(indent) if indent >= min_indent => indent,
+        _ => return Ok(None),
+    };
+    let is_item = |line: &str| line.trim_start() == "-" || line.trim_start().starts_with("- ");
+    if is_item(lines[*i]) {
+        let mut items = Vec::new();
+        while next_indent(lines, i) == Some(indent) && is_item(lines[*i]) {
+            let item = lines[*i].trim_start()[1..].trim();
+            *i += 1;
+            if item.is_empty() {
+                items.extend(yaml_block(lines, i, indent + 1)?);
+            } else {
+                items.extend(yaml_scalar(item)?);
+            }
+        }
+        return Ok(Some(toml::Value::Array(items)));
+    }
+    let mut table = toml::Table::new();
+    while next_indent(lines, i) == Some(indent) {
+        let line = lines[*i].trim();
+        let (key, value) = line
+            .split_once(": ")
+            .or_else(|| line.strip_suffix(':').map(|key| (key, "")))
+            .ok_or_else(|| anyhow!("line {}: expected `key: value`: {line}", *i + 1))?;
+        let key = unquote(key.trim());
+        let value = strip_comment(value);
+        *i += 1;
+        let value = if value.is_empty() {
+            // A nested block, which may be a sequence at the same indent as the key.
+            let min_indent = if next_indent(lines, i) == Some(indent) && is_item(lines[*i]) {
+                indent
+            } else {
+                indent + 1
+            };
+            yaml_block(lines, i, min_indent)?
+        } else if let Some(style) = value.strip_prefix(['|', '>']).map(|_| &value[..1]) {
+            let mut block = Vec::new();
+            while *i < lines.len() && (lines[*i].trim().is_empty() || indent_of(lines[*i]) > indent)
+            {
+                block.push(lines[*i].trim());
+                *i += 1;
+            }
+            while block.last() == Some(&"") {
+                block.pop();
+            }
+            let s = if style == "|" {
+                block.join("\n")
+            } else {
+                block.join(" ")
+            };
+            Some(toml::Value::String(s))
+        } else {
+            yaml_scalar(value)?
+        };
+        if let Some(value) = value {
+            table.insert(key, value);
+        }
+    }
+    Ok(Some(toml::Value::Table(table)))
+}
+
+/// Strip a trailing ` # comment` outside of quotes.
+fn strip_comment(s: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '#') if i == 0 || s[..i].ends_with(' ') => return s[..i].trim_end(),
+            _ => {}
+        }
+    }
+    s.trim()
+}
+
+fn unquote(s: &str) -> String {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        s[1..s.len() - 1]
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\")
+    } else if s.len() >= 2 && s.starts_with('\'') && s.ends_with('\'') {
+        s[1..s.len() - 1].replace("''", "'")
+    } else {
+        s.to_string()
+    }
+}
+
+/// A scalar, or a flow sequence such as `[a, "b"]`. `None` for null.
+fn yaml_scalar(s: &str) -> Result<Option<toml::Value>> {
+    let s = strip_comment(s);
+    if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        let mut items = Vec::new();
+        let mut quote = None;
+        let mut start = 0;
+        for (i, c) in inner.char_indices().chain([(inner.len(), ',')]) {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), c) if q == c => quote = None,
+                (None, ',') => {
+                    let item = inner[start..i].trim();
+                    if !item.is_empty() {
+                        items.extend(yaml_scalar(item)?);
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        return Ok(Some(toml::Value::Array(items)));
+    }
+    if s.starts_with(['"', '\'']) {
+        return Ok(Some(toml::Value::String(unquote(s))));
+    }
+    Ok(match s {
+        "" | "~" | "null" => None,
+        "true" | "True" | "yes" => Some(toml::Value::Boolean(true)),
+        "false" | "False" | "no" => Some(toml::Value::Boolean(false)),
+        _ => Some(
+            s.parse::<i64>()
+                .map_or_else(|_| toml::Value::String(s.to_string()), toml::Value::Integer),
+        ),
+    })
+}
+
+fn as_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Datetime(d) => Some(d.to_string()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        _ => None,
+    }
+}
+
+fn as_strings(value: &toml::Value) -> Vec<String> {
+    match value {
+        toml::Value::Array(values) => values.iter().filter_map(as_string).collect(),
+        value => as_string(value)
+            .map(|s| {
+                // Jekyll allows space-separated tags.
+                s.split_whitespace().map(String::from).collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// `2020-01-02`, `2020-01-02T03:04:05+09:00` or `2020-01-02 03:04:05 +0900`, as a date.
+fn as_date(value: &toml::Value) -> Option<String> {
+    let s = as_string(value)?;
+    let date = s.get(..10)?;
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .map(|_| date.to_string())
+}
+
+/// Convert a markdown with front matter, `s`, into this crate's format.
+fn convert(s: &str, source: &Path, page: bool, draft: bool) -> Result<String> {
+    static POST_DATE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(\d{4}-\d{2}-\d{2})-").unwrap());
+    static SHORTCODE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\{\{<\s*([a-z]+)|\{%\s*([a-z_]+)").unwrap());
+    const SHORTCODES: &[&str] = &["youtube", "vimeo", "video", "audio"];
+
+    let (mut front_matter, content) = split_front_matter(s)?;
+    // Zola's taxonomies are in a table.
+    if let Some(toml::Value::Table(taxonomies)) = front_matter.remove("taxonomies") {
+        front_matter.extend(taxonomies);
+    }
+
+    let file_name = source.file_stem().unwrap().to_string_lossy();
+    let mut title = None;
+    let mut metadata = Vec::<(&str, toml::Value)>::new();
+    let mut tags = Vec::<String>::new();
+    let mut dropped = Vec::new();
+    let mut date = POST_DATE
+        .captures(&file_name)
+        .map(|caps| caps[1].to_string());
+    let mut draft = draft;
+    for (key, value) in &front_matter {
+        let string = || as_string(value).map(toml::Value::String);
+        let value = match key.as_str() {
+            "title" => {
+                title = as_string(value);
+                None
+            }
+            "date" => {
+                date = as_date(value).or(date);
+                None
+            }
+            "draft" => {
+                draft |= value.as_bool() == Some(true);
+                None
+            }
+            // Jekyll
+            "published" => {
+                draft |= value.as_bool() == Some(false);
+                None
+            }
+            "tags" | "categories" | "category" => {
+                for tag in as_strings(value) {
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+                None
+            }
+            "lastmod" | "updated" | "last_modified_at" => {
+                as_date(value).map(|date| ("update_date", toml::Value::String(date)))
+            }
+            "description" | "summary" | "excerpt" => string().map(|v| ("description", v)),
+            "author" => string().map(|v| ("author", v)),
+            "authors" => as_strings(value)
+                .into_iter()
+                .next()
+                .map(|author| ("author", toml::Value::String(author))),
+            "slug" => string().map(|v| ("slug", v)),
+            "series" => as_strings(value)
+                .into_iter()
+                .next()
+                .map(|series| ("series", toml::Value::String(series))),
+            "keywords" => Some((
+                "keywords",
+                toml::Value::Array(as_strings(value).into_iter().map(Into::into).collect()),
+            )),
+            "weight" => value.as_integer().map(|v| ("weight", v.into())),
+            "math" | "katex" | "mathjax" => value.as_bool().map(|v| ("math", v.into())),
+            "toc" => value.as_bool().map(|v| ("toc", v.into())),
+            _ => {
+                dropped.push(key.as_str());
+                None
+            }
+        };
+        metadata.extend(value);
+    }
+    let mut head = Vec::new();
+    if page {
+        head.push(("page", toml::Value::Boolean(true)));
+    }
+    if let Some(date) = date.filter(|_| !page) {
+        head.push(("date", toml::Value::String(date)));
+    }
+    if draft {
+        head.push(("draft", toml::Value::Boolean(true)));
+    }
+    metadata.splice(0..0, head);
+    if !tags.is_empty() {
+        metadata.push((
+            "tags",
+            toml::Value::Array(tags.into_iter().map(Into::into).collect()),
+        ));
+    }
+    if !dropped.is_empty() {
+        log::warn!(
+            "{}: dropped front matter: {}",
+            source.display(),
+            dropped.join(", ")
+        );
+    }
+    let mut unsupported = SHORTCODE
+        .captures_iter(content)
+        .map(|caps| caps.get(1).or(caps.get(2)).unwrap().as_str())
+        .filter(|name| !SHORTCODES.contains(name))
+        .collect::<Vec<_>>();
+    unsupported.sort();
+    unsupported.dedup();
+    if !unsupported.is_empty() {
+        log::warn!(
+            "{}: unsupported shortcodes or tags: {}",
+            source.display(),
+            unsupported.join(", ")
+        );
+    }
+
+    let title = title.unwrap_or_else(|| POST_DATE.replace(&file_name, "").to_string());
+    Ok(markdown(&title, metadata, content))
+}
+
+/// A markdown in this crate's format.
+fn markdown(title: &str, metadata: Vec<(&str, toml::Value)>, content: &str) -> String {
+    let mut out = format!("# {title}\n\n<!--\n");
+    for (key, value) in metadata {
+        out.push_str(&format!("{key} = {value}\n"));
+    }
+    out.push_str("-->\n");
+    let content = content.trim_start_matches(['\n', '\r']);
+    if !content.is_empty() {
+        out.push('\n');
+        out.push_str(content);
+    }
+    out
+}
+
+/// A post or a page in an export.
+#[derive(Debug, Default)]
+struct Post {
+    title: String,
+    /// The URL where it was published, which is kept if possible.
+    link: Option<String>,
+    /// The slug, such as WordPress's `post_name`.
+    name: Option<String>,
+    date: Option<String>,
+    update_date: Option<String>,
+    author: Option<String>,
+    /// HTML.
+    excerpt: Option<String>,
+    tags: Vec<String>,
+    page: bool,
+    draft: bool,
+    /// HTML.
+    content: String,
+}
+
+/// Posts and pages in a WordPress export (WXR).
+fn wordpress_posts(root: &Element) -> Result<Vec<Post>> {
+    let channel = root
+        .element("channel")
+        .ok_or_else(|| anyhow!("not a WordPress export: no <channel>"))?;
+    let authors = channel
+        .elements("wp:author")
+        .filter_map(|author| {
+            Some((
+                author.child_text("wp:author_login")?,
+                author.child_text("wp:author_display_name")?,
+            ))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut posts = Vec::new();
+    for item in channel.elements("item") {
+        let page = match item.child_text("wp:post_type").as_deref() {
+            Some("post") => false,
+            Some("page") => true,
+            // Attachments, menus, and so on.
+            _ => continue,
+        };
+        let draft = match item.child_text("wp:status").as_deref() {
+            Some("publish" | "future") => false,
+            Some("draft" | "pending" | "private") => true,
+            _ => continue,
+        };
+        let date = |name| {
+            item.child_text(name)
+                .and_then(|date| as_date(&toml::Value::String(date)))
+        };
+        posts.push(Post {
+            title: item.child_text("title").unwrap_or_default(),
+            link: item.child_text("link"),
+            name: item.child_text("wp:post_name"),
+            date: date("wp:post_date"),
+            update_date: date("wp:post_modified"),
+            author: item
+                .child_text("dc:creator")
+                .map(|login| authors.get(&login).cloned().unwrap_or(login)),
+            excerpt: item.child_text("excerpt:encoded"),
+            tags: item
+                .elements("category")
+                .map(|category| category.text().trim().to_string())
+                .filter(|tag| !tag.is_empty() && tag != "Uncategorized")
+                .collect(),
+            page,
+            draft,
+            content: item.child_text("content:encoded").unwrap_or_default(),
+        });
+    }
+    Ok(posts)
+}
+
+/// Posts and pages in a Blogger export (Atom), either the old one where the kind of an entry is
+/// a `<category>`, or Google Takeout's one with `<blogger:type>`.
+fn blogger_posts(root: &Element) -> Result<Vec<Post>> {
+    const KIND: &str = "http://schemas.google.com/g/2005#kind";
+
+    anyhow::ensure!(root.name == "feed", "not a Blogger export: no <feed>");
+    let mut posts = Vec::new();
+    for entry in root.elements("entry") {
+        let kind = entry
+            .elements("category")
+            .find(|category| category.attr("scheme") == Some(KIND))
+            .and_then(|category| category.attr("term"))
+            .and_then(|term| term.rsplit_once('#'))
+            .map(|(_, kind)| kind.to_string())
+            .or_else(|| entry.child_text("blogger:type"))
+            .map(|kind| kind.to_ascii_lowercase());
+        let page = match kind.as_deref() {
+            Some("post") => false,
+            Some("page") => true,
+            // Comments, settings, and so on.
+            _ => continue,
+        };
+        let draft = match entry.child_text("blogger:status").as_deref() {
+            Some("LIVE") => false,
+            Some("DRAFT") => true,
+            Some(_) => continue,
+            None => entry
+                .element("app:control")
+                .and_then(|control| control.child_text("app:draft"))
+                .is_some_and(|draft| draft == "yes"),
+        };
+        let date = |name| {
+            entry
+                .child_text(name)
+                .and_then(|date| as_date(&toml::Value::String(date)))
+        };
+        posts.push(Post {
+            title: entry.child_text("title").unwrap_or_default(),
+            link: entry
+                .elements("link")
+                .find(|link| link.attr("rel") == Some("alternate"))
+                .and_then(|link| link.attr("href"))
+                .map(String::from)
+                .or_else(|| entry.child_text("blogger:filename")),
+            name: None,
+            date: date("published"),
+            update_date: date("updated"),
+            author: entry
+                .element("author")
+                .and_then(|author| author.child_text("name")),
+            excerpt: None,
+            tags: entry
+                .elements("category")
+                .filter(|category| category.attr("scheme") != Some(KIND))
+                .filter_map(|category| category.attr("term"))
+                .map(String::from)
+                .collect(),
+            page,
+            draft,
+            content: entry.child_text("content").unwrap_or_default(),
+        });
+    }
+    Ok(posts)
+}
+
+/// Whether `path` is a relative path which stays inside the directory it's joined to, i.e. it
+/// has no `..`, `.`, or root components.
+fn is_relative(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// The path of `link`, without the leading `/`, percent-decoded, such as `2020/01/hello/`.
+/// `None` if the decoded path would point outside of src, such as `/..%2F..%2Fetc/`.
+fn url_path(link: &str) -> Option<String> {
+    let path = match link.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]),
+        None => link,
+    };
+    // A link with a query, such as `/?p=123`, has no meaningful path.
+    if path.contains('?') {
+        return None;
+    }
+    let path = path.split('#').next().unwrap();
+    Some(crate::text::percent_decode(path.trim_start_matches('/'))).filter(|path| is_relative(path))
+}
+
+/// Markdowns for `posts`, at the paths of their links so that their URLs are kept, and images
+/// which they reference, in `images/`.
+fn export_layout(posts: Vec<Post>) -> Vec<(PathBuf, Action)> {
+    const IMAGES: &[&str] = &["avif", "gif", "jpeg", "jpg", "png", "svg", "webp"];
+
+    if !cfg!(feature = "remote") {
+        log::warn!("images are not downloaded: site is built without the `remote` feature");
+    }
+    let mut files = Vec::new();
+    // URL => file name in `images/`.
+    let mut images = BTreeMap::<String, String>::new();
+    let mut paths = BTreeSet::new();
+    for post in posts {
+        // `2020/01/hello/` becomes `2020/01/hello.md`, and Blogger's `2020/01/hello.html`
+        // becomes `2020/01/hello.md` with `slug = "hello.html"`.
+        let path = post
+            .link
+            .as_deref()
+            .and_then(url_path)
+            .map(|path| path.trim_end_matches('/').to_string())
+            .filter(|path| !path.is_empty());
+        let (dir, name) = match path.as_deref().and_then(|path| path.rsplit_once('/')) {
+            Some((dir, name)) => (dir.to_string(), name.to_string()),
+            None => (String::new(), path.unwrap_or_default()),
+        };
+        let (mut stem, slug) = match name.rsplit_once('.') {
+            Some((stem, _)) => (stem.to_string(), Some(name.clone())),
+            None => (name.clone(), None),
+        };
+        if stem.is_empty() {
+            stem = post
+                .name
+                .as_deref()
+                .map(crate::text::percent_decode)
+                .filter(|name| !name.is_empty() && !name.contains('/') && is_relative(name))
+                .unwrap_or_else(|| crate::text::transliterate(&post.title));
+        }
+        if stem.is_empty() {
+            stem = "untitled".to_string();
+        }
+        let mut dest = Path::new(&dir).join(format!("{stem}.md"));
+        let mut n = 1;
+        while !paths.insert(dest.clone()) {
+            n += 1;
+            dest = Path::new(&dir).join(format!("{stem}-{n}.md"));
+        }
+
+        let mut map_url = |url: &str| {
+            let path = url.split(['?', '#']).next().unwrap();
+            let is_image = Path::new(path)
+                .extension()
+                .is_some_and(|ext| IMAGES.contains(&ext.to_string_lossy().to_lowercase().as_str()));
+            if !cfg!(feature = "remote") || !url.starts_with("http") || !is_image {
+                return url.to_string();
+            }
+            let name = images.get(url).cloned().unwrap_or_else(|| {
+                // The last segment may decode to `../../foo.png`.
+                let file_name = crate::text::percent_decode(path.rsplit('/').next().unwrap());
+                let file_name = match Path::new(&file_name).components().next_back() {
+                    Some(Component::Normal(name)) => name.to_string_lossy().into_owned(),
+                    _ => "image".to_string(),
+                };
+                let mut name = file_name.clone();
+                let mut n = 1;
+                while images.values().any(|used| *used == name) {
+                    n += 1;
+                    name = format!("{n}-{file_name}");
+                }
+                images.insert(url.to_string(), name.clone());
+                name
+            });
+            format!("/images/{}", crate::text::percent_encode(&name))
+        };
+        let content = crate::text::html_to_markdown(&post.content, &mut map_url);
+
+        let mut metadata = Vec::<(&str, toml::Value)>::new();
+        if post.page {
+            metadata.push(("page", true.into()));
+        }
+        if let Some(date) = post.date.as_ref().filter(|_| !post.page) {
+            metadata.push(("date", date.as_str().into()));
+            if let Some(update_date) = post.update_date.filter(|update_date| update_date > date) {
+                metadata.push(("update_date", update_date.into()));
+            }
+        }
+        if post.draft {
+            metadata.push(("draft", true.into()));
+        }
+        if let Some(slug) = slug {
+            metadata.push(("slug", slug.into()));
+        }
+        if let Some(author) = post.author {
+            metadata.push(("author", author.into()));
+        }
+        if let Some(excerpt) = post.excerpt {
+            let description = crate::text::html_to_plain(&excerpt)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            metadata.push(("description", description.into()));
+        }
+        let mut tags = Vec::<String>::new();
+        for tag in post.tags {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if !tags.is_empty() {
+            metadata.push((
+                "tags",
+                toml::Value::Array(tags.into_iter().map(Into::into).collect()),
+            ));
+        }
+        let title = if post.title.is_empty() {
+            &stem
+        } else {
+            &post.title
+        };
+        files.push((dest, Action::Write(markdown(title, metadata, &content))));
+    }
+    for (url, name) in images {
+        files.push((Path::new("images").join(name), Action::Download(url)));
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_test() {
+        let dir = crate::testing::TempDir::new("import");
+        let (hugo, jekyll, src_dir) = (dir.join("hugo"), dir.join("jekyll"), dir.join("src"));
+        let write = |path: PathBuf, content: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(
+            hugo.join("content/posts/hello/index.md"),
+            r#"---
+title: "Hello: World"
+date: 2020-01-02T03:04:05+09:00
+lastmod: 2020-02-01
+tags: [rust, "static site"]
+categories:
+  - blog
+aliases:
+  - /old/hello
+description: >
+  A long
+  description.
+---
+
+Hello {{< youtube dQw4w9WgXcQ >}}
+"#,
+        );
+        write(hugo.join("content/posts/hello/cover.png"), "png");
+        write(
+            hugo.join("content/posts/_index.md"),
+            "+++\ntitle = \"Posts\"\n+++\n",
+        );
+        write(
+            jekyll.join("_posts/2021-03-04-first-post.markdown"),
+            "---\nlayout: post\ntags: a b\npublished: false\n---\nFirst.\n",
+        );
+        write(
+            jekyll.join("about.md"),
+            "---\ntitle: About\n---\nAbout me.\n",
+        );
+        write(jekyll.join("_config.yml"), "title: Blog\n");
+
+        let written = import(ImportSource::Hugo, &hugo, &src_dir).unwrap();
+        assert_eq!(written.len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(src_dir.join("posts/hello.md")).unwrap(),
+            r#"# Hello: World
+
+<!--
+date = "2020-01-02"
+description = "A long description."
+update_date = "2020-02-01"
+tags = ["blog", "rust", "static site"]
+-->
+
+Hello {{< youtube dQw4w9WgXcQ >}}
+"#
+        );
+        assert!(src_dir.join("posts/hello/cover.png").exists());
+        assert_eq!(
+            std::fs::read_to_string(src_dir.join("posts/index.md")).unwrap(),
+            "# Posts\n\n<!--\npage = true\n-->\n"
+        );
+        assert!(import(ImportSource::Hugo, &hugo, &src_dir)
+            .unwrap_err()
+            .to_string()
+            .ends_with("already exists"));
+
+        import(ImportSource::Jekyll, &jekyll, &src_dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(src_dir.join("first-post.md")).unwrap(),
+            "# first-post\n\n<!--\ndate = \"2021-03-04\"\ndraft = true\ntags = [\"a\", \"b\"]\n-->\n\nFirst.\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(src_dir.join("about.md")).unwrap(),
+            "# About\n\n<!--\npage = true\n-->\n\nAbout me.\n"
+        );
+        assert!(!src_dir.join("_config.yml").exists());
+
+        write(jekyll.join("_posts/2022-01-01-first-post.md"), "Again.\n");
+        let e = import(ImportSource::Jekyll, &jekyll, &dir.join("src2")).unwrap_err();
+        assert!(e.to_string().contains("are both imported into"));
+        assert!(!dir.join("src2").exists());
+    }
+
+    #[test]
+    fn export_test() {
+        let wxr = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+<channel>
+  <wp:author><wp:author_login>alice</wp:author_login><wp:author_display_name><![CDATA[Alice]]></wp:author_display_name></wp:author>
+  <item>
+    <title>Hello &amp; welcome</title>
+    <link>https://example.com/2020/01/02/hello/</link>
+    <dc:creator>alice</dc:creator>
+    <content:encoded><![CDATA[<!-- wp:paragraph -->
+<p>Hi, <a href="https://example.com/about/">me</a>.</p>
+<!-- /wp:paragraph -->
+<img src="https://example.com/wp-content/uploads/2020/01/cat.jpg" alt="cat">]]></content:encoded>
+    <excerpt:encoded><![CDATA[<p>An excerpt.</p>]]></excerpt:encoded>
+    <wp:post_date>2020-01-02 03:04:05</wp:post_date>
+    <wp:post_modified>2020-01-02 05:00:00</wp:post_modified>
+    <wp:post_name>hello</wp:post_name>
+    <wp:status>publish</wp:status>
+    <wp:post_type>post</wp:post_type>
+    <category domain="category" nicename="uncategorized"><![CDATA[Uncategorized]]></category>
+    <category domain="post_tag" nicename="rust"><![CDATA[Rust]]></category>
+  </item>
+  <item>
+    <title>Draft</title>
+    <link>https://example.com/?p=7</link>
+    <wp:post_date>0000-00-00 00:00:00</wp:post_date>
+    <wp:post_name></wp:post_name>
+    <wp:status>draft</wp:status>
+    <wp:post_type>post</wp:post_type>
+  </item>
+  <item>
+    <title>cat</title>
+    <wp:status>inherit</wp:status>
+    <wp:post_type>attachment</wp:post_type>
+  </item>
+</channel>
+</rss>"#;
+        let posts = wordpress_posts(&crate::xml::parse(wxr).unwrap()).unwrap();
+        let files = export_layout(posts);
+        let image = if cfg!(feature = "remote") {
+            "/images/cat.jpg"
+        } else {
+            "https://example.com/wp-content/uploads/2020/01/cat.jpg"
+        };
+        assert!(matches!(&files[0], (path, Action::Write(markdown))
+            if path == Path::new("2020/01/02/hello.md") && *markdown == format!(r#"# Hello & welcome
+
+<!--
+date = "2020-01-02"
+author = "Alice"
+description = "An excerpt."
+tags = ["Rust"]
+-->
+
+Hi, [me](https://example.com/about/).
+
+![cat]({image})
+"#)));
+        assert!(matches!(&files[1], (path, Action::Write(markdown))
+            if path == Path::new("draft.md") && markdown.starts_with("# Draft\n\n<!--\ndraft = true\n-->\n")));
+        if cfg!(feature = "remote") {
+            assert!(matches!(&files[2], (path, Action::Download(url))
+                if path == Path::new("images/cat.jpg") && url == "https://example.com/wp-content/uploads/2020/01/cat.jpg"));
+        }
+
+        let atom = r#"<?xml version='1.0' encoding='UTF-8'?>
+<feed xmlns='http://www.w3.org/2005/Atom'>
+<entry>
+  <published>2019-05-06T07:08:09.000+09:00</published>
+  <updated>2019-06-01T00:00:00.000+09:00</updated>
+  <category scheme='http://schemas.google.com/g/2005#kind' term='http://schemas.google.com/blogger/2008/kind#post'/>
+  <category scheme='http://www.blogger.com/atom/ns#' term='diary'/>
+  <title type='text'>日記</title>
+  <content type='html'>&lt;b&gt;Today&lt;/b&gt;</content>
+  <link rel='alternate' type='text/html' href='https://foo.blogspot.com/2019/05/blog-post.html' title='日記'/>
+  <author><name>Bob</name></author>
+</entry>
+<entry>
+  <category scheme='http://schemas.google.com/g/2005#kind' term='http://schemas.google.com/blogger/2008/kind#comment'/>
+  <title type='text'>A comment</title>
+</entry>
+</feed>"#;
+        let posts = blogger_posts(&crate::xml::parse(atom).unwrap()).unwrap();
+        let files = export_layout(posts);
+        assert_eq!(files.len(), 1);
+        assert!(matches!(&files[0], (path, Action::Write(markdown))
+            if path == Path::new("2019/05/blog-post.md") && markdown == r#"# 日記
+
+<!--
+date = "2019-05-06"
+update_date = "2019-06-01"
+slug = "blog-post.html"
+author = "Bob"
+tags = ["diary"]
+-->
+
+**Today**
+"#));
+    }
+
+    #[test]
+    fn export_outside_src_test() {
+        let post = |link: &str| Post {
+            title: "Evil".to_string(),
+            link: Some(link.to_string()),
+            name: Some("..%2F..%2Fevil".to_string()),
+            date: None,
+            update_date: None,
+            author: None,
+            excerpt: None,
+            tags: Vec::new(),
+            page: false,
+            draft: false,
+            content: r#"<img src="https://example.com/..%2F..%2Fevil.png">"#.to_string(),
+        };
+        let files = export_layout(vec![
+            post("https://example.com/..%2F..%2Fetc/evil/"),
+            post("/2020/../../evil.html"),
+        ]);
+        let paths = files
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect::<Vec<_>>();
+        let mut expected = vec![Path::new("evil.md"), Path::new("evil-2.md")];
+        if cfg!(feature = "remote") {
+            expected.push(Path::new("images/evil.png"));
+        }
+        assert_eq!(paths, expected);
+    }
+}
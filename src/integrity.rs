@@ -0,0 +1,62 @@
+//! Subresource Integrity hashes for the `asset()` template function, and `checksums.txt`, a
+//! manifest of all output files.
+
+use anyhow::Result;
+use base64::Engine as _;
+use sha2::{Digest, Sha256, Sha384};
+use std::path::Path;
+
+pub const CHECKSUMS: &str = "checksums.txt";
+
+/// The value of an `integrity` attribute.
+pub fn sri(content: &[u8]) -> String {
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(Sha384::digest(content))
+    )
+}
+
+/// SHA-256 checksums of all files in `out_dir`, in the format of `sha256sum`, so that
+/// `sha256sum -c checksums.txt` verifies a deployment.
+pub fn checksums(out_dir: &Path) -> Result<String> {
+    let mut lines = String::new();
+    for entry in walkdir::WalkDir::new(out_dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(out_dir)?;
+        if path == Path::new(CHECKSUMS) {
+            continue;
+        }
+        let hash = Sha256::digest(std::fs::read(entry.path())?);
+        lines.push_str(&format!("{hash:x}  {}\n", crate::site::slash_path(path)));
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrity_test() {
+        // echo -n 'alert(1)' | openssl dgst -sha384 -binary | base64
+        assert_eq!(
+            sri(b"alert(1)"),
+            "sha384-HT2E9NfWiuQ/w1PRai+hTyqW16NIoCGA/m8VQDUopfAtcz6YQjtsMmQd5uRbVDpW"
+        );
+
+        let dir = crate::testing::TempDir::new("integrity");
+        std::fs::create_dir_all(dir.join("js")).unwrap();
+        std::fs::write(dir.join("js/a.js"), "alert(1)").unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.join(CHECKSUMS), "old").unwrap();
+        // echo -n a | sha256sum
+        assert_eq!(
+            checksums(&dir).unwrap().lines().next().unwrap(),
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb  a.txt"
+        );
+        assert_eq!(checksums(&dir).unwrap().lines().count(), 2);
+    }
+}
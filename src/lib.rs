@@ -1,5 +1,47 @@
+mod abbr;
+mod api;
+pub mod bench;
+mod budget;
+mod cache;
+mod content_hash;
+mod csp;
+pub mod daemon;
+pub mod diagnostic;
+mod epub;
+mod favicon;
+mod fediverse;
+mod gemtext;
+mod github_pages;
 mod html;
+mod ical;
+pub mod import;
+mod integrity;
+mod locale;
+pub mod logging;
+mod newsletter;
+mod opml;
+mod profile;
+mod protect;
+mod random;
+mod redirect;
+mod remote;
+mod schema;
+#[cfg(feature = "search")]
+pub mod search;
+mod shortcode;
 mod site;
+mod slides;
+mod static_files;
+pub mod stats;
+pub mod templates;
+mod terminology;
+pub mod testing;
 mod text;
+mod toc;
+mod transclude;
+pub mod webmention;
+mod xml;
 
+pub use crate::github_pages::GithubPagesConfig;
 pub use crate::site::*;
+pub use crate::text::{html_to_plain, markdown_to_plain};
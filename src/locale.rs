@@ -0,0 +1,168 @@
+//! Localized dates for the `dateformat` filter, with the names of months and weekdays from
+//! chrono's locales, and the date styles of common languages.
+
+use anyhow::{anyhow, Context as _, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, NaiveDateTime, TimeZone as _};
+use std::collections::BTreeSet;
+use std::sync::{LazyLock, Mutex};
+
+/// "long", "medium" and "short" in a language. Other languages use the locale's date format.
+struct Style {
+    language: &'static str,
+    long: &'static str,
+    medium: &'static str,
+    short: &'static str,
+}
+
+const STYLES: &[Style] = &[
+    Style {
+        language: "en",
+        long: "%B %-d, %Y",
+        medium: "%b %-d, %Y",
+        short: "%-m/%-d/%y",
+    },
+    Style {
+        language: "ja",
+        long: "%Y年%-m月%-d日",
+        medium: "%Y/%m/%d",
+        short: "%Y/%m/%d",
+    },
+    Style {
+        language: "zh",
+        long: "%Y年%-m月%-d日",
+        medium: "%Y年%-m月%-d日",
+        short: "%Y/%-m/%-d",
+    },
+    Style {
+        language: "ko",
+        long: "%Y년 %-m월 %-d일",
+        medium: "%Y. %-m. %-d.",
+        short: "%y. %-m. %-d.",
+    },
+    Style {
+        language: "de",
+        long: "%-d. %B %Y",
+        medium: "%d.%m.%Y",
+        short: "%d.%m.%y",
+    },
+    Style {
+        language: "fr",
+        long: "%-d %B %Y",
+        medium: "%-d %b %Y",
+        short: "%d/%m/%Y",
+    },
+    Style {
+        language: "es",
+        long: "%-d de %B de %Y",
+        medium: "%-d %b %Y",
+        short: "%-d/%-m/%y",
+    },
+    Style {
+        language: "pt",
+        long: "%-d de %B de %Y",
+        medium: "%-d de %b de %Y",
+        short: "%d/%m/%Y",
+    },
+];
+
+/// chrono's locale for `tag`, such as "ja" or "pt-BR". A tag without a region gets the most
+/// common one, such as "ja_JP".
+fn locale(tag: &str) -> Option<chrono::Locale> {
+    let og_locale = crate::site::og_locale(tag);
+    let language = og_locale.split('_').next().unwrap_or_default();
+    [
+        og_locale.clone(),
+        format!("{language}_{}", language.to_ascii_uppercase()),
+        language.to_string(),
+    ]
+    .iter()
+    .find_map(|name| chrono::Locale::try_from(name.as_str()).ok())
+}
+
+/// Format `date`, such as "2024-05-01" or "2024-05-01T19:00:00", in `locale_tag`, such as "ja".
+/// `format` is "long", "medium", "short", or a `strftime` format, where `%B`, `%b`, `%A` and
+/// `%a` are localized. Unsupported locales fall back to English, with a warning.
+pub fn format_date(date: &str, format: &str, locale_tag: &str) -> Result<String> {
+    let datetime = match date.parse::<NaiveDate>() {
+        Ok(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+        Err(_) => date
+            .parse::<NaiveDateTime>()
+            .with_context(|| format!("invalid date: {date}"))?,
+    };
+    static WARNED: LazyLock<Mutex<BTreeSet<String>>> = LazyLock::new(Default::default);
+    let (locale, language) = match locale(locale_tag) {
+        Some(locale) => (
+            locale,
+            locale_tag.split(['-', '_']).next().unwrap_or_default(),
+        ),
+        None => {
+            // Warned once for each tag, since the filter is used for every date.
+            if WARNED.lock().unwrap().insert(locale_tag.to_string()) {
+                log::warn!("dateformat: unsupported locale: {locale_tag}. Dates are in English");
+            }
+            (chrono::Locale::en_US, "en")
+        }
+    };
+    let style = STYLES
+        .iter()
+        .find(|style| style.language.eq_ignore_ascii_case(language));
+    let format = match (format, style) {
+        ("long", Some(style)) => style.long,
+        ("medium", Some(style)) => style.medium,
+        ("short", Some(style)) => style.short,
+        ("long" | "medium" | "short", None) => "%x",
+        (format, _) => format,
+    };
+    let items = StrftimeItems::new_with_locale(format, locale).collect::<Vec<_>>();
+    if items.contains(&Item::Error) {
+        return Err(anyhow!("invalid date format: {format}"));
+    }
+    Ok(chrono::Utc
+        .from_utc_datetime(&datetime)
+        .format_localized_with_items(items.into_iter(), locale)
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_date_test() {
+        assert_eq!(
+            format_date("2024-05-01", "long", "ja").unwrap(),
+            "2024年5月1日"
+        );
+        assert_eq!(
+            format_date("2024-05-01", "long", "en-US").unwrap(),
+            "May 1, 2024"
+        );
+        assert_eq!(
+            format_date("2024-05-01", "long", "de").unwrap(),
+            "1. Mai 2024"
+        );
+        assert_eq!(
+            format_date("2024-05-01", "%A, %-d %B", "fr").unwrap(),
+            "mercredi, 1 mai"
+        );
+        assert_eq!(
+            format_date("2024-05-01T19:30:00", "%a %H:%M", "ja").unwrap(),
+            "水 19:30"
+        );
+        assert_eq!(
+            format_date("2024-05-01", "%B %Y", "nl").unwrap(),
+            "mei 2024"
+        );
+        assert_eq!(
+            format_date("2024-05-01", "long", "pt-BR").unwrap(),
+            "1 de maio de 2024"
+        );
+        // Languages without built-in styles use the locale's date format.
+        assert_eq!(format_date("2024-05-01", "long", "nl").unwrap(), "01-05-24");
+        // Unsupported locales are in English.
+        assert_eq!(format_date("2024-05-01", "%b", "xx").unwrap(), "May");
+        assert!(format_date("2024-05-01", "%Q", "en").is_err());
+        assert!(format_date("May 1", "long", "en").is_err());
+    }
+}
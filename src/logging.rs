@@ -0,0 +1,220 @@
+//! Logging configured by `--log-level`, `--log-format` and `--log-file`, instead of `RUST_LOG`.
+
+use anyhow::{anyhow, Context as _, Error, Result};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::diagnostic::{Diagnostic, MessageFormat, Severity};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum LogFormat {
+    /// `env_logger`'s human-readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!(
+                "unknown log format: {s} (expected `pretty` or `json`)"
+            )),
+        }
+    }
+}
+
+/// Initialize the logger. Without `level`, `RUST_LOG` is used as before, which defaults to
+/// errors only, in both formats. Logs go to stderr, or are appended to `file`. With
+/// `MessageFormat::Json`, warnings and errors are printed on stdout as JSON diagnostics
+/// instead.
+pub fn init(
+    level: Option<log::LevelFilter>,
+    format: LogFormat,
+    file: Option<&Path>,
+    message_format: MessageFormat,
+) -> Result<()> {
+    let file = file
+        .map(|file| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)
+                .with_context(|| format!("can not open log file: {}", file.display()))
+        })
+        .transpose()?;
+    let mut builder = match level {
+        Some(level) => {
+            let mut builder = env_logger::Builder::new();
+            builder.filter_level(level);
+            builder
+        }
+        None => env_logger::Builder::from_default_env(),
+    };
+    let (logger, mut max_level): (Box<dyn log::Log>, _) = match format {
+        LogFormat::Pretty => {
+            if let Some(file) = file {
+                builder
+                    .target(env_logger::Target::Pipe(Box::new(file)))
+                    .write_style(env_logger::WriteStyle::Never);
+            }
+            let logger = builder.build();
+            let max_level = logger.filter();
+            (Box::new(logger), max_level)
+        }
+        LogFormat::Json => {
+            let writer: Box<dyn Write + Send> = match file {
+                Some(file) => Box::new(file),
+                None => Box::new(std::io::stderr()),
+            };
+            // `env_logger`'s logger is only used for its filter, so that `RUST_LOG`'s
+            // directives, such as `site=debug`, work in the same way.
+            let filter = builder.build();
+            let max_level = filter.filter();
+            (
+                Box::new(JsonLogger {
+                    filter,
+                    writer: Mutex::new(writer),
+                }),
+                max_level,
+            )
+        }
+    };
+    let diagnostics = message_format == MessageFormat::Json;
+    if diagnostics {
+        max_level = max_level.max(log::LevelFilter::Warn);
+    }
+    log::set_boxed_logger(Box::new(Logger {
+        logger,
+        diagnostics,
+    }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Passes records to `logger`, except for warnings and errors, which are printed as JSON
+/// diagnostics with `diagnostics`.
+struct Logger {
+    logger: Box<dyn log::Log>,
+    diagnostics: bool,
+}
+
+impl Logger {
+    fn is_diagnostic(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.diagnostics && metadata.level() <= log::Level::Warn
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.is_diagnostic(metadata) || self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.is_diagnostic(record.metadata()) {
+            println!("{}", diagnostic(record).json());
+        } else {
+            self.logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+fn diagnostic(record: &log::Record<'_>) -> Diagnostic {
+    Diagnostic {
+        severity: if record.level() == log::Level::Error {
+            Severity::Error
+        } else {
+            Severity::Warning
+        },
+        file: None,
+        line: None,
+        message: record.args().to_string(),
+    }
+}
+
+/// A logger which writes a JSON object per record which `filter` matches.
+struct JsonLogger {
+    filter: env_logger::Logger,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl log::Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.filter.matches(record) {
+            let line = json_line(record, &chrono::Local::now().to_rfc3339());
+            let _ = writeln!(self.writer.lock().unwrap(), "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.writer.lock().unwrap().flush();
+    }
+}
+
+fn json_line(record: &log::Record<'_>, timestamp: &str) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str().to_lowercase(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_line_test() {
+        assert_eq!(
+            json_line(
+                &log::Record::builder()
+                    .level(log::Level::Warn)
+                    .target("site::site")
+                    .args(format_args!("can not read: \"a.md\""))
+                    .build(),
+                "2024-01-02T03:04:05+09:00"
+            ),
+            r#"{"level":"warn","message":"can not read: \"a.md\"","target":"site::site","timestamp":"2024-01-02T03:04:05+09:00"}"#
+        );
+        assert!("yaml".parse::<LogFormat>().is_err());
+
+        // `RUST_LOG`'s directives work in JSON, too.
+        let logger = JsonLogger {
+            filter: env_logger::Builder::new()
+                .parse_filters("site=debug")
+                .build(),
+            writer: Mutex::new(Box::new(std::io::sink())),
+        };
+        let metadata = |level, target| log::Metadata::builder().level(level).target(target).build();
+        use log::Log as _;
+        assert!(logger.enabled(&metadata(log::Level::Debug, "site::site")));
+        assert!(!logger.enabled(&metadata(log::Level::Error, "ureq")));
+        assert_eq!(
+            diagnostic(
+                &log::Record::builder()
+                    .level(log::Level::Error)
+                    .args(format_args!("can not read"))
+                    .build()
+            )
+            .json(),
+            r#"{"severity":"error","file":null,"line":null,"message":"can not read"}"#
+        );
+    }
+}
@@ -1,13 +1,27 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use regex::Regex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use site::{Config, Result, Site};
+use site::daemon::{DaemonOptions, Interval};
+use site::diagnostic::{Diagnostic, MessageFormat, Severity};
+use site::import::ImportSource;
+use site::logging::LogFormat;
+use site::{ArticleFilter, BuildOptions, Config, DraftMode, ExportFormat, Result, Site};
 
 #[derive(Parser, Debug)]
+#[command(name = "site", version, about)]
 struct Cli {
     #[command(subcommand)]
     cmd: Command,
+    /// "off", "error", "warn", "info", "debug" or "trace" [default: `RUST_LOG`, or "error"]
+    #[structopt(long = "log-level", global = true)]
+    log_level: Option<log::LevelFilter>,
+    /// "pretty" or "json", which prints a JSON object per line
+    #[structopt(long = "log-format", global = true, default_value = "pretty")]
+    log_format: LogFormat,
+    /// Append logs to this file instead of stderr, e.g. for `site daemon`
+    #[structopt(long = "log-file", global = true)]
+    log_file: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
@@ -19,36 +33,602 @@ enum Command {
         config: Option<String>,
         #[structopt(long = "out-dir")]
         out_dir: String,
+        /// Only build markdowns whose path matches the regex. Can be given multiple times
         #[structopt(long = "article-regex")]
-        article_regex: Option<String>,
+        article_regex: Vec<Regex>,
+        /// Only render this markdown, e.g. `src/notes/foo.md`, and pages listing it, while
+        /// building everything else, such as copying files. An asset, such as an image, renders
+        /// the markdowns referencing it. Can be given multiple times
+        #[structopt(long = "only")]
+        only: Vec<PathBuf>,
+        /// [default: the profile's]
+        #[structopt(long = "drafts")]
+        drafts: Option<DraftMode>,
+        /// Also write a Gemini capsule into this directory
+        #[structopt(long = "gemini-out-dir")]
+        gemini_out_dir: Option<String>,
+        /// The build profile: "dev", "preview", "production" or one in `[profiles]` in the config,
+        /// which sets the defaults for drafts, future posts, minification, fingerprinting and
+        /// `base_url`, and controls `[[inject]]`
+        #[structopt(long = "profile", default_value = "dev")]
+        profile: String,
+        /// Override `base_url` in the config and the profile
+        #[structopt(long = "base-url")]
+        base_url: Option<String>,
+        /// The number of threads [default: `jobs` in the config, or the number of CPUs]
+        #[structopt(long = "jobs", short = 'j')]
+        jobs: Option<usize>,
+        /// Cache rendered markdown in this directory, which is reused across builds
+        #[structopt(long = "cache-dir")]
+        cache_dir: Option<String>,
+        /// Fail on problems which are warnings otherwise, such as duplicate titles or exceeded
+        /// `[budgets]`
+        #[structopt(long = "strict")]
+        strict: bool,
+        /// "human" or "json", which prints warnings and errors as JSON objects, one per line, on
+        /// stdout
+        #[structopt(long = "message-format", default_value = "human")]
+        message_format: MessageFormat,
+        /// Fetch `[remote]` sources again, instead of using the cached ones
+        #[structopt(long = "update-remotes")]
+        update_remotes: bool,
+        /// Build a full-text search index into this directory
+        #[cfg(feature = "search")]
+        #[structopt(long = "search-index-dir")]
+        search_index_dir: Option<String>,
+    },
+    /// Export published articles into a single file, such as an EPUB or a newsletter.
+    Export {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        #[structopt(long = "out-dir", default_value = ".")]
+        out_dir: String,
+        #[structopt(long = "format", default_value = "epub")]
+        format: ExportFormat,
+        /// The output file name, relative to `out-dir` [default: site.<ext>]
+        #[structopt(long = "output")]
+        output: Option<String>,
+        #[structopt(long = "tag")]
+        tag: Option<String>,
+        #[structopt(long = "series")]
+        series: Option<String>,
+        #[structopt(long = "kind")]
+        kind: Option<String>,
+        #[structopt(long = "since")]
+        since: Option<chrono::NaiveDate>,
+        #[structopt(long = "until")]
+        until: Option<chrono::NaiveDate>,
+        /// The number of threads [default: `jobs` in the config, or the number of CPUs]
+        #[structopt(long = "jobs", short = 'j')]
+        jobs: Option<usize>,
+    },
+    /// Print the template context of a markdown as JSON, for debugging templates.
+    Context {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        #[structopt(long = "drafts", default_value = "unlisted")]
+        drafts: DraftMode,
+        /// A markdown file in `src`
+        source_file: PathBuf,
+    },
+    /// Render a markdown into HTML, and print it, without building the whole site.
+    Render {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        #[structopt(long = "profile", default_value = "dev")]
+        profile: String,
+        /// Use this template instead, e.g. "article"
+        #[structopt(long = "template")]
+        template: Option<String>,
+        /// A markdown file in `src`
+        source_file: PathBuf,
+    },
+    /// Show which templates each markdown uses, and report unused templates.
+    Templates {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        /// Print only unused templates
+        #[structopt(long = "unused")]
+        unused: bool,
+    },
+    /// Report content analytics of the published articles: posts per year, average word counts,
+    /// tags, the longest gaps between posts, and the most linked domains.
+    Stats {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        /// The number of entries in each ranking
+        #[structopt(long = "top", default_value = "10")]
+        top: usize,
+        /// Print as JSON
+        #[structopt(long = "json")]
+        json: bool,
+        /// The build profile which decides which articles are published
+        #[structopt(long = "profile", default_value = "production")]
+        profile: String,
+    },
+    /// Search articles in the full-text search index.
+    #[cfg(feature = "search")]
+    Search(SearchArgs),
+    /// Send webmentions for links in published articles.
+    #[cfg(feature = "webmention")]
+    Webmention {
+        #[command(subcommand)]
+        cmd: WebmentionCommand,
+    },
+    /// Publish a built site.
+    Deploy {
+        #[command(subcommand)]
+        cmd: DeployCommand,
+    },
+    /// Pull the content repository, rebuild, and publish the site periodically, forever.
+    Daemon {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        /// A symlink to the latest build, which is replaced atomically. Builds are written into
+        /// `<out-dir>.builds`
+        #[structopt(long = "out-dir")]
+        out_dir: String,
+        /// How often to rebuild, e.g. "30s", "10m" or "1h"
+        #[structopt(long = "interval", default_value = "10m")]
+        interval: Interval,
+        #[structopt(long = "profile", default_value = "production")]
+        profile: String,
+        /// Don't run `git pull` in `root-dir` before each build
+        #[structopt(long = "no-pull")]
+        no_pull: bool,
+        /// Serve a read-only JSON API of published articles at this address, e.g.
+        /// "127.0.0.1:8081"
+        #[structopt(long = "api-addr")]
+        api_addr: Option<std::net::SocketAddr>,
+        #[structopt(long = "jobs", short = 'j')]
+        jobs: Option<usize>,
+    },
+    /// Convert a Hugo, Jekyll or Zola site, or a WordPress or Blogger export, into markdowns in
+    /// `src`.
+    Import {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        /// "hugo", "jekyll", "zola", "wordpress" or "blogger"
+        #[structopt(long = "from")]
+        from: ImportSource,
+        /// The root directory of the site to import, or the export file
+        path: PathBuf,
+    },
+    /// Synthesize a site, build it, and report the throughput of each phase.
+    Bench {
+        /// The number of articles
+        #[structopt(long = "articles", default_value = "1000")]
+        articles: usize,
+        /// The size of each article in bytes
+        #[structopt(long = "size", default_value = "10000")]
+        size: usize,
+        #[structopt(long = "jobs", short = 'j')]
+        jobs: Option<usize>,
+        /// Synthesize the site into this directory and keep it [default: a temporary directory]
+        #[structopt(long = "dir")]
+        dir: Option<String>,
+    },
+    /// Print a shell completion script, e.g. `site completions zsh > ~/.zfunc/_site`.
+    Completions { shell: clap_complete::Shell },
+    /// Print the man page.
+    Man {
+        /// Write man pages for all subcommands into this directory instead, e.g. `site.1`
+        /// and `site-build.1`
+        #[structopt(long = "out-dir")]
+        out_dir: Option<String>,
     },
 }
 
+#[cfg(feature = "search")]
+#[derive(Parser, Debug)]
+struct SearchArgs {
+    /// The index built by `site build --search-index-dir`
+    #[structopt(long = "index-dir")]
+    index_dir: String,
+    #[structopt(long = "limit", default_value = "10")]
+    limit: usize,
+    /// Print hits as JSON
+    #[structopt(long = "json")]
+    json: bool,
+    query: String,
+}
+
+#[cfg(feature = "webmention")]
+#[derive(Parser, Debug)]
+enum WebmentionCommand {
+    /// Send webmentions which have not been sent yet, and record them in the state file.
+    Send {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        /// Print webmentions to send, without sending them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// The build profile which decides which articles are published
+        #[structopt(long = "profile", default_value = "production")]
+        profile: String,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum DeployCommand {
+    /// Commit `out-dir` to the `[github_pages]` branch, such as `gh-pages`, and push it.
+    Github {
+        #[structopt(long = "root-dir", default_value = ".")]
+        root_dir: String,
+        #[structopt(long = "config")]
+        config: Option<String>,
+        #[structopt(long = "out-dir", required_unless_present = "workflow")]
+        out_dir: Option<String>,
+        #[structopt(long = "message", default_value = "Deploy site")]
+        message: String,
+        /// Commit without pushing
+        #[structopt(long = "no-push")]
+        no_push: bool,
+        /// Print a GitHub Actions workflow which deploys with GitHub Pages' artifacts instead
+        #[structopt(long = "workflow")]
+        workflow: bool,
+    },
+}
+
+fn read_config(root_dir: &Path, config: Option<&str>) -> Result<Config> {
+    let mut default_config = Config::read(root_dir.join("config.toml"))?;
+    if let Some(config) = config {
+        default_config.extend(&mut Config::read(config)?);
+    }
+    Ok(default_config)
+}
+
 fn main() -> Result<()> {
     let opt = Cli::parse();
-    env_logger::init();
-    match opt.cmd {
+    let message_format = match &opt.cmd {
+        Command::Build { message_format, .. } => *message_format,
+        _ => MessageFormat::Human,
+    };
+    site::logging::init(
+        opt.log_level,
+        opt.log_format,
+        opt.log_file.as_deref(),
+        message_format,
+    )?;
+    let result = run(opt.cmd);
+    if let (MessageFormat::Json, Err(e)) = (message_format, &result) {
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            file: None,
+            line: None,
+            message: format!("{e:#}"),
+        };
+        println!("{}", diagnostic.json());
+        std::process::exit(1);
+    }
+    result
+}
+
+fn run(cmd: Command) -> Result<()> {
+    match cmd {
         Command::Build {
             config,
             root_dir,
             out_dir,
             article_regex,
+            only,
+            drafts,
+            gemini_out_dir,
+            profile,
+            base_url,
+            jobs,
+            cache_dir,
+            strict,
+            message_format,
+            update_remotes,
+            #[cfg(feature = "search")]
+            search_index_dir,
         } => {
             let root_dir = PathBuf::from(root_dir);
-            let config = {
-                let mut default_config = Config::read(root_dir.join("config.toml"))?;
-                if let Some(config) = config.as_ref() {
-                    default_config.extend(&mut Config::read(config)?);
-                }
-                default_config
-            };
+            let config = read_config(&root_dir, config.as_deref())?;
+            let profile = BuildOptions::profile(&config, &profile)?;
             let app = Site::new(
                 config,
                 root_dir,
                 PathBuf::from(out_dir),
-                article_regex.map(|regex| Regex::new(&regex).expect("invalid regex")),
-            );
+                BuildOptions {
+                    article_regex,
+                    only,
+                    draft_mode: drafts.unwrap_or(profile.draft_mode),
+                    gemini_out_dir: gemini_out_dir.map(PathBuf::from),
+                    jobs,
+                    cache_dir: cache_dir.map(PathBuf::from),
+                    strict,
+                    message_format,
+                    update_remotes,
+                    #[cfg(feature = "search")]
+                    search_index_dir: search_index_dir.map(PathBuf::from),
+                    base_url: base_url.or(profile.base_url),
+                    ..profile
+                },
+            )?;
             app.build()
         }
+        Command::Export {
+            root_dir,
+            config,
+            out_dir,
+            format,
+            output,
+            tag,
+            series,
+            kind,
+            since,
+            until,
+            jobs,
+        } => {
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let app = Site::new(
+                config,
+                root_dir,
+                PathBuf::from(out_dir),
+                BuildOptions {
+                    jobs,
+                    ..BuildOptions::default()
+                },
+            )?;
+            let filter = ArticleFilter {
+                tag,
+                series,
+                kind,
+                since,
+                until,
+            };
+            let output = output.unwrap_or_else(|| format!("site.{}", format.extension()));
+            app.export(format, &filter, &output)
+        }
+        Command::Context {
+            root_dir,
+            config,
+            drafts,
+            source_file,
+        } => {
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let app = Site::new(
+                config,
+                root_dir,
+                PathBuf::new(),
+                BuildOptions {
+                    draft_mode: drafts,
+                    ..BuildOptions::default()
+                },
+            )?;
+            let context = app.context(&app.source_path(&source_file)?)?;
+            println!("{}", serde_json::to_string_pretty(&context)?);
+            Ok(())
+        }
+        Command::Render {
+            root_dir,
+            config,
+            profile,
+            template,
+            source_file,
+        } => {
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let options = BuildOptions::profile(&config, &profile)?;
+            let app = Site::new(config, root_dir, PathBuf::new(), options)?;
+            app.render(
+                &app.source_path(&source_file)?,
+                template.as_deref(),
+                std::io::stdout().lock(),
+            )
+        }
+        Command::Templates {
+            root_dir,
+            config,
+            unused,
+        } => {
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let app = Site::new(config, root_dir, PathBuf::new(), BuildOptions::default())?;
+            let usage = app.template_usage()?;
+            if !unused {
+                for (path, templates) in &usage.sources {
+                    let templates = templates.iter().cloned().collect::<Vec<_>>();
+                    println!("{}: {}", path.display(), templates.join(", "));
+                }
+                if !usage.unused.is_empty() {
+                    println!("\nUnused templates:");
+                }
+            }
+            for name in &usage.unused {
+                println!("{name}");
+            }
+            Ok(())
+        }
+        Command::Stats {
+            root_dir,
+            config,
+            top,
+            json,
+            profile,
+        } => {
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let options = BuildOptions::profile(&config, &profile)?;
+            let app = Site::new(config, root_dir, PathBuf::new(), options)?;
+            let stats = app.content_stats(top)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print!("{stats}");
+            }
+            Ok(())
+        }
+        #[cfg(feature = "search")]
+        Command::Search(SearchArgs {
+            index_dir,
+            limit,
+            json,
+            query,
+        }) => {
+            let hits = site::search::search(Path::new(&index_dir), &query, limit)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&hits)?);
+            } else {
+                for hit in hits {
+                    println!("{:.3}\t{}\t{}", hit.score, hit.url, hit.title);
+                }
+            }
+            Ok(())
+        }
+        #[cfg(feature = "webmention")]
+        Command::Webmention {
+            cmd:
+                WebmentionCommand::Send {
+                    root_dir,
+                    config,
+                    dry_run,
+                    profile,
+                },
+        } => {
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let options = BuildOptions::profile(&config, &profile)?;
+            let app = Site::new(config, root_dir, PathBuf::new(), options)?;
+            app.send_webmentions(dry_run)
+        }
+        Command::Deploy {
+            cmd:
+                DeployCommand::Github {
+                    root_dir,
+                    config,
+                    out_dir,
+                    message,
+                    no_push,
+                    workflow,
+                },
+        } => {
+            if workflow {
+                print!("{}", site::GithubPagesConfig::workflow());
+                return Ok(());
+            }
+            let root_dir = PathBuf::from(root_dir);
+            let config = read_config(&root_dir, config.as_deref())?;
+            let app = Site::new(
+                config,
+                root_dir,
+                PathBuf::from(out_dir.unwrap()),
+                BuildOptions::default(),
+            )?;
+            app.deploy_github(&message, !no_push)
+        }
+        Command::Daemon {
+            root_dir,
+            config,
+            out_dir,
+            interval,
+            profile,
+            no_pull,
+            api_addr,
+            jobs,
+        } => {
+            let options = DaemonOptions {
+                root_dir: PathBuf::from(root_dir),
+                out_dir: PathBuf::from(out_dir),
+                interval,
+                pull: !no_pull,
+                api_addr,
+            };
+            site::daemon::run(&options, |build_dir| {
+                // The config is read every time, since it may be updated by a pull.
+                let config = read_config(&options.root_dir, config.as_deref())?;
+                let profile = BuildOptions::profile(&config, &profile)?;
+                let app = Site::new(
+                    config,
+                    options.root_dir.clone(),
+                    build_dir.to_path_buf(),
+                    BuildOptions {
+                        jobs,
+                        update_remotes: true,
+                        ..profile
+                    },
+                )?;
+                app.build()?;
+                if options.api_addr.is_some() {
+                    app.published_articles()
+                } else {
+                    Ok(Vec::new())
+                }
+            })
+        }
+        Command::Import {
+            root_dir,
+            from,
+            path,
+        } => {
+            let written = site::import::import(from, &path, &Path::new(&root_dir).join("src"))?;
+            log::info!("Imported {} files", written.len());
+            Ok(())
+        }
+        Command::Bench {
+            articles,
+            size,
+            jobs,
+            dir,
+        } => {
+            let (root_dir, keep) = match dir {
+                Some(dir) => (PathBuf::from(dir), true),
+                None => (
+                    std::env::temp_dir().join(format!("site-bench-{}", std::process::id())),
+                    false,
+                ),
+            };
+            site::bench::synthesize(&root_dir, articles, size)?;
+            let app = Site::new(
+                Config::default(),
+                root_dir.clone(),
+                root_dir.join("out"),
+                BuildOptions {
+                    jobs,
+                    ..BuildOptions::default()
+                },
+            )?;
+            let phases = app.bench();
+            if !keep {
+                std::fs::remove_dir_all(&root_dir)?;
+            }
+            for phase in phases? {
+                println!("{phase}");
+            }
+            Ok(())
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "site", &mut std::io::stdout());
+            Ok(())
+        }
+        Command::Man { out_dir } => {
+            let command = Cli::command();
+            match out_dir {
+                Some(out_dir) => {
+                    std::fs::create_dir_all(&out_dir)?;
+                    clap_mangen::generate_to(command, out_dir)?;
+                }
+                None => clap_mangen::Man::new(command).render(&mut std::io::stdout())?,
+            }
+            Ok(())
+        }
     }
 }
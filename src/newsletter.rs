@@ -0,0 +1,140 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Inline styles for elements, since many email clients ignore `<style>`.
+const STYLES: &[(&str, &str)] = &[
+    ("h1", "font-size:1.6em;margin:1em 0 0.5em;"),
+    ("h2", "font-size:1.35em;margin:1em 0 0.5em;"),
+    ("h3", "font-size:1.15em;margin:1em 0 0.5em;"),
+    ("p", "margin:0 0 1em;line-height:1.6;"),
+    ("a", "color:#1a0dab;"),
+    (
+        "pre",
+        "background:#f6f8fa;padding:12px;overflow:auto;font-size:0.9em;",
+    ),
+    ("code", "font-family:monospace;"),
+    (
+        "blockquote",
+        "margin:0 0 1em;padding-left:1em;border-left:4px solid #ddd;color:#555;",
+    ),
+    ("img", "max-width:100%;height:auto;"),
+    ("table", "border-collapse:collapse;"),
+    ("th", "border:1px solid #ddd;padding:4px 8px;"),
+    ("td", "border:1px solid #ddd;padding:4px 8px;"),
+];
+
+/// Remove scripts, iframes and inline event handlers.
+pub fn strip_scripts(html: &str) -> String {
+    static ELEMENTS: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?is)<script\b.*?</script\s*>|<iframe\b.*?</iframe\s*>|<noscript\b.*?</noscript\s*>",
+        )
+        .unwrap()
+    });
+    static EVENT_HANDLERS: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"(?i)\s+on[a-z]+\s*=\s*("[^"]*"|'[^']*'|[^\s>]+)"#).unwrap());
+    let html = ELEMENTS.replace_all(html, "");
+    EVENT_HANDLERS.replace_all(&html, "").into_owned()
+}
+
+/// Make `href` and `src` absolute, resolving them against `base`, the absolute URL of the
+/// document, which must end with `/`.
+pub fn absolutize_urls(html: &str, base: &str) -> String {
+    static URL_ATTR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"\b(href|src)="([^"]*)""#).unwrap());
+    static ORIGIN: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*://[^/]*").unwrap());
+
+    let origin = ORIGIN.find(base).map(|m| m.as_str()).unwrap_or("");
+    URL_ATTR
+        .replace_all(html, |caps: &regex::Captures<'_>| {
+            let url = &caps[2];
+            let url = if url.contains(':') || url.starts_with("//") {
+                url.to_string()
+            } else if url.starts_with('/') {
+                format!("{origin}{url}")
+            } else {
+                format!("{base}{url}")
+            };
+            format!(r#"{}="{}""#, &caps[1], url)
+        })
+        .into_owned()
+}
+
+/// Add [`STYLES`] to elements which don't have a `style` attribute yet.
+pub fn inline_styles(html: &str) -> String {
+    static START_TAG: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"<([a-zA-Z][a-zA-Z0-9]*)\b([^>]*?)(/?)>").unwrap());
+
+    START_TAG
+        .replace_all(html, |caps: &regex::Captures<'_>| {
+            let name = caps[1].to_ascii_lowercase();
+            match STYLES.iter().find(|(tag, _)| *tag == name) {
+                Some((_, style)) if !caps[2].contains("style=") => {
+                    let self_closing = if caps[3].is_empty() { "" } else { " /" };
+                    format!(
+                        r#"<{}{} style="{}"{}>"#,
+                        &caps[1],
+                        caps[2].trim_end(),
+                        style,
+                        self_closing
+                    )
+                }
+                _ => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_scripts_test() {
+        assert_eq!(
+            strip_scripts(
+                r#"<p onclick="x()">a</p><script>alert(1)</script><SCRIPT src="a.js"></SCRIPT>b"#
+            ),
+            "<p>a</p>b"
+        );
+        assert_eq!(strip_scripts("<iframe src=\"x\">\n</iframe>c"), "c");
+    }
+
+    #[test]
+    fn absolutize_urls_test() {
+        let base = "https://example.com/blog/foo/";
+        assert_eq!(
+            absolutize_urls(r#"<a href="/a/">a</a>"#, base),
+            r#"<a href="https://example.com/a/">a</a>"#
+        );
+        assert_eq!(
+            absolutize_urls(r#"<img src="b.png">"#, base),
+            r#"<img src="https://example.com/blog/foo/b.png">"#
+        );
+        assert_eq!(
+            absolutize_urls(r##"<a href="#x">x</a>"##, base),
+            r##"<a href="https://example.com/blog/foo/#x">x</a>"##
+        );
+        assert_eq!(
+            absolutize_urls(r#"<a href="mailto:a@example.com">a</a>"#, base),
+            r#"<a href="mailto:a@example.com">a</a>"#
+        );
+        assert_eq!(
+            absolutize_urls(r#"<a href="https://example.org/">a</a>"#, base),
+            r#"<a href="https://example.org/">a</a>"#
+        );
+    }
+
+    #[test]
+    fn inline_styles_test() {
+        assert_eq!(
+            inline_styles(r#"<p>a</p><img src="x" />"#),
+            r#"<p style="margin:0 0 1em;line-height:1.6;">a</p><img src="x" style="max-width:100%;height:auto;" />"#
+        );
+        assert_eq!(
+            inline_styles(r#"<p style="color:red">a</p><span>b</span>"#),
+            r#"<p style="color:red">a</p><span>b</span>"#
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use crate::html::escape;
+use serde::{Deserialize, Serialize};
+
+/// `[[blogroll]]`: a site which I read, exposed as `blogroll` in templates.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Blog {
+    pub title: String,
+    /// The URL of the site.
+    pub url: String,
+    /// The URL of the RSS or Atom feed, if any.
+    pub feed: Option<String>,
+    pub description: Option<String>,
+    /// Blogs in the same category are grouped in OPML, e.g. "Rust".
+    pub category: Option<String>,
+}
+
+/// Build an OPML document of `blogs`, which feed readers can import. Blogs without a feed are
+/// exported as links.
+pub fn opml(title: &str, blogs: &[Blog]) -> String {
+    let mut body = String::new();
+    let mut categories = Vec::<&str>::new();
+    for blog in blogs {
+        match blog.category.as_deref() {
+            Some(category) if !categories.contains(&category) => categories.push(category),
+            Some(_) => {}
+            None => body.push_str(&outline(blog, "    ")),
+        }
+    }
+    for category in categories {
+        body.push_str(&format!(
+            "    <outline text=\"{0}\" title=\"{0}\">\n",
+            escape(category)
+        ));
+        for blog in blogs
+            .iter()
+            .filter(|blog| blog.category.as_deref() == Some(category))
+        {
+            body.push_str(&outline(blog, "      "));
+        }
+        body.push_str("    </outline>\n");
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>{}</title>
+  </head>
+  <body>
+{body}  </body>
+</opml>
+"#,
+        escape(title)
+    )
+}
+
+fn outline(blog: &Blog, indent: &str) -> String {
+    let mut attrs = format!("text=\"{0}\" title=\"{0}\"", escape(&blog.title));
+    match blog.feed.as_ref() {
+        Some(feed) => attrs.push_str(&format!(
+            " type=\"rss\" xmlUrl=\"{}\" htmlUrl=\"{}\"",
+            escape(feed),
+            escape(&blog.url)
+        )),
+        None => attrs.push_str(&format!(" type=\"link\" url=\"{}\"", escape(&blog.url))),
+    }
+    if let Some(description) = blog.description.as_ref() {
+        attrs.push_str(&format!(" description=\"{}\"", escape(description)));
+    }
+    format!("{indent}<outline {attrs}/>\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opml_test() {
+        let blogs = toml::from_str::<std::collections::BTreeMap<String, Vec<Blog>>>(
+            r#"
+[[blogroll]]
+title = "This Week in Rust"
+url = "https://this-week-in-rust.org/"
+feed = "https://this-week-in-rust.org/rss.xml"
+category = "Rust"
+
+[[blogroll]]
+title = "Tom & Jerry"
+url = "https://example.com/"
+description = "No feed"
+"#,
+        )
+        .unwrap()
+        .remove("blogroll")
+        .unwrap();
+        assert_eq!(
+            opml("My blogroll", &blogs),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>My blogroll</title>
+  </head>
+  <body>
+    <outline text="Tom &amp; Jerry" title="Tom &amp; Jerry" type="link" url="https://example.com/" description="No feed"/>
+    <outline text="Rust" title="Rust">
+      <outline text="This Week in Rust" title="This Week in Rust" type="rss" xmlUrl="https://this-week-in-rust.org/rss.xml" htmlUrl="https://this-week-in-rust.org/"/>
+    </outline>
+  </body>
+</opml>
+"#
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+use crate::site::DraftMode;
+
+/// `[profiles.NAME]`: defaults bundled by `--profile NAME`. Unset ones fall back to the built-in
+/// profile of the same name, if any.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub drafts: Option<DraftMode>,
+    /// Render articles and pages whose `date` is in the future.
+    pub future: Option<bool>,
+    pub minify: Option<bool>,
+    /// Add a content hash to the names of CSS and JavaScript files.
+    pub fingerprint: Option<bool>,
+    /// Overrides `base_url` in the config.
+    pub base_url: Option<String>,
+}
+
+impl ProfileConfig {
+    pub fn builtin(name: &str) -> Option<ProfileConfig> {
+        let (drafts, future, optimize) = match name {
+            "dev" => (DraftMode::Unlisted, true, false),
+            "preview" => (DraftMode::Publish, true, true),
+            "production" => (DraftMode::Skip, false, true),
+            _ => return None,
+        };
+        Some(ProfileConfig {
+            drafts: Some(drafts),
+            future: Some(future),
+            minify: Some(optimize),
+            fingerprint: Some(optimize),
+            base_url: None,
+        })
+    }
+
+    /// Fill unset values with `other`'s.
+    pub fn or(self, other: ProfileConfig) -> ProfileConfig {
+        ProfileConfig {
+            drafts: self.drafts.or(other.drafts),
+            future: self.future.or(other.future),
+            minify: self.minify.or(other.minify),
+            fingerprint: self.fingerprint.or(other.fingerprint),
+            base_url: self.base_url.or(other.base_url),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! `protected = true`: encrypt the content of a page at build time, with a stub which decrypts
+//! it in the browser with the Web Crypto API.
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::html;
+
+/// `[protected]`: the passphrase of protected pages.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProtectedConfig {
+    /// Usually given by an environment variable, such as `"${SITE_PASSWORD}"`, so that it's not
+    /// committed.
+    pub password: String,
+    /// PBKDF2-HMAC-SHA256 iterations to derive the key from `password`.
+    #[serde(default = "ProtectedConfig::default_iterations")]
+    pub iterations: u32,
+}
+
+/// Decrypts `data-ciphertext` of the parent element with the password in the form, and
+/// replaces the element with the plain HTML.
+const SCRIPT: &str = r#"<script>
+(() => {
+  const root = document.currentScript.parentElement;
+  const bytes = (s) => Uint8Array.from(atob(s), (c) => c.charCodeAt(0));
+  root.querySelector("form").addEventListener("submit", async (event) => {
+    event.preventDefault();
+    const input = root.querySelector("input");
+    const material = await crypto.subtle.importKey("raw", new TextEncoder().encode(input.value), "PBKDF2", false, ["deriveKey"]);
+    const key = await crypto.subtle.deriveKey(
+      { name: "PBKDF2", salt: bytes(root.dataset.salt), iterations: Number(root.dataset.iterations), hash: "SHA-256" },
+      material, { name: "AES-GCM", length: 256 }, false, ["decrypt"]);
+    try {
+      const plain = await crypto.subtle.decrypt({ name: "AES-GCM", iv: bytes(root.dataset.iv) }, key, bytes(root.dataset.ciphertext));
+      root.outerHTML = new TextDecoder().decode(plain);
+    } catch {
+      input.value = "";
+      input.setCustomValidity("Wrong password");
+      input.reportValidity();
+    }
+  });
+  root.querySelector("input").addEventListener("input", (event) => event.target.setCustomValidity(""));
+})();
+</script>"#;
+
+impl ProtectedConfig {
+    fn default_iterations() -> u32 {
+        600_000
+    }
+
+    /// Encrypt `content` with AES-256-GCM, and wrap it with the decrypting stub.
+    pub fn protect(&self, content: &str) -> Result<String> {
+        let encrypted = encrypt(content.as_bytes(), &self.password, self.iterations)?;
+        Ok(format!(
+            r#"<div class="site-protected" data-salt="{}" data-iv="{}" data-iterations="{}" data-ciphertext="{}">
+<form><input type="password" placeholder="Password" autocomplete="current-password" required> <button>Unlock</button></form>
+{SCRIPT}
+</div>
+"#,
+            encrypted.salt,
+            encrypted.iv,
+            self.iterations,
+            html::escape(&encrypted.ciphertext)
+        ))
+    }
+}
+
+/// Base64-encoded values.
+#[derive(Debug)]
+struct Encrypted {
+    salt: String,
+    iv: String,
+    /// The ciphertext followed by the tag, as the Web Crypto API expects.
+    ciphertext: String,
+}
+
+#[cfg(feature = "protected")]
+fn encrypt(plain: &[u8], password: &str, iterations: u32) -> Result<Encrypted> {
+    use base64::Engine as _;
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+    use ring::rand::{SecureRandom as _, SystemRandom};
+
+    let random = SystemRandom::new();
+    let mut salt = [0; 16];
+    let mut iv = [0; NONCE_LEN];
+    random
+        .fill(&mut salt)
+        .and_then(|_| random.fill(&mut iv))
+        .map_err(|_| anyhow::anyhow!("can not generate random bytes"))?;
+    let mut key = [0; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        std::num::NonZeroU32::new(iterations)
+            .ok_or_else(|| anyhow::anyhow!("`iterations` must be positive"))?,
+        &salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).unwrap());
+    let mut data = plain.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut data)
+        .map_err(|_| anyhow::anyhow!("can not encrypt"))?;
+    let base64 = base64::engine::general_purpose::STANDARD;
+    Ok(Encrypted {
+        salt: base64.encode(salt),
+        iv: base64.encode(iv),
+        ciphertext: base64.encode(data),
+    })
+}
+
+#[cfg(not(feature = "protected"))]
+fn encrypt(_plain: &[u8], _password: &str, _iterations: u32) -> Result<Encrypted> {
+    Err(anyhow::anyhow!(
+        "site is built without the `protected` feature"
+    ))
+}
+
+#[cfg(all(test, feature = "protected"))]
+mod tests {
+    use super::*;
+    use base64::Engine as _;
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+
+    #[test]
+    fn encrypt_test() {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let encrypted = encrypt(b"<p>Family</p>", "secret", 1000).unwrap();
+        let salt = base64.decode(&encrypted.salt).unwrap();
+        let mut key = [0; 32];
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            std::num::NonZeroU32::new(1000).unwrap(),
+            &salt,
+            b"secret",
+            &mut key,
+        );
+        let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).unwrap());
+        let nonce =
+            Nonce::try_assume_unique_for_key(&base64.decode(&encrypted.iv).unwrap()).unwrap();
+        let mut data = base64.decode(&encrypted.ciphertext).unwrap();
+        let plain = key.open_in_place(nonce, Aad::empty(), &mut data).unwrap();
+        assert_eq!(plain, b"<p>Family</p>");
+
+        let config = ProtectedConfig {
+            password: "secret".to_string(),
+            iterations: 1000,
+        };
+        let html = config.protect("<p>Family</p>").unwrap();
+        assert!(html.starts_with(r#"<div class="site-protected" data-salt=""#));
+        assert!(!html.contains("Family"));
+    }
+}
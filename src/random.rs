@@ -0,0 +1,60 @@
+//! Deterministic shuffling for the `shuffle` and `sample` filters, so that "random" sections,
+//! such as related posts, are the same in every build of the same content.
+
+use sha2::{Digest, Sha256};
+
+/// A seed made from the hash of `parts`.
+pub fn seed<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // Separate parts, so that ["ab", "c"] and ["a", "bc"] differ.
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap())
+}
+
+/// SplitMix64, which is enough for shuffling, and stable across platforms and versions.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Shuffle `items` with the Fisher-Yates shuffle.
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64(seed);
+    for i in (1..items.len()).rev() {
+        let j = (rng.next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shuffle_test() {
+        let shuffled = |seed| {
+            let mut items = (0..10).collect::<Vec<_>>();
+            shuffle(&mut items, seed);
+            items
+        };
+        assert_eq!(shuffled(1), shuffled(1));
+        assert_ne!(shuffled(1), shuffled(2));
+        let mut sorted = shuffled(1);
+        sorted.sort();
+        assert_eq!(sorted, (0..10).collect::<Vec<_>>());
+        assert_ne!(
+            seed([b"ab".as_slice(), b"c"]),
+            seed([b"a".as_slice(), b"bc"])
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! `redirects.toml`: a mapping of old paths to new ones for bulk migrations, written as redirect
+//! stub pages and host-specific redirect files.
+
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use crate::diagnostic::Diagnostic;
+use crate::html;
+use crate::text;
+
+pub const FILE: &str = "redirects.toml";
+
+/// Marks stub pages, which are overwritten, unlike other outputs.
+const STUB_MARK: &str = "<!-- redirects.toml -->";
+
+/// Host-specific redirect files, in addition to the stub pages.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectFormat {
+    /// `_redirects`, for Netlify and Cloudflare Pages.
+    Redirects,
+    /// `redirects.nginx.conf`, to be included in a `server` block.
+    Nginx,
+}
+
+impl RedirectFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            RedirectFormat::Redirects => "_redirects",
+            RedirectFormat::Nginx => "redirects.nginx.conf",
+        }
+    }
+}
+
+/// Old paths => new paths or URLs, both root-relative, such as "/2019/05/hello.html" =>
+/// "/notes/hello/".
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Redirects {
+    pub paths: BTreeMap<String, String>,
+    /// The content of the file, to locate problems.
+    source: String,
+}
+
+impl Redirects {
+    /// Read `path`, if it exists.
+    pub fn read(path: &Path) -> Result<Option<Redirects>> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let paths = toml::from_str::<BTreeMap<String, String>>(&source)
+            .with_context(|| format!("invalid {}", path.display()))?
+            .into_iter()
+            .map(|(from, to)| (root_relative(&from), root_relative(&to)))
+            .collect::<BTreeMap<_, _>>();
+        for (from, to) in &paths {
+            // They are written as they are into redirect files, where whitespace separates
+            // fields.
+            for p in [from, to] {
+                anyhow::ensure!(
+                    !p.contains(|c: char| c.is_whitespace() || c.is_control()),
+                    "invalid {}: whitespace in a path: {p:?}",
+                    path.display()
+                );
+            }
+            anyhow::ensure!(
+                !is_external(from)
+                    && Path::new(&text::percent_decode(from.trim_start_matches('/')))
+                        .components()
+                        .all(|c| matches!(c, Component::Normal(_))),
+                "invalid {}: an old path must be a path in the site, without `.` or `..`: {from}",
+                path.display()
+            );
+        }
+        Ok(Some(Redirects { paths, source }))
+    }
+
+    /// Problems of redirects against the output in `out_dir`: the old path is an existing
+    /// output, which is not overwritten, or the new path doesn't exist.
+    pub fn check(&self, out_dir: &Path) -> Vec<Diagnostic> {
+        let mut problems = Vec::new();
+        for (from, to) in &self.paths {
+            let problem = if is_output(&out_dir.join(output_file(from))) {
+                format!("{from} is not redirected, since it exists in the output")
+            } else if !is_external(to) && !out_dir.join(output_file(to)).exists() {
+                format!("{from} is redirected to {to}, which doesn't exist in the output")
+            } else {
+                continue;
+            };
+            problems.push(Diagnostic::warning(
+                Some(FILE.to_string()),
+                self.line(from),
+                problem,
+            ));
+        }
+        problems
+    }
+
+    /// The 1-based line of the key `from`, if it's found.
+    fn line(&self, from: &str) -> Option<usize> {
+        let from = from.trim_start_matches('/');
+        self.source
+            .lines()
+            .position(|line| {
+                line.trim_start()
+                    .trim_start_matches(['"', '\''])
+                    .trim_start_matches('/')
+                    .strip_prefix(from)
+                    .is_some_and(|rest| rest.starts_with(['"', '\'', ' ', '=']))
+            })
+            .map(|i| i + 1)
+    }
+
+    /// Write a stub page for each redirect whose old path is not an existing output, and the
+    /// redirect files in `formats`. `path_prefix` is prepended to root-relative paths.
+    pub fn write(
+        &self,
+        src_dir: &Path,
+        out_dir: &Path,
+        formats: &[RedirectFormat],
+        path_prefix: &str,
+    ) -> Result<()> {
+        let prefixed = |path: &str| {
+            if is_external(path) {
+                path.to_string()
+            } else {
+                format!("{path_prefix}{path}")
+            }
+        };
+        for (from, to) in &self.paths {
+            let out_file = out_dir.join(output_file(from));
+            if is_output(&out_file) {
+                continue;
+            }
+            std::fs::create_dir_all(out_file.parent().unwrap())?;
+            std::fs::write(&out_file, stub_html(&prefixed(to)))?;
+        }
+        for format in formats {
+            let mut s = String::new();
+            for (from, to) in &self.paths {
+                let (from, to) = (prefixed(from), prefixed(to));
+                s.push_str(&match format {
+                    RedirectFormat::Redirects => format!("{from} {to} 301\n"),
+                    RedirectFormat::Nginx => format!("location = {from} {{ return 301 {to}; }}\n"),
+                });
+            }
+            let out_file = out_dir.join(format.file_name());
+            log::info!("{:32} => {}", format.file_name(), out_file.display());
+            crate::site::write_generated(src_dir, out_dir, format.file_name(), &s)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `file` exists, and is not a stub page of a previous build.
+fn is_output(file: &Path) -> bool {
+    file.exists()
+        && !std::fs::read_to_string(file).is_ok_and(|s| s.lines().nth(1) == Some(STUB_MARK))
+}
+
+fn is_external(path: &str) -> bool {
+    path.contains("://")
+}
+
+fn root_relative(path: &str) -> String {
+    if is_external(path) || path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{path}")
+    }
+}
+
+/// The output file of a root-relative path, such as "old/index.html" for "/old/" or "/old".
+fn output_file(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    if path.is_empty() || path.ends_with('/') || Path::new(path).extension().is_some() {
+        crate::site::url_to_filename(path)
+    } else {
+        crate::site::url_to_filename(&format!("{path}/"))
+    }
+}
+
+/// A page which redirects to `to` without JavaScript, for hosts without redirect files.
+fn stub_html(to: &str) -> String {
+    let to = html::escape(to);
+    format!(
+        r#"<!DOCTYPE html>
+{STUB_MARK}
+<meta charset="utf-8">
+<title>Redirecting to {to}</title>
+<link rel="canonical" href="{to}">
+<meta name="robots" content="noindex">
+<meta http-equiv="refresh" content="0; url={to}">
+<p>Moved to <a href="{to}">{to}</a>.</p>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects_test() {
+        let dir = crate::testing::TempDir::new("redirect");
+        let out_dir = dir.join("out");
+        std::fs::create_dir_all(out_dir.join("notes/hello")).unwrap();
+        std::fs::write(out_dir.join("notes/hello/index.html"), "hello").unwrap();
+        std::fs::write(out_dir.join("about.html"), "about").unwrap();
+        std::fs::write(
+            dir.join(FILE),
+            r#""2019/05/hello.html" = "/notes/hello/"
+"/about.html" = "/about/"
+"/old/" = "/missing/"
+"/feed" = "https://example.com/feed.xml"
+"#,
+        )
+        .unwrap();
+        let redirects = Redirects::read(&dir.join(FILE)).unwrap().unwrap();
+        assert_eq!(redirects.paths["/2019/05/hello.html"], "/notes/hello/");
+        let problems = redirects
+            .check(&out_dir)
+            .iter()
+            .map(Diagnostic::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                "redirects.toml:2: /about.html is not redirected, since it exists in the output",
+                "redirects.toml:3: /old/ is redirected to /missing/, which doesn't exist in the output",
+            ]
+        );
+
+        // The user's own rules come first.
+        let src_dir = dir.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("_redirects"), "/home / 302").unwrap();
+        redirects
+            .write(
+                &src_dir,
+                &out_dir,
+                &[RedirectFormat::Redirects, RedirectFormat::Nginx],
+                "/blog",
+            )
+            .unwrap();
+        let read = |file: &str| std::fs::read_to_string(out_dir.join(file)).unwrap();
+        assert!(read("2019/05/hello.html").contains(r#"url=/blog/notes/hello/""#));
+        assert!(read("feed/index.html").contains(r#"url=https://example.com/feed.xml""#));
+        assert_eq!(read("about.html"), "about");
+        // Stubs of a previous build are not outputs.
+        assert_eq!(redirects.check(&out_dir).len(), 2);
+        assert_eq!(
+            read("_redirects").lines().take(2).collect::<Vec<_>>(),
+            [
+                "/home / 302",
+                "/blog/2019/05/hello.html /blog/notes/hello/ 301"
+            ]
+        );
+        assert_eq!(
+            read("redirects.nginx.conf").lines().next().unwrap(),
+            "location = /blog/2019/05/hello.html { return 301 /blog/notes/hello/; }"
+        );
+        assert!(Redirects::read(&dir.join("missing.toml"))
+            .unwrap()
+            .is_none());
+
+        for invalid in [
+            r#""../../outside.html" = "/""#,
+            r#""/a/%2E%2E/%2E%2E/outside.html" = "/""#,
+            r#""https://example.com/" = "/""#,
+            r#""/a b" = "/""#,
+            r#""/a" = "/b\n/c""#,
+        ] {
+            std::fs::write(dir.join(FILE), invalid).unwrap();
+            assert!(Redirects::read(&dir.join(FILE)).is_err(), "{invalid}");
+        }
+    }
+}
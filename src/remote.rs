@@ -0,0 +1,234 @@
+use anyhow::{anyhow, Context as _, Result};
+use serde::Deserialize;
+use std::path::{Component, Path};
+
+use crate::github_pages::git;
+
+/// A file which marks a directory in `src` as a copy of a remote source, so that it can be
+/// replaced. It's not copied into the output.
+pub const MARKER: &str = ".site-remote";
+
+/// `[remote.NAME]`: content fetched from a git repository or a tarball, and copied into `src`
+/// before a build, such as a page shared by several sites.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteSource {
+    /// A git repository URL.
+    pub git: Option<String>,
+    /// A branch, tag or commit of `git` [default: the default branch].
+    pub rev: Option<String>,
+    /// A URL of a `.tar.gz` archive. A single top-level directory in it is stripped.
+    pub url: Option<String>,
+    /// A directory in the source to copy [default: the whole source].
+    pub path: Option<String>,
+    /// The directory in `src` to copy into [default: the name].
+    pub dest: Option<String>,
+}
+
+impl RemoteSource {
+    fn location(&self) -> Result<&str> {
+        match (self.git.as_ref(), self.url.as_ref()) {
+            (Some(git), None) => Ok(git),
+            (None, Some(url)) => Ok(url),
+            _ => Err(anyhow!("either `git` or `url` must be given")),
+        }
+    }
+
+    /// Fetch the source into `cache_dir`, if it's not there yet or `update`, and replace
+    /// `src_dir/<dest>` with a copy of it. If fetching fails, the cached one is used, if any.
+    pub fn sync(&self, name: &str, root_dir: &Path, src_dir: &Path, update: bool) -> Result<()> {
+        let location = self.location()?;
+        let dest = self.dest.as_deref().unwrap_or(name);
+        anyhow::ensure!(
+            !dest.is_empty()
+                && Path::new(dest)
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_))),
+            "`dest` must be a relative path in src: {dest}"
+        );
+        let cache_dir = root_dir.join(".cache/remote").join(name);
+        if update || !cache_dir.exists() {
+            match self.fetch(&cache_dir) {
+                Ok(()) => log::info!("Fetched remote {name}: {location}"),
+                Err(e) if cache_dir.exists() => {
+                    log::warn!("can not fetch remote {name}, using the cached one: {e:#}")
+                }
+                Err(e) => return Err(e.context(format!("can not fetch remote {name}"))),
+            }
+        }
+
+        let source_dir = cache_dir.join(self.path.as_deref().unwrap_or(""));
+        anyhow::ensure!(
+            source_dir.is_dir(),
+            "`path` is not found in remote {name}: {}",
+            self.path.as_deref().unwrap_or("")
+        );
+        let target = src_dir.join(dest);
+        if target.exists() {
+            anyhow::ensure!(
+                target.join(MARKER).exists(),
+                "{} exists, but it's not a copy of remote {name}. Remove it, or set another `dest`",
+                target.display()
+            );
+            std::fs::remove_dir_all(&target)?;
+        }
+        copy_dir(&source_dir, &target)?;
+        std::fs::write(
+            target.join(MARKER),
+            format!("Copied from remote {name}: {location}. Changes here are overwritten.\n"),
+        )?;
+        log::info!("{:32} => {}", location, target.display());
+        Ok(())
+    }
+
+    fn fetch(&self, cache_dir: &Path) -> Result<()> {
+        if let Some(repo) = self.git.as_ref() {
+            if !cache_dir.join(".git").exists() {
+                std::fs::create_dir_all(cache_dir)?;
+                git(cache_dir, &["init", "--quiet"], &[])?;
+            }
+            let rev = self.rev.as_deref().unwrap_or("HEAD");
+            git(
+                cache_dir,
+                &["fetch", "--quiet", "--depth", "1", repo, rev],
+                &[],
+            )?;
+            git(
+                cache_dir,
+                &["checkout", "--quiet", "--force", "FETCH_HEAD"],
+                &[],
+            )?;
+            return Ok(());
+        }
+        // Extract into a temporary directory, so that the cached one is kept on failure.
+        let tmp_dir = cache_dir.with_extension("tmp");
+        if tmp_dir.exists() {
+            std::fs::remove_dir_all(&tmp_dir)?;
+        }
+        std::fs::create_dir_all(&tmp_dir)?;
+        let archive = tmp_dir.join("archive.tar.gz");
+        download(self.location()?, &archive)?;
+        let status = std::process::Command::new("tar")
+            .arg("-xzf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&tmp_dir)
+            .status()
+            .context("can not run tar")?;
+        anyhow::ensure!(status.success(), "tar failed: {status}");
+        std::fs::remove_file(&archive)?;
+        let entries = std::fs::read_dir(&tmp_dir)?.collect::<std::io::Result<Vec<_>>>()?;
+        let root = match entries.as_slice() {
+            [entry] if entry.file_type()?.is_dir() => entry.path(),
+            _ => tmp_dir.clone(),
+        };
+        if cache_dir.exists() {
+            std::fs::remove_dir_all(cache_dir)?;
+        }
+        std::fs::rename(&root, cache_dir)?;
+        if tmp_dir.exists() {
+            std::fs::remove_dir_all(&tmp_dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copy files in `from` into `to`, except for `.git`.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    let entries = walkdir::WalkDir::new(from)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git");
+    for entry in entries {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from)?);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry.file_type().is_file() {
+            std::fs::copy(entry.path(), &target).with_context(|| {
+                format!(
+                    "can not copy: {} => {}",
+                    entry.path().display(),
+                    target.display()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Download `url` into the file `dest`.
+#[cfg(feature = "remote")]
+pub(crate) fn download(url: &str, dest: &Path) -> Result<()> {
+    let response = ureq::get(url).call()?;
+    let mut file = std::fs::File::create(dest)?;
+    std::io::copy(&mut response.into_reader(), &mut file)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "remote"))]
+pub(crate) fn download(_url: &str, _dest: &Path) -> Result<()> {
+    Err(anyhow!("site is built without the `remote` feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_test() {
+        let dir = crate::testing::TempDir::new("remote");
+        let (repo, root_dir) = (dir.join("shared"), dir.join("site"));
+        let src_dir = root_dir.join("src");
+        std::fs::create_dir_all(repo.join("content")).unwrap();
+        std::fs::create_dir_all(&src_dir).unwrap();
+        let commit = |content: &str| {
+            std::fs::write(repo.join("content/about.md"), content).unwrap();
+            git(&repo, &["add", "."], &[]).unwrap();
+            git(
+                &repo,
+                &[
+                    "-c",
+                    "user.name=site",
+                    "-c",
+                    "user.email=site@example.com",
+                    "commit",
+                    "--quiet",
+                    "-m",
+                    "update",
+                ],
+                &[],
+            )
+            .unwrap();
+        };
+        git(&repo, &["init", "--quiet"], &[]).unwrap();
+        commit("# About\n");
+
+        let source = toml::from_str::<RemoteSource>(&format!(
+            "git = {:?}\npath = \"content\"\ndest = \"shared\"",
+            repo.display().to_string()
+        ))
+        .unwrap();
+        let read = || std::fs::read_to_string(src_dir.join("shared/about.md")).unwrap();
+        source.sync("shared", &root_dir, &src_dir, false).unwrap();
+        assert_eq!(read(), "# About\n");
+        assert!(src_dir.join("shared").join(MARKER).exists());
+
+        // The cached one is used until it's updated.
+        commit("# About me\n");
+        source.sync("shared", &root_dir, &src_dir, false).unwrap();
+        assert_eq!(read(), "# About\n");
+        source.sync("shared", &root_dir, &src_dir, true).unwrap();
+        assert_eq!(read(), "# About me\n");
+
+        std::fs::create_dir_all(src_dir.join("mine")).unwrap();
+        let source = RemoteSource {
+            dest: Some("mine".to_string()),
+            ..source
+        };
+        assert!(source
+            .sync("shared", &root_dir, &src_dir, false)
+            .unwrap_err()
+            .to_string()
+            .contains("it's not a copy of remote shared"));
+    }
+}
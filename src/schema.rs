@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// The edit distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The candidate closest to `name`, if it's close enough to be a typo.
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max = if name.chars().count() <= 5 { 1 } else { 2 };
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Append a suggestion to serde's "unknown field `x`, expected one of `y`, `z`" in `message`.
+pub fn suggest_field(message: String) -> String {
+    static UNKNOWN_FIELD: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"unknown field `([^`]*)`, expected (.*)").unwrap());
+    static NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`]*)`").unwrap());
+    let suggestion = UNKNOWN_FIELD.captures(&message).and_then(|caps| {
+        let candidates = NAME
+            .captures_iter(caps.get(2).unwrap().as_str())
+            .map(|caps| caps.get(1).unwrap().as_str())
+            .collect::<Vec<_>>();
+        did_you_mean(&caps[1], candidates).map(String::from)
+    });
+    match suggestion {
+        Some(suggestion) => format!("{}\ndid you mean `{suggestion}`?", message.trim_end()),
+        None => message,
+    }
+}
+
+/// The 1-based line where the top-level `key` is defined in `toml`, as `key = ...`, `[key]`,
+/// `[key.x]` or `[[key]]`.
+fn line_of(toml: &str, key: &str) -> Option<usize> {
+    toml.lines()
+        .position(|line| {
+            let line = line.trim_start().trim_start_matches('[').trim_start();
+            let line = line.strip_prefix(key).or_else(|| {
+                line.strip_prefix('"')
+                    .and_then(|line| line.strip_prefix(key))
+                    .and_then(|line| line.strip_prefix('"'))
+            });
+            line.is_some_and(|rest| rest.trim_start().starts_with(['=', ']', '.']))
+        })
+        .map(|i| i + 1)
+}
+
+/// A type name with an indefinite article, such as "an integer".
+fn with_article(type_name: &str) -> String {
+    if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        format!("an {type_name}")
+    } else {
+        format!("a {type_name}")
+    }
+}
+
+/// Check top-level keys in `table`, parsed from `toml`. A key other than `typed_keys` is a
+/// free-form value, which must not be a table. `known_values` are free-form values which are
+/// understood, such as `lang`, with their allowed types, such as `["string"]`, as named by
+/// `toml::Value::type_str`. A table whose key is close to a typed key, or to one of
+/// `known_values`, is rejected as a typo. A free-form value with such a key, which may be
+/// intended, such as `authors` for `author`, is returned as a warning.
+pub fn check_keys(
+    toml: &str,
+    table: &toml::Table,
+    typed_keys: &[&str],
+    known_values: &[(&str, &[&str])],
+) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    for (key, value) in table {
+        let at = line_of(toml, key).map_or(String::new(), |line| format!("line {line}: "));
+        if typed_keys.contains(&key.as_str()) {
+            continue;
+        }
+        if let Some((_, types)) = known_values.iter().find(|(name, _)| name == key) {
+            anyhow::ensure!(
+                types.contains(&value.type_str()),
+                "{at}`{key}` must be {}, but it's {}",
+                types
+                    .iter()
+                    .map(|t| with_article(t))
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                with_article(value.type_str())
+            );
+            continue;
+        }
+        let suggestion = did_you_mean(
+            key,
+            typed_keys
+                .iter()
+                .copied()
+                .chain(known_values.iter().map(|(name, _)| *name)),
+        );
+        match (value, suggestion) {
+            (toml::Value::Table(_), Some(suggestion)) => {
+                return Err(anyhow!(
+                    "{at}unknown key `{key}`, did you mean `{suggestion}`?"
+                ))
+            }
+            (_, Some(suggestion)) => warnings.push(format!(
+                "{at}`{key}` is a free-form value. Did you mean `{suggestion}`?"
+            )),
+            (toml::Value::Table(_), None) => {
+                return Err(anyhow!(
+                    "{at}unknown key `{key}`: a table can't be a free-form value. Use `[params]` for nested values"
+                ))
+            }
+            (_, None) => {}
+        }
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_keys_test() {
+        assert_eq!(edit_distance("favicom", "favicon"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("jobs", "sort"), 3);
+
+        let check = |toml: &str| {
+            let table = toml::from_str::<toml::Table>(toml).unwrap();
+            check_keys(
+                toml,
+                &table,
+                &["favicon", "path_prefix", "jobs"],
+                &[
+                    ("base_url", &["string"]),
+                    ("params", &["table"]),
+                    ("pdf_command", &["string", "array"]),
+                ],
+            )
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(check("title = \"x\"\nmy_key = \"y\""), Ok(vec![]));
+        assert_eq!(
+            check("title = \"x\"\n\n[favicom]\nsource = \"a\""),
+            Err("line 3: unknown key `favicom`, did you mean `favicon`?".to_string())
+        );
+        assert_eq!(
+            check("path_prefx = \"/blog\""),
+            Ok(vec![
+                "line 1: `path_prefx` is a free-form value. Did you mean `path_prefix`?"
+                    .to_string()
+            ])
+        );
+        assert_eq!(
+            check("title = \"x\"\nbase_ur = \"https://example.com\""),
+            Ok(vec![
+                "line 2: `base_ur` is a free-form value. Did you mean `base_url`?".to_string()
+            ])
+        );
+        assert_eq!(
+            check("title = \"x\"\nyear = 2024\nsocial = [\"a\"]"),
+            Ok(vec![])
+        );
+        assert_eq!(
+            check("title = \"x\"\n\n[social]\nmastodon = \"a\""),
+            Err(
+                "line 3: unknown key `social`: a table can't be a free-form value. Use `[params]` for nested values"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(
+                "base_url = 1
+"
+            ),
+            Err("line 1: `base_url` must be a string, but it's an integer".to_string())
+        );
+        assert_eq!(
+            check("pdf_command = [\"a\"]\n\n[params]\nx = 1"),
+            Ok(vec![])
+        );
+        assert_eq!(
+            check("params = \"x\""),
+            Err("line 1: `params` must be a table, but it's a string".to_string())
+        );
+
+        assert_eq!(
+            suggest_field(
+                "unknown field `endpont`, expected one of `endpoint`, `pingback`, `state_file`\n"
+                    .to_string()
+            ),
+            "unknown field `endpont`, expected one of `endpoint`, `pingback`, `state_file`\ndid you mean `endpoint`?"
+        );
+    }
+}
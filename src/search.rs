@@ -0,0 +1,157 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexWriter, TantivyDocument};
+
+/// A document to be indexed.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub title: String,
+    pub url: String,
+    pub date: Option<chrono::NaiveDate>,
+    /// Plain text.
+    pub body: String,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Hit {
+    pub title: String,
+    pub url: String,
+    pub date: Option<chrono::NaiveDate>,
+    pub score: f32,
+}
+
+struct Fields {
+    title: Field,
+    url: Field,
+    date: Field,
+    body: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Fields) {
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            title: builder.add_text_field("title", TEXT | STORED),
+            url: builder.add_text_field("url", STRING | STORED),
+            date: builder.add_text_field("date", STRING | STORED),
+            body: builder.add_text_field("body", TEXT),
+        };
+        (builder.build(), fields)
+    }
+
+    fn from_index(index: &Index) -> Result<Fields> {
+        let schema = index.schema();
+        Ok(Fields {
+            title: schema.get_field("title")?,
+            url: schema.get_field("url")?,
+            date: schema.get_field("date")?,
+            body: schema.get_field("body")?,
+        })
+    }
+}
+
+/// Build an index from scratch in `index_dir`, replacing the existing one. `index_dir` is
+/// only replaced if it's empty or an index, so that a wrong path doesn't delete other files.
+pub fn build_index(index_dir: &Path, documents: &[Document]) -> Result<()> {
+    if index_dir.exists() {
+        anyhow::ensure!(
+            index_dir.join("meta.json").is_file() || index_dir.read_dir()?.next().is_none(),
+            "{} is neither empty nor a search index",
+            index_dir.display()
+        );
+        std::fs::remove_dir_all(index_dir)?;
+    }
+    std::fs::create_dir_all(index_dir)?;
+    let (schema, fields) = Fields::schema();
+    let index = Index::create_in_dir(index_dir, schema)?;
+    let mut writer: IndexWriter = index.writer(50_000_000)?;
+    for d in documents {
+        writer.add_document(doc!(
+            fields.title => d.title.as_str(),
+            fields.url => d.url.as_str(),
+            fields.date => d.date.map(|date| date.to_string()).unwrap_or_default(),
+            fields.body => d.body.as_str(),
+        ))?;
+    }
+    writer.commit()?;
+    log::info!(
+        "Search index: {} documents => {}",
+        documents.len(),
+        index_dir.display()
+    );
+    Ok(())
+}
+
+pub fn search(index_dir: &Path, query: &str, limit: usize) -> Result<Vec<Hit>> {
+    let index = Index::open_in_dir(index_dir)?;
+    let fields = Fields::from_index(&index)?;
+    let searcher = index.reader()?.searcher();
+    let query =
+        QueryParser::for_index(&index, vec![fields.title, fields.body]).parse_query(query)?;
+    searcher
+        .search(&query, &TopDocs::with_limit(limit))?
+        .into_iter()
+        .map(|(score, address)| -> Result<Hit> {
+            let doc = searcher.doc::<TantivyDocument>(address)?;
+            let get = |field| {
+                doc.get_first(field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            Ok(Hit {
+                title: get(fields.title),
+                url: get(fields.url),
+                date: get(fields.date).parse().ok(),
+                score,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_test() {
+        let dir = crate::testing::TempDir::new("search");
+        let documents = vec![
+            Document {
+                title: "Hello Rust".to_string(),
+                url: "hello/".to_string(),
+                date: Some("2024-05-01".parse().unwrap()),
+                body: "Ownership and borrowing.".to_string(),
+            },
+            Document {
+                title: "Gardening".to_string(),
+                url: "garden/".to_string(),
+                date: None,
+                body: "Tomatoes need sun.".to_string(),
+            },
+        ];
+        build_index(&dir, &documents).unwrap();
+
+        let hits = search(&dir, "borrowing", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].title, "Hello Rust");
+        assert_eq!(hits[0].url, "hello/");
+        assert_eq!(hits[0].date, Some("2024-05-01".parse().unwrap()));
+
+        let hits = search(&dir, "tomatoes", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].date, None);
+
+        // An existing index is replaced, but not other files.
+        build_index(&dir, &documents).unwrap();
+        let other = dir.join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join("a.md"), "a").unwrap();
+        assert!(build_index(&other, &documents).is_err());
+        assert!(other.join("a.md").exists());
+    }
+}
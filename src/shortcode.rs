@@ -0,0 +1,213 @@
+use crate::html::escape;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+/// How to embed a third-party video, such as a YouTube video.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbedMode {
+    /// A placeholder which loads the player only when it's clicked, so that visitors don't
+    /// load anything from a third party until then.
+    #[default]
+    Facade,
+    /// The player's `<iframe>`, loaded lazily.
+    Iframe,
+    /// A plain link to the video.
+    Link,
+}
+
+/// Expand shortcodes, such as `{{< youtube dQw4w9WgXcQ >}}`, each of which must be on its own
+/// line, into HTML. Shortcodes in fenced code blocks are kept as is.
+pub fn expand(markdown: &str, mode: EmbedMode) -> String {
+    static SHORTCODE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\s*\{\{<\s*([a-z]+)(.*?)\s*>\}\}\s*$").unwrap());
+
+    if !markdown.contains("{{<") {
+        return markdown.to_string();
+    }
+    let mut out = String::with_capacity(markdown.len());
+    let mut fence = None::<String>;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(f) = fence.as_ref() {
+            if trimmed.starts_with(f.as_str()) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(trimmed[..3].to_string());
+        } else if let Some(caps) = SHORTCODE.captures(line.trim_end()) {
+            match render(&caps[1], &parse_args(&caps[2]), mode) {
+                Some(html) => {
+                    out.push_str(&html);
+                    out.push_str("\n\n");
+                    continue;
+                }
+                None => log::warn!("unknown shortcode or missing arguments: {}", line.trim()),
+            }
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// Parse `a "b c" key=value key="d e"`, where positional arguments are keyed by their index,
+/// such as "0".
+pub fn parse_args(s: &str) -> BTreeMap<String, String> {
+    static ARG: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?:([a-z_]+)=)?(?:"([^"]*)"|'([^']*)'|([^\s"']+))"#).unwrap()
+    });
+    let mut args = BTreeMap::new();
+    let mut index = 0;
+    for caps in ARG.captures_iter(s) {
+        let value = caps
+            .get(2)
+            .or(caps.get(3))
+            .or(caps.get(4))
+            .unwrap()
+            .as_str()
+            .to_string();
+        let key = match caps.get(1) {
+            Some(key) => key.as_str().to_string(),
+            None => {
+                index += 1;
+                (index - 1).to_string()
+            }
+        };
+        args.insert(key, value);
+    }
+    args
+}
+
+fn render(name: &str, args: &BTreeMap<String, String>, mode: EmbedMode) -> Option<String> {
+    let arg = |key: &str, index: &str| args.get(key).or(args.get(index)).map(String::as_str);
+    let attr = |name: &str| {
+        args.get(name)
+            .map(|value| format!(" {name}=\"{}\"", escape(value)))
+            .unwrap_or_default()
+    };
+    match name {
+        "youtube" | "vimeo" => {
+            let id = arg("id", "0")?;
+            let title = arg("title", "1").unwrap_or("Video");
+            let (player, watch) = if name == "youtube" {
+                (
+                    format!("https://www.youtube-nocookie.com/embed/{id}"),
+                    format!("https://www.youtube.com/watch?v={id}"),
+                )
+            } else {
+                (
+                    format!("https://player.vimeo.com/video/{id}?dnt=1"),
+                    format!("https://vimeo.com/{id}"),
+                )
+            };
+            Some(embed(
+                name,
+                &player,
+                &watch,
+                title,
+                args.get("poster"),
+                mode,
+            ))
+        }
+        "video" => Some(format!(
+            r#"<video controls preload="metadata"{}{}{}></video>"#,
+            attr("src"),
+            attr("poster"),
+            attr("title")
+        ))
+        .filter(|_| args.contains_key("src")),
+        "audio" => Some(format!(
+            r#"<audio controls preload="metadata"{}{}></audio>"#,
+            attr("src"),
+            attr("title")
+        ))
+        .filter(|_| args.contains_key("src")),
+        _ => None,
+    }
+}
+
+fn embed(
+    name: &str,
+    player: &str,
+    watch: &str,
+    title: &str,
+    poster: Option<&String>,
+    mode: EmbedMode,
+) -> String {
+    let iframe = |extra: &str| {
+        format!(
+            r#"<div class="embed embed-{name}"><iframe src="{}" title="{}"{extra} loading="lazy" allow="autoplay; encrypted-media; picture-in-picture; fullscreen" allowfullscreen></iframe></div>"#,
+            escape(player),
+            escape(title)
+        )
+    };
+    match mode {
+        EmbedMode::Facade => {
+            // The player is loaded in place of `srcdoc` when the link is clicked.
+            let autoplay = if player.contains('?') { '&' } else { '?' };
+            let background = poster
+                .map(|poster| format!("background-image:url('{}');", escape(poster)))
+                .unwrap_or_default();
+            let srcdoc = format!(
+                r#"<style>*{{margin:0;padding:0;overflow:hidden}}html,body{{height:100%}}a{{display:flex;align-items:center;justify-content:center;height:100%;background:#000 center/cover no-repeat;color:#fff;font:1.5em sans-serif;text-decoration:none}}</style><a href="{}" style="{background}">&#9654; {}</a>"#,
+                escape(&format!("{player}{autoplay}autoplay=1")),
+                escape(title)
+            );
+            iframe(&format!(" srcdoc=\"{}\"", escape(&srcdoc)))
+        }
+        EmbedMode::Iframe => iframe(""),
+        EmbedMode::Link => format!(
+            r#"<p class="embed embed-{name}"><a href="{}">{}</a></p>"#,
+            escape(watch),
+            escape(title)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_test() {
+        let markdown = r#"Hello
+
+{{< youtube dQw4w9WgXcQ "Never & ever" >}}
+{{< vimeo id=76979871 >}}
+{{< audio src="/episodes/1.mp3" >}}
+
+```
+{{< youtube abc >}}
+```
+{{< unknown >}}
+"#;
+        assert_eq!(
+            expand(markdown, EmbedMode::Link),
+            r#"Hello
+
+<p class="embed embed-youtube"><a href="https://www.youtube.com/watch?v=dQw4w9WgXcQ">Never &amp; ever</a></p>
+
+<p class="embed embed-vimeo"><a href="https://vimeo.com/76979871">Video</a></p>
+
+<audio controls preload="metadata" src="/episodes/1.mp3"></audio>
+
+
+```
+{{< youtube abc >}}
+```
+{{< unknown >}}
+"#
+        );
+
+        let html = expand("{{< youtube abc >}}", EmbedMode::Iframe);
+        assert!(html.starts_with(r#"<div class="embed embed-youtube"><iframe src="https://www.youtube-nocookie.com/embed/abc" title="Video" loading="lazy""#));
+
+        let html = expand("{{< vimeo 123 poster=/img/p.jpg >}}", EmbedMode::Facade);
+        assert!(html.contains(r#" srcdoc="&lt;style&gt;"#));
+        assert!(html.contains(
+            "&lt;a href=&quot;https://player.vimeo.com/video/123?dnt=1&amp;amp;autoplay=1&quot; style=&quot;background-image:url(&#39;/img/p.jpg&#39;);&quot;&gt;"
+        ));
+    }
+}
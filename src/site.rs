@@ -1,18 +1,56 @@
 use anyhow::Context as _;
 pub use anyhow::Result;
 use anyhow::{anyhow, Error};
+use base64::Engine as _;
 use chrono::Datelike;
-use minijinja::{context, path_loader, Environment, Value};
+use minijinja::value::Kwargs;
+use minijinja::{context, path_loader, AutoEscape, Environment, State, Value};
 use rayon::prelude::*;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
 
+use crate::abbr::{self, Abbreviations};
+use crate::bench;
+use crate::budget::BudgetConfig;
+use crate::cache::RenderCache;
+use crate::content_hash::{Change, HashMode, HashState};
+use crate::csp;
+use crate::diagnostic::{Diagnostic, MessageFormat, Severity};
+use crate::epub;
+use crate::favicon::FaviconConfig;
+use crate::fediverse::FediverseConfig;
+use crate::gemtext;
+use crate::github_pages::GithubPagesConfig;
 use crate::html;
+use crate::ical::{self, EventTime};
+use crate::integrity;
+use crate::locale;
+use crate::newsletter;
+use crate::opml;
+use crate::profile::ProfileConfig;
+use crate::protect::ProtectedConfig;
+use crate::random;
+use crate::redirect::{self, RedirectFormat, Redirects};
+use crate::remote::{self, RemoteSource};
+use crate::schema;
+use crate::shortcode::{self, EmbedMode};
+use crate::slides;
+use crate::static_files::{HumansConfig, ManifestConfig, SecurityConfig};
+use crate::stats::{self, ContentStats};
+use crate::templates::TemplateGraph;
+use crate::terminology::Terminology;
 use crate::text;
+use crate::toc;
+use crate::transclude;
+use crate::webmention::WebmentionConfig;
+use crate::xml;
 
 #[derive(PartialEq, Eq, Debug, Deserialize, Default)]
 struct Metadata {
@@ -21,10 +59,39 @@ struct Metadata {
     author: Option<String>,
     date: Option<chrono::NaiveDate>,
     update_date: Option<chrono::NaiveDate>,
+    unpublish_date: Option<chrono::NaiveDate>,
+    protected: Option<bool>,
     slug: Option<String>,
+    output: Option<String>,
+    output_ext: Option<String>,
     math: Option<bool>,
     draft: Option<bool>,
     template: Option<String>,
+    tags: Option<Vec<String>>,
+    series: Option<String>,
+    kind: Option<String>,
+    link: Option<String>,
+    slides: Option<bool>,
+    slides_pdf: Option<String>,
+    pdf: Option<bool>,
+    toc: Option<bool>,
+    markdown: Option<MarkdownOptions>,
+    description: Option<String>,
+    keywords: Option<Vec<String>>,
+    cover: Option<String>,
+    weight: Option<i64>,
+    comments: Option<bool>,
+    sort: Option<SortOrder>,
+    event_start: Option<EventTime>,
+    event_end: Option<EventTime>,
+    event_location: Option<String>,
+    event: Option<String>,
+    audio: Option<String>,
+    duration: Option<String>,
+    episode: Option<u32>,
+    season: Option<u32>,
+    lang: Option<String>,
+    query: Option<ArticleFilter>,
 }
 
 impl FromStr for Metadata {
@@ -35,6 +102,66 @@ impl FromStr for Metadata {
     }
 }
 
+/// Markdown extensions. Unspecified ones fall back to the site's config, then to the defaults.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct MarkdownOptions {
+    footnotes: Option<bool>,
+    strikethrough: Option<bool>,
+    tables: Option<bool>,
+    tasklists: Option<bool>,
+    smart_punctuation: Option<bool>,
+    heading_attributes: Option<bool>,
+    math: Option<bool>,
+    /// How `youtube` and `vimeo` shortcodes are embedded.
+    embeds: Option<EmbedMode>,
+    /// Prefix ids of headings and footnotes with the slug of the article.
+    id_prefix: Option<bool>,
+}
+
+impl MarkdownOptions {
+    /// `other` takes precedence.
+    fn merge(&self, other: &MarkdownOptions) -> MarkdownOptions {
+        MarkdownOptions {
+            footnotes: other.footnotes.or(self.footnotes),
+            strikethrough: other.strikethrough.or(self.strikethrough),
+            tables: other.tables.or(self.tables),
+            tasklists: other.tasklists.or(self.tasklists),
+            smart_punctuation: other.smart_punctuation.or(self.smart_punctuation),
+            heading_attributes: other.heading_attributes.or(self.heading_attributes),
+            math: other.math.or(self.math),
+            embeds: other.embeds.or(self.embeds),
+            id_prefix: other.id_prefix.or(self.id_prefix),
+        }
+    }
+
+    fn pulldown_cmark_options(&self) -> pulldown_cmark::Options {
+        use pulldown_cmark::Options;
+
+        let mut opts = Options::empty();
+        for (enabled, default, opt) in [
+            (self.footnotes, true, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, true, Options::ENABLE_STRIKETHROUGH),
+            (self.tables, true, Options::ENABLE_TABLES),
+            (self.tasklists, true, Options::ENABLE_TASKLISTS),
+            (
+                self.smart_punctuation,
+                false,
+                Options::ENABLE_SMART_PUNCTUATION,
+            ),
+            (
+                self.heading_attributes,
+                false,
+                Options::ENABLE_HEADING_ATTRIBUTES,
+            ),
+            (self.math, false, Options::ENABLE_MATH),
+        ] {
+            opts.set(opt, enabled.unwrap_or(default));
+        }
+        opts
+    }
+}
+
 #[derive(Debug)]
 struct MarkdownFile {
     relative_path: PathBuf,
@@ -44,32 +171,80 @@ struct MarkdownFile {
 #[derive(PartialEq, Eq, Debug)]
 struct Markdown {
     metadata: Metadata,
-    content: String,
+    /// `None` if the file has only metadata.
+    content: Option<String>,
+    /// The line number of the first line of `content` in the file, starting from 1.
+    content_line: usize,
 }
 
 impl Markdown {
-    pub fn render(&self) -> String {
-        let mut opts = pulldown_cmark::Options::empty();
-        opts.insert(pulldown_cmark::Options::ENABLE_FOOTNOTES);
-        opts.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-        opts.insert(pulldown_cmark::Options::ENABLE_TABLES);
-        opts.insert(pulldown_cmark::Options::ENABLE_TASKLISTS);
-        let mut html = String::with_capacity(self.content.len() * 3 / 2);
-        let content = self.pre_process_content();
-        let p = pulldown_cmark::Parser::new_ext(&content, opts);
-        pulldown_cmark::html::push_html(&mut html, p);
-        Self::post_process_markdown_html(&html)
-    }
-
-    fn pre_process_content(&self) -> String {
-        let s = text::remove_newline_between_cjk(&self.content);
+    /// `assets` are the files which the markdown references, relative to `src`, which are a
+    /// part of the cache key. A slide deck's slides are wrapped into sections.
+    pub fn render(
+        &self,
+        options: &MarkdownOptions,
+        cache: Option<&RenderCache>,
+        assets: &[PathBuf],
+    ) -> String {
+        let options = options.merge(&self.metadata.markdown.unwrap_or_default());
+        let opts = options.pulldown_cmark_options();
+        let content = self.pre_process_content(&options);
+        let is_slides = self.metadata.slides.unwrap_or(false);
+        let render = || {
+            let mut html = String::with_capacity(content.len() * 3 / 2);
+            let p = pulldown_cmark::Parser::new_ext(&content, opts);
+            if is_slides {
+                pulldown_cmark::html::push_html(&mut html, slides::wrap(p).into_iter());
+            } else {
+                pulldown_cmark::html::push_html(&mut html, p);
+            }
+            Self::post_process_markdown_html(&html)
+        };
+        match cache {
+            Some(cache) => cache.get_or_insert_with(
+                &[
+                    &opts.bits().to_le_bytes(),
+                    &[u8::from(is_slides)],
+                    content.as_bytes(),
+                    &cache.asset_hash(assets),
+                ],
+                render,
+            ),
+            None => render(),
+        }
+    }
+
+    /// The number of slides of a slide deck, as `render` wraps them.
+    fn slide_count(&self, options: &MarkdownOptions) -> usize {
+        let options = options.merge(&self.metadata.markdown.unwrap_or_default());
+        let content = self.pre_process_content(&options);
+        slides::count(pulldown_cmark::Parser::new_ext(
+            &content,
+            options.pulldown_cmark_options(),
+        ))
+    }
+
+    /// Render a markdown without metadata, such as a string in data, in the same way as articles.
+    fn render_snippet(s: &str, options: &MarkdownOptions) -> String {
+        Markdown {
+            metadata: Metadata::default(),
+            content: Some(s.to_string()),
+            content_line: 1,
+        }
+        .render(options, None, &[])
+    }
+
+    fn pre_process_content(&self, options: &MarkdownOptions) -> String {
+        let (s, _) = abbr::split(self.content.as_deref().unwrap_or_default());
+        let s = text::remove_newline_between_cjk(&s);
         let s = text::remove_prettier_ignore_preceeding_code_block(&s);
-        text::remove_deno_fmt_ignore(&s)
+        let s = text::remove_deno_fmt_ignore(&s);
+        shortcode::expand(&s, options.embeds.unwrap_or_default())
     }
 
     fn post_process_markdown_html(html: &str) -> String {
-        let html = html::build_header_links(html);
-        html.to_string()
+        let mut ids = html::IdAllocator::from_html(html);
+        html::build_header_links(html, &mut ids)
     }
 }
 
@@ -79,48 +254,269 @@ impl FromStr for Markdown {
     fn from_str(s: &str) -> Result<Markdown> {
         // Skip the comment at the beginning. Emacs may use the first line for buffer-local variables.
         // e.g. <!-- -*- apheleia-formatters: prettier -*- -->
-        static COMMENT_LINES: LazyLock<Regex> =
-            LazyLock::new(|| Regex::new(r"^<!--.*-->\n+").unwrap());
+        static COMMENT_LINE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"^<!--.*-->$").unwrap());
+
+        static TITLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^# +(.+?) *$").unwrap());
+
+        // Ignore comments, such as <!-- prettier-ignore -->, in metadata.
+        static METADATA_COMMENT: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"<!--.*|-->.*").unwrap());
+
+        // `key = value`, `[table]` or `# comment`.
+        static TOML_LINE: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r#"^\s*(([A-Za-z0-9_.-]+|"[^"]*"|'[^']*')\s*=|\[|#)"#).unwrap()
+        });
+
+        let s = text::normalize_line_endings(s);
+        let lines = s.split('\n').collect::<Vec<_>>();
+        let skip_blank_lines = |mut i: usize| {
+            while i < lines.len() && lines[i].is_empty() {
+                i += 1;
+            }
+            i
+        };
 
-        static TITLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^# +(.+?) *\n+").unwrap());
+        let mut i = 0;
+        if COMMENT_LINE.is_match(lines[0]) {
+            i = skip_blank_lines(1);
+        }
+        // If the first line starts with "#", treat it as a title.
+        let title = TITLE
+            .captures(lines.get(i).unwrap_or(&""))
+            .map(|cap| (i, cap[1].to_string()));
+        if title.is_some() {
+            i = skip_blank_lines(i + 1);
+        }
 
-        let s = COMMENT_LINES.replace_all(s, "");
+        // Metadata is either fenced by "<!--" and "-->", or continues until a blank line or a line
+        // which doesn't look like TOML. Other lines are kept as blank lines, so that line numbers
+        // in metadata match the ones in the file.
+        let mut metadata = lines[..i].iter().map(|_| "").collect::<Vec<_>>();
+        let mut fence_start = None;
+        // The depth of brackets, for arrays and inline tables spanning lines.
+        let mut depth = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            if fence_start.is_some() {
+                if let Some((before, _)) = line.split_once("-->") {
+                    metadata.push(before);
+                    fence_start = None;
+                    i += 1;
+                    break;
+                }
+                metadata.push(line);
+            } else if line.trim_start().starts_with("<!--") {
+                if !line.contains("-->") {
+                    fence_start = Some(i);
+                }
+                // A comment, such as <!-- prettier-ignore -->, or the beginning of a fence.
+                metadata.push("");
+            } else if depth > 0 || TOML_LINE.is_match(line) {
+                depth += bracket_depth(line);
+                metadata.push(line);
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        if let Some(fence_start) = fence_start {
+            return Err(anyhow!(
+                "line {}: metadata is not closed by \"-->\"",
+                fence_start + 1
+            ));
+        }
+        let i = skip_blank_lines(i);
+        let content = (i < lines.len()).then(|| lines[i..].join("\n"));
 
-        let (metadata_yaml, content) = if let Some(cap) = TITLE.captures(&s) {
-            // If the first line starts with "#", treat it as a title.
-            let title = cap[1].to_string();
-            let s = TITLE.replace(&s, "").to_string();
+        let mut metadata_toml = metadata
+            .iter()
+            .map(|line| METADATA_COMMENT.replace_all(line, ""))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Some((_, title)) = title.as_ref() {
+            let title = toml::Value::String(title.clone());
+            metadata_toml.push_str(&format!("\ntitle = {title}\n"));
+        }
 
-            let mut split = s.splitn(2, "\n\n");
+        let metadata = toml::from_str(&metadata_toml).map_err(|e| {
+            let line_in_file = |line: usize| match title.as_ref() {
+                // The title is appended after the metadata.
+                Some((title_line, _)) if line >= metadata.len() => *title_line,
+                _ => line,
+            };
+            MetadataError::new(&e, &metadata_toml, &lines, line_in_file)
+        })?;
+        Ok(Markdown {
+            metadata,
+            content,
+            content_line: i + 1,
+        })
+    }
+}
 
-            // Add "title: xxx" to metadata
-            let metadata_yaml = split.next().ok_or_else(|| anyhow!("split error"))?;
-            // TODO: Espace double quote?
-            let metadata_yaml = format!("title = \"{title}\"\n{metadata_yaml}");
+/// The change of the depth of brackets in a TOML line, ignoring ones in strings.
+fn bracket_depth(line: &str) -> i32 {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => break,
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
 
-            let content = split.next().unwrap_or("");
+/// An invalid metadata, with the location in the file.
+#[derive(Debug)]
+struct MetadataError {
+    /// 1-based.
+    line: Option<usize>,
+    /// 1-based, in characters.
+    column: Option<usize>,
+    message: String,
+    /// The line in the file, with a caret under the invalid part.
+    snippet: String,
+}
 
-            (metadata_yaml, content.to_string())
-        } else {
-            let mut split = s.splitn(2, "\n\n");
-            let metadata_yaml = split.next().ok_or_else(|| anyhow!("split error"))?;
-            let content = split.next().unwrap_or("");
-            (metadata_yaml.to_string(), content.to_string())
+impl MetadataError {
+    /// `line_in_file` maps a 0-based line in `metadata_toml` into the one in `lines`.
+    fn new(
+        e: &toml::de::Error,
+        metadata_toml: &str,
+        lines: &[&str],
+        line_in_file: impl Fn(usize) -> usize,
+    ) -> MetadataError {
+        let message = e.message().trim_end().to_string();
+        let Some(span) = e.span() else {
+            return MetadataError {
+                line: None,
+                column: None,
+                message,
+                snippet: String::new(),
+            };
         };
+        let before = &metadata_toml[..span.start];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = metadata_toml[line_start..span.start].chars().count();
+        let width = metadata_toml[span.clone()]
+            .split('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            .max(1);
+        let file_line = line_in_file(line);
+        let source_line = lines.get(file_line).copied().unwrap_or_default();
+        // The location is not meaningful if the line is not in the file as is, e.g. a title.
+        let column = (file_line == line).then_some(column);
+        let number = (file_line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        let caret = match column {
+            Some(column) => format!("{}{}", " ".repeat(column), "^".repeat(width)),
+            None => "^".repeat(source_line.chars().count().max(1)),
+        };
+        MetadataError {
+            line: Some(file_line + 1),
+            column: column.map(|c| c + 1),
+            message,
+            snippet: format!("{gutter} |\n{number} | {source_line}\n{gutter} | {caret}"),
+        }
+    }
+}
 
-        // Ignore comments, such as <!-- prettier-ignore -->, in metadata.
-        static METADATA_COMMENT: LazyLock<Regex> =
-            LazyLock::new(|| Regex::new(r"(<!--.*\n*)|(-->.*\n*)").unwrap());
+impl std::fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {line}, column {column}: ")?,
+            (Some(line), None) => write!(f, "line {line}: ")?,
+            _ => {}
+        }
+        write!(f, "invalid metadata: {}", self.message)?;
+        if !self.snippet.is_empty() {
+            write!(f, "\n{}", self.snippet)?;
+        }
+        Ok(())
+    }
+}
 
-        let metadata_yaml = METADATA_COMMENT.replace_all(&metadata_yaml, "");
-        assert!(!metadata_yaml.contains("-->"));
-        assert!(!metadata_yaml.contains("<!--"));
+impl std::error::Error for MetadataError {}
 
-        Ok(Markdown {
-            metadata: metadata_yaml
-                .parse()
-                .with_context(|| format!("can not parse metatada: {metadata_yaml}"))?,
-            content,
+/// An image in `src`, such as a cover image.
+#[derive(PartialEq, Eq, Debug, Serialize)]
+struct Image {
+    /// Relative to the site's root, like [`Article::url`].
+    url: String,
+    width: Option<usize>,
+    height: Option<usize>,
+}
+
+impl Image {
+    /// `path` is relative to `src_dir`.
+    fn new(src_dir: &Path, path: &Path) -> Result<Image> {
+        let file = src_dir.join(path);
+        anyhow::ensure!(file.is_file(), "image not found: {}", file.display());
+        let (width, height) = match imagesize::size(&file) {
+            Ok(size) => (Some(size.width), Some(size.height)),
+            Err(e) => {
+                log::warn!("can not get the size of {}: {e}", file.display());
+                (None, None)
+            }
+        };
+        Ok(Image {
+            url: path.display().to_string(),
+            width,
+            height,
+        })
+    }
+}
+
+/// An audio file in `src`, such as a podcast episode, e.g. for `<enclosure>` in RSS.
+#[derive(PartialEq, Eq, Debug, Serialize)]
+struct Audio {
+    /// Relative to the site's root, like [`Article::url`].
+    url: String,
+    /// The size in bytes.
+    length: u64,
+    mime_type: &'static str,
+}
+
+impl Audio {
+    /// `path` is relative to `src_dir`.
+    fn new(src_dir: &Path, path: &Path) -> Result<Audio> {
+        let file = src_dir.join(path);
+        let length = std::fs::metadata(&file)
+            .with_context(|| format!("audio not found: {}", file.display()))?
+            .len();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mime_type = match extension.as_str() {
+            "mp3" => "audio/mpeg",
+            "m4a" | "mp4" => "audio/mp4",
+            "aac" => "audio/aac",
+            "ogg" | "oga" | "opus" => "audio/ogg",
+            "flac" => "audio/flac",
+            "wav" => "audio/wav",
+            _ => anyhow::bail!("unknown audio format: {}", file.display()),
+        };
+        Ok(Audio {
+            url: path.display().to_string(),
+            length,
+            mime_type,
         })
     }
 }
@@ -137,27 +533,543 @@ fn slug_to_url(slug: &str) -> String {
     }
 }
 
-fn url_to_filename(url: &str) -> String {
+/// The URL of an entry in its directory, given by `output`, such as "feed.xml", or by `slug`
+/// with `output_ext`, such as ".txt", instead of the `slug_to_url` heuristics.
+fn output_url(slug: &str, output: Option<&str>, output_ext: Option<&str>) -> Result<String> {
+    match (output, output_ext) {
+        (Some(_), Some(_)) => Err(anyhow!("`output` and `output_ext` are exclusive")),
+        (Some(output), None) => {
+            anyhow::ensure!(
+                !output.is_empty()
+                    && !output.starts_with('/')
+                    && !output.split('/').any(|c| c == ".."),
+                "`output` must be a relative path in the directory: {output}"
+            );
+            Ok(output.to_string())
+        }
+        (None, Some(ext)) => {
+            let ext = ext.trim_start_matches('.');
+            anyhow::ensure!(
+                !ext.is_empty() && !ext.contains('/'),
+                "invalid `output_ext`: {ext}"
+            );
+            Ok(format!("{}.{ext}", slug.trim_end_matches('/')))
+        }
+        (None, None) => Ok(slug_to_url(slug)),
+    }
+}
+
+/// Local files which `markdown` of the entry at `url` references by links, images or `src`
+/// attributes, relative to `src`, such as "notes/foo/diagram.png" for "diagram.png" in
+/// "notes/foo/". External URLs and fragments are skipped.
+fn asset_refs(markdown: &str, url: &str) -> Vec<PathBuf> {
+    static REF: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"\]\(\s*<?([^)\s>]+)|\b(?:src|href|poster)\s*=\s*"([^"]+)""#).unwrap()
+    });
+    let base = &url[..url.rfind('/').map_or(0, |i| i + 1)];
+    let mut assets = REF
+        .captures_iter(markdown)
+        .filter_map(|caps| {
+            let target = caps.get(1).or(caps.get(2)).unwrap().as_str();
+            let target = target.split(['?', '#']).next().unwrap();
+            if target.is_empty() || target.contains(':') || target.starts_with("//") {
+                return None;
+            }
+            let path = match target.strip_prefix('/') {
+                Some(target) => target.to_string(),
+                None => format!("{base}{target}"),
+            };
+            let mut normalized = PathBuf::new();
+            for component in Path::new(&text::percent_decode(&path)).components() {
+                match component {
+                    std::path::Component::Normal(c) => normalized.push(c),
+                    std::path::Component::ParentDir => {
+                        normalized.pop();
+                    }
+                    _ => {}
+                }
+            }
+            (!normalized.as_os_str().is_empty() && normalized.extension().is_some())
+                .then_some(normalized)
+        })
+        .collect::<Vec<_>>();
+    assets.sort();
+    assets.dedup();
+    assets
+}
+
+/// The path of the output file for `url`, where percent-encoded characters are decoded, as web
+/// servers do.
+pub(crate) fn url_to_filename(url: &str) -> String {
+    let url = text::percent_decode(url);
     if url.is_empty() || url.ends_with('/') {
         format!("{}{}", url, "index.html")
     } else {
-        url.to_string()
+        url
+    }
+}
+
+/// Write the generated `content` as `name` in `out_dir`, such as `_headers`, after the content
+/// of the user's file of the same name in `src_dir`, if any, which would be overwritten
+/// otherwise.
+pub(crate) fn write_generated(
+    src_dir: &Path,
+    out_dir: &Path,
+    name: &str,
+    content: &str,
+) -> Result<()> {
+    let mut s = match std::fs::read_to_string(src_dir.join(name)) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    if !s.is_empty() && !s.ends_with('\n') {
+        s.push('\n');
     }
+    s.push_str(content);
+    std::fs::write(out_dir.join(name), s)?;
+    Ok(())
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Default)]
-struct Article {
+pub struct Article {
     title: String,
     slug: String,
     author: Option<String>,
     date: Option<chrono::NaiveDate>,
     update_date: Option<chrono::NaiveDate>,
+    /// `update_date` if it's given, otherwise `date`.
+    last_modified: Option<chrono::NaiveDate>,
+    /// Whether it has been updated after `date`. e.g. for an "updated on" badge.
+    updated: bool,
+    /// Whether it's older than `[stale] years`.
+    is_stale: bool,
+    /// The `[stale] banner` for a stale article, which templates render.
+    stale: Option<String>,
+    /// The date from which it's removed from listings.
+    unpublish_date: Option<chrono::NaiveDate>,
+    /// Whether `unpublish_date` has come. It's rendered with the `tombstone` template, if any.
+    expired: bool,
+    /// Whether the content is encrypted with the password in `[protected]`.
+    protected: bool,
     draft: bool,
     url: String,
     page: bool,
     math: bool,
     template: Option<String>,
+    /// The template given by `[[templates]]` rules in the config, used if `template` is missing.
+    #[serde(skip)]
+    default_template: Option<String>,
+    tags: Vec<String>,
+    series: Option<String>,
+    /// The kind of content, such as "note" or "bookmark", if it's not a plain article.
+    kind: Option<String>,
+    /// The external URL which the article is about, such as a bookmarked page.
+    link: Option<String>,
+    /// The host of `link` without `www.`, such as "example.com".
+    link_host: Option<String>,
+    /// Whether it's a slide deck, whose content is `<section class="slide">`s.
+    slides: bool,
+    /// The number of slides, if it's a slide deck.
+    slide_count: usize,
+    /// A PDF of the slides, relative to the site's root, like [`Article::url`].
+    slides_pdf: Option<String>,
+    description: Option<String>,
+    keywords: Vec<String>,
+    cover: Option<Image>,
+    weight: Option<i64>,
+    /// The order of `articles`, if it's a page. Defaults to `sort` in the config.
+    #[serde(skip)]
+    sort: Option<SortOrder>,
+    /// Selects `articles`, if it's a page.
+    #[serde(skip)]
+    query: Option<ArticleFilter>,
+    /// Whether comments are enabled. Defaults to true for articles, if `[comments]` is configured.
+    comments: bool,
+    /// The start of the event which the article is about, if any.
+    event_start: Option<EventTime>,
+    /// The end of the event, inclusive.
+    event_end: Option<EventTime>,
+    event_location: Option<String>,
+    /// The name of the event, such as a conference where a talk was given.
+    event: Option<String>,
+    /// The audio of a podcast episode.
+    audio: Option<Audio>,
+    /// The duration of `audio`, such as "42:10".
+    duration: Option<String>,
+    episode: Option<u32>,
+    season: Option<u32>,
+    /// The language tag, such as "ja" or "en-GB". Defaults to `lang` in the config.
+    lang: Option<String>,
+    /// `lang` as an OpenGraph locale, such as "ja_JP" or "en_GB".
+    locale: Option<String>,
+    pdf: bool,
+    toc: bool,
     content: String,
+    toc_html: Option<String>,
+    #[serde(skip)]
+    markdown: String,
+    /// The path of the markdown, relative to `src`, such as `notes/rust/foo.md`.
+    #[serde(serialize_with = "serialize_slash_path")]
+    source_path: PathBuf,
+    /// Files in `src` which the content references, such as images. See `asset_refs`.
+    #[serde(skip)]
+    assets: Vec<PathBuf>,
+    /// The first directory of `source_path`, such as `notes`. `None` for a markdown at the top.
+    section: Option<String>,
+    /// The directories which `source_path` is in, from the top, such as
+    /// `["notes", "notes/rust"]`. e.g. for breadcrumbs.
+    ancestors: Vec<String>,
+}
+
+/// Whether `s` looks like a BCP 47 language tag, such as "en", "ja-JP" or "zh-Hant-TW".
+fn is_language_tag(s: &str) -> bool {
+    static TAG: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^[A-Za-z]{2,3}(-[A-Za-z0-9]{1,8})*$").unwrap());
+    TAG.is_match(s)
+}
+
+/// The OpenGraph locale for a language tag, such as "ja" => "ja_JP". The region in the tag is
+/// used if any, otherwise the most common one for some languages.
+pub(crate) fn og_locale(lang: &str) -> String {
+    let mut subtags = lang.split('-');
+    let language = subtags.next().unwrap_or_default().to_ascii_lowercase();
+    let region = subtags
+        .find(|s| s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|s| s.to_ascii_uppercase())
+        .or_else(|| {
+            let region = match language.as_str() {
+                "en" => "US",
+                "ja" => "JP",
+                "zh" => "CN",
+                "ko" => "KR",
+                "de" => "DE",
+                "fr" => "FR",
+                "es" => "ES",
+                "it" => "IT",
+                "pt" => "BR",
+                "ru" => "RU",
+                _ => return None,
+            };
+            Some(region.to_string())
+        });
+    match region {
+        Some(region) => format!("{language}_{region}"),
+        None => language,
+    }
+}
+
+/// `path` with `/` as the separator on all platforms.
+pub(crate) fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn serialize_slash_path<S>(path: &Path, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&slash_path(path))
+}
+
+/// The directories which `path` is in, from the top.
+fn ancestors(path: &Path) -> Vec<String> {
+    let mut ancestors = path
+        .ancestors()
+        .skip(1)
+        .filter(|a| !a.as_os_str().is_empty())
+        .map(slash_path)
+        .collect::<Vec<_>>();
+    ancestors.reverse();
+    ancestors
+}
+
+/// Other entries, which are available to templates.
+#[derive(Clone, Copy, Default)]
+struct Entries<'a> {
+    /// Published articles. Only pages can see them.
+    articles: Option<&'a [Article]>,
+    /// Pages, ordered by `weight`, then by title.
+    pages: &'a [Article],
+    stats: Option<&'a SiteStats>,
+}
+
+/// Template variables made from `Entries::articles`, which are undefined in articles.
+const PAGE_VARIABLES: &[&str] = &[
+    "articles",
+    "events",
+    "upcoming_events",
+    "talks",
+    "articles_by_year",
+    "articles_by_month",
+    "undated_articles",
+    "recently_updated",
+    "changes",
+];
+
+/// `source`, a template, where guarded uses of `PAGE_VARIABLES` are renamed, so that they are
+/// not taken as undeclared variables. A use is guarded if it's in the body of
+/// `{% if x is defined %}` for `x`, or of `{% if entry.page %}` for any, or in the `{% else %}`
+/// body of their negations, such as `{% if x is undefined %}`, or in `x | default(..)`, or the
+/// test itself. It's a heuristic: other conditions, such as `{% if x is defined and y %}`, and
+/// inline `if` expressions don't guard.
+fn unguarded_source(source: &str) -> String {
+    static TAG: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\{%[-+]?\s*(if|elif|else|endif)\b\s*(.*?)\s*[-+]?%\}").unwrap()
+    });
+    static GUARD: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^(not\s+)?(?:([A-Za-z_]\w*)\s+is\s+(not\s+)?(un)?defined|entry\.page)$")
+            .unwrap()
+    });
+    static IDENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[A-Za-z_]\w*\b").unwrap());
+    static GUARDED_USE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^\s*(?:\|\s*default\b|is\s+(?:not\s+)?(?:un)?defined\b)").unwrap()
+    });
+
+    struct If<'a> {
+        /// The guarded variable, or `None` for all, and whether the `if` body is guarded, as
+        /// opposed to the `else` body.
+        guard: Option<(Option<&'a str>, bool)>,
+        in_else: bool,
+        start: usize,
+    }
+    // Guarded spans of `source`, with the guarded variable, or `None` for all.
+    let mut spans = Vec::new();
+    let mut ifs = Vec::<If>::new();
+    for caps in TAG.captures_iter(source) {
+        let tag = caps.get(0).unwrap();
+        match &caps[1] {
+            "if" => {
+                let guard = GUARD.captures(caps.get(2).unwrap().as_str()).map(|guard| {
+                    let negations = [1, 3, 4]
+                        .iter()
+                        .filter(|i| guard.get(**i).is_some())
+                        .count();
+                    (guard.get(2).map(|m| m.as_str()), negations % 2 == 0)
+                });
+                ifs.push(If {
+                    guard,
+                    in_else: false,
+                    start: tag.end(),
+                });
+            }
+            "elif" | "else" | "endif" => {
+                let Some(last) = ifs.last_mut() else {
+                    continue;
+                };
+                if let Some((variable, in_if)) = last.guard {
+                    if in_if != last.in_else {
+                        spans.push((last.start..tag.start(), variable));
+                    }
+                }
+                if &caps[1] == "endif" {
+                    ifs.pop();
+                } else if !last.in_else {
+                    last.in_else = true;
+                    last.start = tag.end();
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    let mut unguarded = String::with_capacity(source.len());
+    let mut last = 0;
+    for m in IDENT.find_iter(source) {
+        let name = m.as_str();
+        if !PAGE_VARIABLES.contains(&name) || source[..m.start()].ends_with('.') {
+            continue;
+        }
+        let guarded = GUARDED_USE.is_match(&source[m.end()..])
+            || spans.iter().any(|(span, variable)| {
+                span.contains(&m.start()) && variable.is_none_or(|variable| variable == name)
+            });
+        if guarded {
+            unguarded.push_str(&source[last..m.end()]);
+            unguarded.push_str("_guarded");
+            last = m.end();
+        }
+    }
+    unguarded.push_str(&source[last..]);
+    unguarded
+}
+
+/// `site_stats`: statistics of the published articles, computed once per build.
+#[derive(PartialEq, Eq, Debug, Default, Serialize)]
+struct SiteStats {
+    /// The number of articles.
+    articles: usize,
+    /// Year => the number of articles.
+    years: BTreeMap<i32, usize>,
+    /// Tag => the number of articles.
+    tags: BTreeMap<String, usize>,
+    /// The date of the oldest article.
+    first_date: Option<chrono::NaiveDate>,
+    /// The date of the newest article.
+    last_date: Option<chrono::NaiveDate>,
+}
+
+impl SiteStats {
+    fn new<'a>(articles: impl IntoIterator<Item = &'a Metadata>) -> SiteStats {
+        let mut stats = SiteStats::default();
+        for metadata in articles {
+            stats.articles += 1;
+            for tag in metadata.tags.iter().flatten() {
+                *stats.tags.entry(tag.clone()).or_default() += 1;
+            }
+            if let Some(date) = metadata.date {
+                *stats.years.entry(date.year()).or_default() += 1;
+                stats.first_date = Some(stats.first_date.map_or(date, |d| d.min(date)));
+                stats.last_date = Some(stats.last_date.map_or(date, |d| d.max(date)));
+            }
+        }
+        stats
+    }
+}
+
+/// Find empty titles, and articles with the same title, which are confusing in listings, and
+/// make the same heading ids.
+fn lint_titles<'a>(markdowns: impl IntoIterator<Item = &'a MarkdownFile>) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+    let mut articles_by_title = BTreeMap::<&str, Vec<String>>::new();
+    for m in markdowns {
+        let title = m.markdown.metadata.title.trim();
+        if title.is_empty() {
+            problems.push(Diagnostic::warning(
+                Some(m.relative_path.display().to_string()),
+                None,
+                "the title is empty".to_string(),
+            ));
+        } else if !m.markdown.metadata.page.unwrap_or(false) {
+            articles_by_title
+                .entry(title)
+                .or_default()
+                .push(m.relative_path.display().to_string());
+        }
+    }
+    for (title, paths) in articles_by_title {
+        if paths.len() > 1 {
+            problems.push(Diagnostic::warning(
+                Some(paths[0].clone()),
+                None,
+                format!(
+                    "articles have the same title {title:?}: {}",
+                    paths.join(", ")
+                ),
+            ));
+        }
+    }
+    problems
+}
+
+/// Find entries written to the same output file, such as `foo.md` and `foo/index.md`, where
+/// one overwrites the other.
+fn lint_urls<'a>(entries: impl IntoIterator<Item = &'a Article>) -> Vec<Diagnostic> {
+    let mut entries_by_file = BTreeMap::<String, Vec<String>>::new();
+    for entry in entries {
+        entries_by_file
+            .entry(url_to_filename(&entry.url))
+            .or_default()
+            .push(entry.source_path.display().to_string());
+    }
+    entries_by_file
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(file, paths)| {
+            Diagnostic::warning(
+                Some(paths[0].clone()),
+                None,
+                format!(
+                    "entries are written to the same {file}: {}",
+                    paths.join(", ")
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Sort articles from the newest. Articles on the same date are ordered by slug, so that the
+/// output doesn't change between builds.
+fn sort_by_date(articles: &mut [Article]) {
+    articles.sort_by(newest_first);
+}
+
+fn newest_first(a: &Article, b: &Article) -> Ordering {
+    b.date.cmp(&a.date).then_with(|| a.slug.cmp(&b.slug))
+}
+
+/// Group articles by `key` of their date, from the newest. Articles without date are skipped.
+fn group_by_date<'a, K: Ord>(
+    articles: impl IntoIterator<Item = &'a Article>,
+    key: impl Fn(&chrono::NaiveDate) -> K,
+) -> Vec<(K, Vec<&'a Article>)> {
+    let mut groups = BTreeMap::<K, Vec<&Article>>::new();
+    for a in articles {
+        if let Some(date) = a.date.as_ref() {
+            groups.entry(key(date)).or_default().push(a);
+        }
+    }
+    groups
+        .into_iter()
+        .rev()
+        .map(|(key, mut articles)| {
+            articles.sort_by(|a, b| newest_first(a, b));
+            (key, articles)
+        })
+        .collect()
+}
+
+/// The order of `articles` in templates. Ties are broken by `date`, from the newest.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    /// From the newest.
+    #[default]
+    Date,
+    /// From the most recently updated, by `update_date`, or `date` if it's missing.
+    UpdateDate,
+    /// By `weight`, ascending. Articles without `weight` come last.
+    Weight,
+    /// By `title`, alphabetically, ignoring case.
+    Title,
+}
+
+impl SortOrder {
+    fn compare(&self, a: &Article, b: &Article) -> Ordering {
+        match self {
+            SortOrder::Date => Ordering::Equal,
+            SortOrder::UpdateDate => b.last_modified.cmp(&a.last_modified),
+            SortOrder::Weight => {
+                (a.weight.is_none(), a.weight).cmp(&(b.weight.is_none(), b.weight))
+            }
+            SortOrder::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+        }
+        .then_with(|| newest_first(a, b))
+    }
+}
+
+/// Order pages for menus: Ones with `weight` come first, in ascending order.
+fn sort_by_weight(pages: &mut [Article]) {
+    pages.sort_by(|a, b| {
+        (a.weight.is_none(), a.weight, &a.title, &a.slug).cmp(&(
+            b.weight.is_none(),
+            b.weight,
+            &b.title,
+            &b.slug,
+        ))
+    });
+}
+
+/// Articles with `event_start`, the earliest first.
+fn events<'a>(articles: impl IntoIterator<Item = &'a Article>) -> Vec<&'a Article> {
+    let mut events = articles
+        .into_iter()
+        .filter(|a| a.event_start.is_some())
+        .collect::<Vec<_>>();
+    events.sort_by_key(|a| (a.event_start.unwrap().datetime(), &a.slug));
+    events
 }
 
 impl Article {
@@ -166,82 +1078,433 @@ impl Article {
             relative_path,
             markdown,
         }: MarkdownFile,
-    ) -> Article {
+        config: &Config,
+        cache: Option<&RenderCache>,
+    ) -> Result<Article> {
         log::debug!("article: {}", relative_path.display());
         let slug = if let Some(slug) = markdown.metadata.slug.as_ref() {
             slug.to_string()
         } else {
-            relative_path
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
+            config.slugs.slug(
+                relative_path
+                    .file_stem()
+                    .ok_or_else(|| anyhow!("no file name: {}", relative_path.display()))?
+                    .to_str()
+                    .ok_or_else(|| {
+                        anyhow!("file name is not UTF-8: {}", relative_path.display())
+                    })?,
+            )
         };
         let url = relative_path
             .parent()
-            .unwrap()
-            .join(slug_to_url(&slug))
+            .unwrap_or(Path::new(""))
+            .join(
+                output_url(
+                    &slug,
+                    markdown.metadata.output.as_deref(),
+                    markdown.metadata.output_ext.as_deref(),
+                )
+                .with_context(|| format!("invalid output: {}", relative_path.display()))?,
+            )
             .display()
             .to_string();
-        let content = markdown.render();
+        if let Some(event_end) = markdown.metadata.event_end {
+            let event_start = markdown.metadata.event_start.ok_or_else(|| {
+                anyhow!(
+                    "`event_end` is given without `event_start`: {}",
+                    relative_path.display()
+                )
+            })?;
+            anyhow::ensure!(
+                event_end.datetime() >= event_start.datetime(),
+                "`event_end` is before `event_start`: {}",
+                relative_path.display()
+            );
+        }
+        let lang = markdown
+            .metadata
+            .lang
+            .clone()
+            .or_else(|| config.get("lang").map(String::from));
+        if let Some(link) = markdown.metadata.link.as_deref() {
+            anyhow::ensure!(
+                (link.starts_with("https://") || link.starts_with("http://"))
+                    && stats::domain(link).is_some(),
+                "invalid `link`: {link} (expected an absolute URL): {}",
+                relative_path.display()
+            );
+        }
+        if let Some(lang) = lang.as_ref() {
+            anyhow::ensure!(
+                is_language_tag(lang),
+                "invalid `lang`: {lang} (expected a language tag, such as `en` or `ja-JP`): {}",
+                relative_path.display()
+            );
+        }
+        let assets = asset_refs(markdown.content.as_deref().unwrap_or_default(), &url);
+        let content = markdown.render(&config.markdown.unwrap_or_default(), cache, &assets);
+        let content = {
+            // Definitions in the markdown take precedence.
+            let mut abbreviations = config.abbreviations.clone();
+            abbreviations.extend(abbr::split(markdown.content.as_deref().unwrap_or_default()).1);
+            abbr::expand(&content, &abbreviations)
+        };
+        let content = if config
+            .markdown
+            .unwrap_or_default()
+            .merge(&markdown.metadata.markdown.unwrap_or_default())
+            .id_prefix
+            .unwrap_or(false)
+        {
+            html::prefix_ids(&content, &slug)
+        } else {
+            content
+        };
+        let slides = markdown.metadata.slides.unwrap_or(false);
+        let slide_count = if slides {
+            markdown.slide_count(&config.markdown.unwrap_or_default())
+        } else {
+            0
+        };
+        let ancestors = ancestors(&relative_path);
 
-        Article {
+        let mut article = Article {
             title: markdown.metadata.title,
             slug,
             author: markdown.metadata.author,
             date: markdown.metadata.date,
-            update_date: markdown.metadata.update_date,
+            update_date: None,
+            last_modified: None,
+            updated: false,
+            is_stale: false,
+            stale: None,
             draft: markdown.metadata.draft.unwrap_or(false),
             url,
             page: markdown.metadata.page.unwrap_or(false),
             math: markdown.metadata.math.unwrap_or(false),
             template: markdown.metadata.template,
+            default_template: config.template_for(&relative_path).map(String::from),
+            tags: markdown.metadata.tags.unwrap_or_default(),
+            series: markdown.metadata.series,
+            kind: markdown.metadata.kind,
+            link_host: markdown
+                .metadata
+                .link
+                .as_deref()
+                .and_then(stats::domain)
+                .map(String::from),
+            link: markdown.metadata.link,
+            slides,
+            slide_count,
+            slides_pdf: None,
+            description: markdown.metadata.description,
+            keywords: markdown.metadata.keywords.unwrap_or_default(),
+            cover: None,
+            weight: markdown.metadata.weight,
+            sort: markdown.metadata.sort,
+            query: markdown.metadata.query,
+            comments: config.comments.is_some()
+                && markdown
+                    .metadata
+                    .comments
+                    .unwrap_or(!markdown.metadata.page.unwrap_or(false)),
+            event_start: markdown.metadata.event_start,
+            event_end: markdown.metadata.event_end,
+            event_location: markdown.metadata.event_location,
+            event: markdown.metadata.event,
+            audio: None,
+            duration: markdown.metadata.duration,
+            episode: markdown.metadata.episode,
+            season: markdown.metadata.season,
+            unpublish_date: markdown.metadata.unpublish_date,
+            expired: markdown
+                .metadata
+                .unpublish_date
+                .is_some_and(|date| date <= config.today()),
+            protected: markdown.metadata.protected.unwrap_or(false),
+            locale: lang.as_deref().map(og_locale),
+            lang,
+            pdf: markdown.metadata.pdf.unwrap_or(false),
+            toc: markdown.metadata.toc.unwrap_or(false),
             content,
+            toc_html: None,
+            markdown: markdown.content.unwrap_or_default(),
+            section: ancestors.first().cloned(),
+            ancestors,
+            source_path: relative_path,
+            assets,
+        };
+        article.set_update_date(markdown.metadata.update_date);
+        Ok(article)
+    }
+
+    /// The plain text of the rendered content, e.g. for search indexes and summaries.
+    pub fn plain_text(&self) -> String {
+        text::html_to_plain(&self.content)
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The URL relative to the site's root, such as `notes/foo/`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The path of the markdown, relative to `src`.
+    pub fn source_path(&self) -> &Path {
+        &self.source_path
+    }
+
+    pub fn is_page(&self) -> bool {
+        self.page
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        self.date
+    }
+
+    /// The rendered content, which is encrypted if the article is protected.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
+
+    /// Whether the article is a talk: a slide deck, given at an `event`, or of the kind "talk".
+    fn is_talk(&self) -> bool {
+        self.slides || self.event.is_some() || self.kind.as_deref() == Some("talk")
+    }
+
+    /// The content wrapped in a microformats2 `h-entry`, with hidden properties, so that
+    /// IndieWeb tools can parse it regardless of templates.
+    fn h_entry(&self, url: &str) -> String {
+        let mut s = String::from("<div class=\"h-entry\">\n");
+        s.push_str(&format!(
+            "<data class=\"p-name\" value=\"{}\"></data>\n",
+            html::escape(&self.title)
+        ));
+        s.push_str(&format!(
+            "<a class=\"u-url\" href=\"{}\" hidden></a>\n",
+            html::escape(url)
+        ));
+        if let Some(link) = self.link.as_ref() {
+            s.push_str(&format!(
+                "<a class=\"u-bookmark-of\" href=\"{}\" hidden></a>\n",
+                html::escape(link)
+            ));
+        }
+        for (class, date) in [
+            ("dt-published", self.date),
+            ("dt-updated", self.update_date),
+        ] {
+            if let Some(date) = date {
+                s.push_str(&format!(
+                    "<time class=\"{class}\" datetime=\"{date}\" hidden></time>\n"
+                ));
+            }
+        }
+        if let Some(author) = self.author.as_ref() {
+            s.push_str(&format!(
+                "<data class=\"p-author h-card\" value=\"{}\"></data>\n",
+                html::escape(author)
+            ));
+        }
+        if let Some(description) = self.description.as_ref() {
+            s.push_str(&format!(
+                "<data class=\"p-summary\" value=\"{}\"></data>\n",
+                html::escape(description)
+            ));
+        }
+        for tag in &self.tags {
+            s.push_str(&format!(
+                "<data class=\"p-category\" value=\"{}\"></data>\n",
+                html::escape(tag)
+            ));
         }
+        s.push_str(&format!(
+            "<div class=\"e-content\">\n{}</div>\n</div>\n",
+            self.content
+        ));
+        s
     }
 
-    fn context(&self, config: &Config, articles: Option<&[Article]>) -> Value {
+    fn set_update_date(&mut self, update_date: Option<chrono::NaiveDate>) {
+        self.update_date = update_date;
+        self.last_modified = update_date.or(self.date);
+        self.updated = match (self.date, update_date) {
+            (Some(date), Some(update_date)) => update_date > date,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+    }
+
+    fn context(&self, config: &Config, entries: Entries) -> Value {
         #[derive(PartialEq, Eq, Debug, Default, Serialize)]
         struct YearArticles<'a> {
             year: i32,
             articles: Vec<&'a Article>,
         }
 
+        #[derive(PartialEq, Eq, Debug, Default, Serialize)]
+        struct MonthArticles<'a> {
+            year: i32,
+            month: u32,
+            articles: Vec<&'a Article>,
+        }
+
+        /// An entry which was added, on `date`, or updated, on `update_date`.
+        #[derive(PartialEq, Eq, Debug, Serialize)]
+        struct EntryChange<'a> {
+            date: chrono::NaiveDate,
+            updated: bool,
+            entry: &'a Article,
+        }
+
         let mut context = config.context();
-        if let Some(articles) = articles {
-            let mut articles_by_year = BTreeMap::<i32, Vec<&Article>>::new();
-            for a in articles {
-                articles_by_year
-                    .entry(a.date.as_ref().unwrap().year())
-                    .or_default()
-                    .push(a);
-            }
-            let mut articles_by_year = articles_by_year
+        if let Some(articles) = entries.articles {
+            let articles = articles
+                .iter()
+                .filter(|a| self.query.as_ref().is_none_or(|query| query.matches(a)))
+                .collect::<Vec<_>>();
+            let articles_by_year = group_by_date(articles.iter().copied(), |date| date.year())
                 .into_iter()
-                .map(|(year, mut articles)| {
-                    articles.sort_by_key(|a| a.date);
-                    articles.reverse();
-                    YearArticles { year, articles }
+                .map(|(year, articles)| YearArticles { year, articles })
+                .collect::<Vec<_>>();
+            let articles_by_month =
+                group_by_date(articles.iter().copied(), |date| (date.year(), date.month()))
+                    .into_iter()
+                    .map(|((year, month), articles)| MonthArticles {
+                        year,
+                        month,
+                        articles,
+                    })
+                    .collect::<Vec<_>>();
+            let undated_articles = articles
+                .iter()
+                .filter(|a| a.date.is_none())
+                .copied()
+                .collect::<Vec<_>>();
+
+            let mut recently_updated = articles.clone();
+            recently_updated.sort_by(|a, b| SortOrder::UpdateDate.compare(a, b));
+
+            // Pages are included, so that updates of evergreen pages are seen.
+            let mut changes = articles
+                .iter()
+                .copied()
+                .chain(
+                    entries
+                        .pages
+                        .iter()
+                        .filter(|p| self.query.as_ref().is_none_or(|query| query.matches(p))),
+                )
+                .flat_map(|a| {
+                    let added = a.date.map(|date| EntryChange {
+                        date,
+                        updated: false,
+                        entry: a,
+                    });
+                    let updated = a.update_date.filter(|_| a.updated).map(|date| EntryChange {
+                        date,
+                        updated: true,
+                        entry: a,
+                    });
+                    added.into_iter().chain(updated)
                 })
                 .collect::<Vec<_>>();
-            articles_by_year.reverse();
+            changes.sort_by(|a, b| {
+                (b.date, b.updated)
+                    .cmp(&(a.date, a.updated))
+                    .then_with(|| a.entry.slug.cmp(&b.entry.slug))
+            });
+
+            let events = events(articles.iter().copied());
+            let today = config.today();
+            let upcoming_events = events
+                .iter()
+                .filter(|a| a.event_end.or(a.event_start).unwrap().date() >= today)
+                .collect::<Vec<_>>();
+            let talks = articles
+                .iter()
+                .filter(|a| a.is_talk())
+                .copied()
+                .collect::<Vec<_>>();
+
+            let order = self.sort.or(config.sort).unwrap_or_default();
+            let mut articles = articles;
+            articles.sort_by(|a, b| order.compare(a, b));
 
             context = context! {
                 articles,
+                events,
+                upcoming_events,
+                talks,
                 articles_by_year,
+                articles_by_month,
+                undated_articles,
+                recently_updated,
+                changes,
                 ..context
             };
         };
         context = context! {
             entry => &self,
+            pages => entries.pages,
+            site_stats => entries.stats,
+            comments => config.comments.as_ref().filter(|_| self.comments).map(|comments| {
+                context! { html => comments.snippet(self), ..Value::from_serialize(comments) }
+            }),
             ..context
         };
+        if !config.context_hooks.is_empty() {
+            let mut extra = BTreeMap::new();
+            for hook in &config.context_hooks {
+                hook(self, &mut extra);
+            }
+            context = context! { ..Value::from(extra), ..context };
+        }
         context
     }
 
+    /// Render `toc_html` with `toc.jinja` if it exists, where the heading tree is given as `toc`.
+    fn build_toc(&mut self, env: &Environment) -> Result<()> {
+        if !self.toc {
+            return Ok(());
+        }
+        let entries = toc::from_html(&self.content);
+        let toc_html = match env.get_template("toc.jinja") {
+            Ok(template) => template
+                .render(context! { toc => entries, entry => &self })
+                .map_err(|e| anyhow!("renderer err: {}", e))?,
+            Err(e) if e.kind() == minijinja::ErrorKind::TemplateNotFound => toc::to_html(&entries),
+            Err(e) => return Err(e.into()),
+        };
+        self.toc_html = Some(toc_html);
+        Ok(())
+    }
+
+    /// The templates which rendering this article starts from. Some of them may not exist.
+    fn root_templates(&self) -> Vec<String> {
+        let mut templates = vec![
+            format!("{}.jinja", self.template_name()),
+            format!("{}.gmi.jinja", self.template_name()),
+        ];
+        if self.toc {
+            templates.push("toc.jinja".to_string());
+        }
+        templates
+    }
+
     fn template_name(&self) -> &str {
-        match self.template.as_ref() {
+        match self.template.as_ref().or(self.default_template.as_ref()) {
             Some(a) => a,
             None => {
                 if self.page {
@@ -253,227 +1516,4374 @@ impl Article {
         }
     }
 
+    /// Render the article into `w`. The output is streamed, without building the whole HTML
+    /// in memory, unless it has to be post-processed, e.g. for `[[inject]]` or `path_prefix`.
     fn render(
         &self,
         config: &Config,
-        articles: Option<&[Article]>,
-        env: &Environment,
-    ) -> Result<String> {
-        let context = self.context(config, articles);
-        let template = env.get_template(&format!("{}.jinja", self.template_name()))?;
-        template
-            .render(&context)
-            .map_err(|e| anyhow!("renderer err: {}", e))
-    }
-
-    fn render_and_write(
-        &self,
-        config: &Config,
-        articles: Option<&[Article]>,
+        entries: Entries,
         env: &Environment,
-        out_dir: &Path,
+        mut w: impl Write,
     ) -> Result<()> {
-        let html = self.render(config, articles, env)?;
-        let mut out_file = PathBuf::from(out_dir);
-        out_file.push(url_to_filename(&self.url));
-        log::debug!("{:32} => {}", self.url, out_file.display());
+        let mut context = self.context(config, entries);
+        let template = env.get_template(&format!("{}.jinja", self.template_name()))?;
+        let is_html = url_to_filename(&self.url).ends_with(".html");
+        // Only the HTML page of an article is marked up, not feeds or other outputs which
+        // embed `content`.
+        if is_html && config.microformats.unwrap_or(false) && !self.page && !self.protected {
+            let url = match config.site_url() {
+                Some(site_url) => format!("{site_url}/{}", self.url),
+                None => format!("{}/{}", config.path_prefix(), self.url),
+            };
+            context = context! {
+                entry => context! { content => self.h_entry(&url), ..Value::from_serialize(self) },
+                ..context
+            };
+        }
+        if config.inject.is_empty()
+            && config.path_prefix().is_empty()
+            && !config.minify
+            && config.fingerprints.is_empty()
+            && is_html
+        {
+            template
+                .render_captured_to(&context, &mut w)
+                .map_err(|e| anyhow!("renderer err: {}", e))?;
+        } else {
+            let mut html = template
+                .render(&context)
+                .map_err(|e| anyhow!("renderer err: {}", e))?;
+            config.inject(&mut html);
+            if config.minify && is_html {
+                html = html::minify(&html);
+            }
+            // Feeds and other outputs embed `content` without self-links of headings.
+            if !is_html {
+                html = html::strip_self_links(&html);
+            }
+            if !config.fingerprints.is_empty() {
+                html = html::fingerprint_urls(&html, &config.fingerprints);
+            }
+            if !config.path_prefix().is_empty() {
+                html = html::prefix_urls(&html, config.path_prefix());
+            }
+            w.write_all(html.as_bytes())?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    fn render_and_write(
+        &self,
+        config: &Config,
+        entries: Entries,
+        env: &Environment,
+        out_dir: &Path,
+    ) -> Result<()> {
+        let mut out_file = PathBuf::from(out_dir);
+        out_file.push(url_to_filename(&self.url));
+        log::debug!("{:32} => {}", self.url, out_file.display());
+        std::fs::create_dir_all(out_file.parent().unwrap())?;
+        // Render into a temporary file, which is renamed on success, so that an error doesn't
+        // leave a truncated output behind.
+        let tmp_file = out_file.with_file_name(format!(
+            ".{}.tmp",
+            out_file.file_name().unwrap().to_string_lossy()
+        ));
+        if let Err(e) = self.render(
+            config,
+            entries,
+            env,
+            BufWriter::new(std::fs::File::create(&tmp_file)?),
+        ) {
+            std::fs::remove_file(&tmp_file)?;
+            return Err(e);
+        }
+        std::fs::rename(&tmp_file, &out_file)?;
+        if out_file.extension().is_some_and(|ext| ext == "html") {
+            for raw_output in config.raw_outputs() {
+                self.write_raw_output(
+                    *raw_output,
+                    &out_file.with_extension(raw_output.extension()),
+                )?;
+            }
+        }
+        if self.pdf {
+            self.write_pdf(config, &out_file)?;
+        }
+        Ok(())
+    }
+
+    /// Write the article as gemtext, with `<template>.gmi.jinja` if it exists, where
+    /// `entry.content` is gemtext.
+    fn render_and_write_gemtext(
+        &self,
+        config: &Config,
+        entries: Entries,
+        env: &Environment,
+        out_dir: &Path,
+    ) -> Result<()> {
+        let gemtext = gemtext::from_markdown(&text::remove_newline_between_cjk(&self.markdown));
+        let gemtext = match env.get_template(&format!("{}.gmi.jinja", self.template_name())) {
+            Ok(template) => {
+                let context = context! {
+                    entry => context! { content => gemtext, ..Value::from_serialize(self) },
+                    ..self.context(config, entries)
+                };
+                template
+                    .render(&context)
+                    .map_err(|e| anyhow!("renderer err: {}", e))?
+            }
+            Err(e) if e.kind() == minijinja::ErrorKind::TemplateNotFound => {
+                let mut s = format!("# {}\n\n", self.title);
+                if let Some(date) = self.date {
+                    s.push_str(&format!("{date}\n\n"));
+                }
+                s.push_str(&gemtext);
+                s
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut out_file = out_dir.join(url_to_filename(&self.url));
+        if out_file.extension().is_some_and(|ext| ext == "html") {
+            out_file.set_extension("gmi");
+        }
+        log::debug!("{:32} => {}", self.url, out_file.display());
         std::fs::create_dir_all(out_file.parent().unwrap())?;
-        std::fs::write(&out_file, html)?;
+        std::fs::write(&out_file, gemtext)?;
+        Ok(())
+    }
+
+    /// Convert the rendered HTML into a PDF, next to the HTML, with `pdf_command` in the config.
+    fn write_pdf(&self, config: &Config, html_file: &Path) -> Result<()> {
+        let command = config.pdf_command()?.ok_or_else(|| {
+            anyhow!(
+                "`pdf_command` must be set in the config to generate a PDF for {}",
+                self.url
+            )
+        })?;
+        let input = html_file.canonicalize()?;
+        let output = input.with_extension("pdf");
+        let args = command
+            .iter()
+            .map(|arg| {
+                arg.replace("{input}", &input.display().to_string())
+                    .replace("{output}", &output.display().to_string())
+            })
+            .collect::<Vec<_>>();
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| anyhow!("`pdf_command` is empty"))?;
+        log::debug!("{:32} => {}", self.url, output.display());
+        let status = std::process::Command::new(program)
+            .args(args)
+            .status()
+            .with_context(|| format!("can not run pdf_command: {program}"))?;
+        anyhow::ensure!(
+            status.success(),
+            "pdf_command failed for {}: {status}",
+            self.url
+        );
+        Ok(())
+    }
+
+    fn write_raw_output(&self, raw_output: RawOutput, out_file: &Path) -> Result<()> {
+        let s = match raw_output {
+            RawOutput::Markdown => format!("# {}\n\n{}", self.title, self.markdown),
+            RawOutput::Text => format!(
+                "{}\n\n{}",
+                self.title,
+                text::markdown_to_plain(&self.markdown)
+            ),
+        };
+        log::debug!("{:32} => {}", self.url, out_file.display());
+        std::fs::write(out_file, s)?;
         Ok(())
     }
 }
 
-pub struct Config(std::collections::BTreeMap<String, String>);
+/// The date of the last commit which touched `path`, if it's tracked by git.
+fn git_last_commit_date(path: &Path) -> Option<chrono::NaiveDate> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(path.parent()?)
+        .args(["log", "-1", "--format=%cs", "--"])
+        .arg(path.file_name()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()?.trim().parse().ok()
+}
+
+/// Selects articles to export, or to list in a page with `query`.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ArticleFilter {
+    pub tag: Option<String>,
+    pub series: Option<String>,
+    pub kind: Option<String>,
+    pub since: Option<chrono::NaiveDate>,
+    pub until: Option<chrono::NaiveDate>,
+}
+
+impl ArticleFilter {
+    fn matches(&self, article: &Article) -> bool {
+        self.tag
+            .as_ref()
+            .is_none_or(|tag| article.tags.contains(tag))
+            && self
+                .series
+                .as_ref()
+                .is_none_or(|series| article.series.as_ref() == Some(series))
+            && self
+                .kind
+                .as_ref()
+                .is_none_or(|kind| article.kind.as_ref() == Some(kind))
+            && self.since.is_none_or(|since| article.date >= Some(since))
+            && self.until.is_none_or(|until| article.date <= Some(until))
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Epub,
+    /// Email-friendly HTML of the newest article, or of all the articles with `--since`.
+    Newsletter,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Epub => "epub",
+            ExportFormat::Newsletter => "html",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "epub" => Ok(ExportFormat::Epub),
+            "newsletter" => Ok(ExportFormat::Newsletter),
+            _ => Err(anyhow!(
+                "unknown export format: {s} (expected `epub` or `newsletter`)"
+            )),
+        }
+    }
+}
+
+/// How drafts, articles or pages with `draft = true`, are published.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DraftMode {
+    /// Render drafts at their usual URL, but exclude them from listings.
+    #[default]
+    Unlisted,
+    /// Render drafts under an unguessable URL, derived from `draft_secret` in the config, so
+    /// that a preview link can be shared without publishing the draft.
+    Obfuscate,
+    /// Neither render nor list drafts.
+    Skip,
+    /// Render and list drafts like other entries, e.g. to preview a site locally.
+    Publish,
+}
+
+impl FromStr for DraftMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "unlisted" => Ok(DraftMode::Unlisted),
+            "obfuscate" => Ok(DraftMode::Obfuscate),
+            "skip" => Ok(DraftMode::Skip),
+            "publish" => Ok(DraftMode::Publish),
+            _ => Err(anyhow!(
+                "unknown draft mode: {s} (expected `unlisted`, `obfuscate`, `skip` or `publish`)"
+            )),
+        }
+    }
+}
+
+fn obfuscated_draft_url(secret: &str, url: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::new()
+        .chain_update(secret)
+        .chain_update("\0")
+        .chain_update(url)
+        .finalize();
+    format!("drafts/{}/", &format!("{hash:x}")[..32])
+}
+
+/// Fingerprinted URLs of CSS and JavaScript files in `src_dir`, such as "/style.css" =>
+/// "/style.0123abcd.css", where the hash is of the content.
+fn fingerprints(src_dir: &Path) -> Result<BTreeMap<String, String>> {
+    use sha2::{Digest, Sha256};
+    let mut fingerprints = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(src_dir) {
+        let entry = entry?;
+        let path = entry.path();
+        let Some(ext) = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .filter(|ext| ["css", "js"].contains(ext))
+        else {
+            continue;
+        };
+        let Some(relative_path) = path.strip_prefix(src_dir)?.to_str() else {
+            continue;
+        };
+        let hash = Sha256::digest(std::fs::read(path)?);
+        let stem = &relative_path[..relative_path.len() - ext.len() - 1];
+        fingerprints.insert(
+            format!("/{relative_path}"),
+            format!("/{stem}.{}.{ext}", &format!("{hash:x}")[..8]),
+        );
+    }
+    Ok(fingerprints)
+}
+
+/// A sibling output, emitted next to each `index.html` without going through templates.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize)]
+enum RawOutput {
+    /// `index.md`: The title and the markdown content.
+    #[serde(rename = "md")]
+    Markdown,
+    /// `index.txt`: The plain text of the content.
+    #[serde(rename = "txt")]
+    Text,
+}
+
+impl RawOutput {
+    fn extension(&self) -> &'static str {
+        match self {
+            RawOutput::Markdown => "md",
+            RawOutput::Text => "txt",
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct Config {
+    /// Free-form values, exposed as `site` in templates.
+    #[serde(flatten)]
+    site: BTreeMap<String, toml::Value>,
+    raw_outputs: Option<Vec<RawOutput>>,
+    markdown: Option<MarkdownOptions>,
+    update_date_from_git: Option<bool>,
+    /// Notice changes of the content of articles without `update_date`, by their hashes.
+    update_date_from_hash: Option<HashMode>,
+    /// The template for articles and pages whose `unpublish_date` has come. They are not
+    /// rendered without it.
+    tombstone: Option<String>,
+    /// Mark up the content of articles as microformats2 `h-entry`.
+    microformats: Option<bool>,
+    /// Escape values in `*.xml.jinja` templates for XML.
+    xml_autoescape: Option<bool>,
+    /// Write `publish-schedule.json`, listing articles dated in the future.
+    publish_schedule: Option<bool>,
+    protected: Option<ProtectedConfig>,
+    stale: Option<StaleConfig>,
+    comments: Option<CommentsConfig>,
+    #[serde(default)]
+    inject: Vec<Injection>,
+    csp: Option<CspConfig>,
+    /// The number of threads to build with. Defaults to the number of CPUs.
+    jobs: Option<usize>,
+    #[serde(default)]
+    non_utf8_paths: NonUtf8Paths,
+    #[serde(default)]
+    slugs: Slugs,
+    /// Default templates by path. The first matching rule wins.
+    #[serde(default)]
+    templates: Vec<TemplateRule>,
+    /// The order of `articles` in templates.
+    sort: Option<SortOrder>,
+    /// Environment variables which templates can read with `env()`.
+    #[serde(default)]
+    allowed_env: Vec<String>,
+    /// Metadata for all markdowns, used if a markdown doesn't have it.
+    defaults: Option<MetadataDefaults>,
+    /// Metadata for markdowns by path, which takes precedence over `defaults`.
+    #[serde(default)]
+    cascade: Vec<CascadeRule>,
+    #[serde(default)]
+    terminology: Terminology,
+    /// Abbreviations expanded into `<abbr>` in all articles, in addition to ones in markdowns.
+    #[serde(default, deserialize_with = "abbr::deserialize")]
+    abbreviations: Abbreviations,
+    /// Sites which I read, exposed as `blogroll` in templates and exported as `blogroll.opml`.
+    #[serde(default)]
+    blogroll: Vec<opml::Blog>,
+    webmention: Option<WebmentionConfig>,
+    fediverse: Option<FediverseConfig>,
+    humans: Option<HumansConfig>,
+    security: Option<SecurityConfig>,
+    manifest: Option<ManifestConfig>,
+    favicon: Option<FaviconConfig>,
+    /// The path where the site is hosted, such as "/blog", which is prepended to root-relative
+    /// URLs.
+    path_prefix: Option<String>,
+    github_pages: Option<GithubPagesConfig>,
+    /// Build profiles, which override the built-in ones.
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileConfig>,
+    /// Content copied into `src` before a build.
+    #[serde(default)]
+    remote: BTreeMap<String, RemoteSource>,
+    /// Pages without markdowns.
+    #[serde(default)]
+    virtual_pages: Vec<VirtualPage>,
+    series_pages: Option<SeriesPagesConfig>,
+    /// Kind => its settings.
+    #[serde(default)]
+    kinds: BTreeMap<String, KindConfig>,
+    /// Limits of the sizes of output files.
+    budgets: Option<BudgetConfig>,
+    /// Write `checksums.txt`, the SHA-256 checksums of all output files.
+    checksums: Option<bool>,
+    /// The redirect files written for `redirects.toml`. Defaults to `_redirects`.
+    redirect_formats: Option<Vec<RedirectFormat>>,
+    /// Minify rendered HTML, set by the build profile.
+    #[serde(skip)]
+    minify: bool,
+    /// Set by `BuildOptions::today`.
+    #[serde(skip)]
+    today: Option<chrono::NaiveDate>,
+    /// Fingerprinted URLs of assets, set by the build profile, such as "/style.css" =>
+    /// "/style.0123abcd.css".
+    #[serde(skip)]
+    fingerprints: BTreeMap<String, String>,
+    /// Set by `Site::extend_context`.
+    #[serde(skip)]
+    context_hooks: Vec<ContextHook>,
+}
+
+/// A function which adds variables to the template context of a page, given to
+/// `Site::extend_context`.
+pub type ContextHook = Box<dyn Fn(&Article, &mut BTreeMap<String, Value>) + Send + Sync>;
+
+/// Replace `${NAME}` and `${NAME:-default}` in strings in `value` with environment variables.
+fn interpolate_env(value: &mut toml::Value, var: &impl Fn(&str) -> Option<String>) -> Result<()> {
+    static VAR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap());
+    match value {
+        toml::Value::String(s) => {
+            let mut error = None;
+            let replaced = VAR.replace_all(s, |caps: &regex::Captures| {
+                var(&caps[1])
+                    .or_else(|| caps.get(2).map(|m| m.as_str().to_string()))
+                    .unwrap_or_else(|| {
+                        error.get_or_insert_with(|| {
+                            anyhow!("environment variable is not set: {}", &caps[1])
+                        });
+                        String::new()
+                    })
+            });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            *s = replaced.into_owned();
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                interpolate_env(value, var)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                interpolate_env(value, var)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `[[templates]]`: markdowns matching `path`, relative to `src`, use `template` by default.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct TemplateRule {
+    /// A glob pattern, such as `notes/**`.
+    #[serde(deserialize_with = "deserialize_pattern")]
+    path: glob::Pattern,
+    /// A template name without `.jinja`, such as `note`.
+    template: String,
+}
+
+/// Metadata which can be given by `[defaults]` and `[[cascade]]`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct MetadataDefaults {
+    author: Option<String>,
+    math: Option<bool>,
+    template: Option<String>,
+    tags: Option<Vec<String>>,
+    series: Option<String>,
+    kind: Option<String>,
+    keywords: Option<Vec<String>>,
+    toc: Option<bool>,
+    pdf: Option<bool>,
+    comments: Option<bool>,
+    lang: Option<String>,
+}
+
+impl MetadataDefaults {
+    /// Fill values which `metadata` doesn't have.
+    fn apply(&self, metadata: &mut Metadata) {
+        fn or<T: Clone>(value: &mut Option<T>, default: &Option<T>) {
+            if value.is_none() {
+                value.clone_from(default);
+            }
+        }
+        or(&mut metadata.author, &self.author);
+        or(&mut metadata.math, &self.math);
+        or(&mut metadata.template, &self.template);
+        or(&mut metadata.tags, &self.tags);
+        or(&mut metadata.series, &self.series);
+        or(&mut metadata.kind, &self.kind);
+        or(&mut metadata.keywords, &self.keywords);
+        or(&mut metadata.toc, &self.toc);
+        or(&mut metadata.pdf, &self.pdf);
+        or(&mut metadata.comments, &self.comments);
+        or(&mut metadata.lang, &self.lang);
+    }
+
+    /// Override values with ones in `other`.
+    fn extend(&mut self, other: MetadataDefaults) {
+        fn set<T>(value: &mut Option<T>, other: Option<T>) {
+            if other.is_some() {
+                *value = other;
+            }
+        }
+        set(&mut self.author, other.author);
+        set(&mut self.math, other.math);
+        set(&mut self.template, other.template);
+        set(&mut self.tags, other.tags);
+        set(&mut self.series, other.series);
+        set(&mut self.kind, other.kind);
+        set(&mut self.keywords, other.keywords);
+        set(&mut self.toc, other.toc);
+        set(&mut self.pdf, other.pdf);
+        set(&mut self.comments, other.comments);
+        set(&mut self.lang, other.lang);
+    }
+}
+
+/// `[[cascade]]`: markdowns matching `path`, relative to `src`, use the metadata by default.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "toml::Table")]
+struct CascadeRule {
+    /// A glob pattern, such as `notes/**`.
+    path: glob::Pattern,
+    metadata: MetadataDefaults,
+}
+
+// Not `#[serde(flatten)]`, which would accept unknown fields, such as typos.
+impl TryFrom<toml::Table> for CascadeRule {
+    type Error = Error;
+
+    fn try_from(mut table: toml::Table) -> Result<Self> {
+        let path = table
+            .remove("path")
+            .ok_or_else(|| anyhow!("missing field `path`"))?;
+        let path = path
+            .as_str()
+            .ok_or_else(|| anyhow!("invalid type: `path` must be a string"))?;
+        Ok(CascadeRule {
+            path: glob::Pattern::new(path)?,
+            metadata: table.try_into()?,
+        })
+    }
+}
+
+fn deserialize_pattern<'de, D>(deserializer: D) -> std::result::Result<glob::Pattern, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    glob::Pattern::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// `[csp]`: a Content-Security-Policy with hashes of the inline scripts and styles in the output.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct CspConfig {
+    /// e.g. `{ img-src = "'self' data:" }`. `default-src` is `'self'` unless given.
+    #[serde(default)]
+    directives: BTreeMap<String, String>,
+    #[serde(default = "CspConfig::default_formats")]
+    formats: Vec<CspFormat>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum CspFormat {
+    /// `_headers`, for Netlify and Cloudflare Pages.
+    Headers,
+    /// `csp.nginx.conf`.
+    Nginx,
+}
+
+impl CspConfig {
+    fn default_formats() -> Vec<CspFormat> {
+        vec![CspFormat::Headers]
+    }
+}
+
+/// `[[virtual_pages]]`: a page which has no markdown, rendered with a template, such as a list
+/// of tags.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct VirtualPage {
+    /// The URL, such as "tags/" or "feeds/rust.xml".
+    url: String,
+    template: String,
+    #[serde(default)]
+    title: String,
+    /// Selects `articles`.
+    query: Option<ArticleFilter>,
+}
+
+impl VirtualPage {
+    /// A page as if it were a markdown, such as `tags.md` for "tags/", which has only metadata.
+    fn markdown_file(&self) -> Result<MarkdownFile> {
+        let (dir, name) = self
+            .url
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .unwrap_or(("", self.url.trim_end_matches('/')));
+        anyhow::ensure!(
+            !name.is_empty() && !self.url.starts_with('/'),
+            "`url` of a virtual page must be relative, such as \"tags/\": {}",
+            self.url
+        );
+        let slug = if self.url.ends_with('/') {
+            format!("{name}/")
+        } else {
+            name.to_string()
+        };
+        Ok(MarkdownFile {
+            relative_path: Path::new(dir).join(format!("{name}.md")),
+            markdown: Markdown {
+                metadata: Metadata {
+                    page: Some(true),
+                    title: self.title.clone(),
+                    slug: Some(slug),
+                    template: Some(self.template.clone()),
+                    query: self.query.clone(),
+                    ..Metadata::default()
+                },
+                content: None,
+                content_line: 1,
+            },
+        })
+    }
+}
+
+/// The seed of the `shuffle` and `sample` filters: `seed`, or the URL of the entry being
+/// rendered, combined with the items, identified by their `url`s if they have them. The same
+/// content is shuffled in the same way in every build.
+fn sample_seed(state: &State, items: &[Value], kwargs: &Kwargs) -> Result<u64, minijinja::Error> {
+    let seed = match kwargs.get::<Option<Value>>("seed")? {
+        Some(seed) => seed.to_string(),
+        None => state
+            .lookup("entry")
+            .and_then(|entry| entry.get_attr("url").ok())
+            .map(|url| url.to_string())
+            .unwrap_or_default(),
+    };
+    kwargs.assert_all_used()?;
+    let items = items
+        .iter()
+        .map(|item| match item.get_attr("url") {
+            Ok(url) if !url.is_undefined() => url.to_string(),
+            _ => item.to_string(),
+        })
+        .collect::<Vec<_>>();
+    Ok(random::seed(
+        std::iter::once(seed.as_bytes()).chain(items.iter().map(String::as_bytes)),
+    ))
+}
+
+/// `[kinds.NAME]`: settings of articles whose `kind` is NAME, such as "note".
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct KindConfig {
+    /// The template of the articles, unless they have `template` or match `[[templates]]`.
+    /// Defaults to the name of the kind, if the template exists.
+    template: Option<String>,
+    /// Pages of the kind, such as a list and a feed, whose `query` selects the articles of the
+    /// kind.
+    #[serde(default)]
+    pages: Vec<VirtualPage>,
+}
+
+/// `[series_pages]`: a page for each series, where its articles are put together in the
+/// reading order, to read the whole series on one page.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct SeriesPagesConfig {
+    /// The directory of the pages, such as "series" for "series/my-tutorial/".
+    #[serde(default = "SeriesPagesConfig::default_dir")]
+    dir: String,
+    #[serde(default = "SeriesPagesConfig::default_template")]
+    template: String,
+}
+
+impl SeriesPagesConfig {
+    fn default_dir() -> String {
+        "series".to_string()
+    }
+
+    fn default_template() -> String {
+        "series".to_string()
+    }
+}
+
+/// How to make a slug from a file name, if `slug` isn't given.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Slugs {
+    /// Use the file name as it is.
+    #[default]
+    Keep,
+    /// Fold it into lowercase ASCII with dashes, where possible. See [`text::transliterate`].
+    Transliterate,
+    /// Percent-encode non-ASCII characters and symbols, so that URLs are ASCII. Output files
+    /// still have the decoded names.
+    PercentEncode,
+}
+
+impl Slugs {
+    fn slug(&self, file_stem: &str) -> String {
+        match self {
+            Slugs::Keep => file_stem.to_string(),
+            Slugs::Transliterate => text::transliterate(file_stem),
+            Slugs::PercentEncode => text::percent_encode(file_stem),
+        }
+    }
+}
+
+/// How to handle a file whose path, relative to `src`, is not valid UTF-8.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum NonUtf8Paths {
+    /// Skip it with a warning.
+    #[default]
+    Skip,
+    /// Replace invalid sequences with U+FFFD.
+    Lossy,
+}
+
+/// `path` itself if it's valid UTF-8. Otherwise, `None` if it should be skipped, or the lossy
+/// conversion of it.
+fn utf8_path(path: &Path, non_utf8_paths: NonUtf8Paths) -> Option<PathBuf> {
+    if path.to_str().is_some() {
+        return Some(path.to_path_buf());
+    }
+    match non_utf8_paths {
+        NonUtf8Paths::Skip => {
+            log::warn!("Skip a non-UTF-8 path: {}", path.display());
+            None
+        }
+        NonUtf8Paths::Lossy => {
+            let lossy = PathBuf::from(path.to_string_lossy().into_owned());
+            log::warn!("Non-UTF-8 path: {}", lossy.display());
+            Some(lossy)
+        }
+    }
+}
+
+/// See [`Site::template_usage`].
+#[derive(Debug)]
+pub struct TemplateUsage {
+    pub graph: TemplateGraph,
+    /// Markdown (relative to `src`) => the templates used to render it.
+    pub sources: BTreeMap<PathBuf, BTreeSet<String>>,
+    pub unused: Vec<String>,
+}
+
+/// An HTML snippet, such as analytics, injected into every rendered HTML.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Injection {
+    position: InjectionPosition,
+    html: String,
+    /// Build profiles where the snippet is injected.
+    #[serde(default = "Injection::default_profiles")]
+    profiles: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum InjectionPosition {
+    /// At the end of `<head>`.
+    Head,
+    /// At the end of `<body>`.
+    Body,
+}
+
+impl Injection {
+    fn default_profiles() -> Vec<String> {
+        vec!["production".to_string()]
+    }
+}
+
+/// Resolve `path`, relative to `dir`, refusing ones outside of `dir`, such as `../secret`.
+fn resolve_in(dir: &Path, path: &str) -> Result<PathBuf> {
+    let dir = dir.canonicalize()?;
+    let resolved = dir
+        .join(path)
+        .canonicalize()
+        .with_context(|| format!("not found: {path}"))?;
+    anyhow::ensure!(
+        resolved.starts_with(&dir),
+        "not in {}: {path}",
+        dir.display()
+    );
+    Ok(resolved)
+}
+
+/// The content of a text file, such as CSS or SVG, as it is, or a `data:` URL of a binary file,
+/// such as an image or a font, e.g. for `src` and `url()`.
+fn inline_asset(path: &Path) -> Result<String> {
+    let mime_type = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("css" | "svg" | "js" | "html" | "txt" | "json") => {
+            return std::fs::read_to_string(path)
+                .with_context(|| format!("can not read: {}", path.display()));
+        }
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    };
+    let bytes = std::fs::read(path).with_context(|| format!("can not read: {}", path.display()))?;
+    Ok(format!(
+        "data:{mime_type};base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+fn template_error(e: Error) -> minijinja::Error {
+    minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, format!("{e:#}"))
+}
+
+/// Insert `snippet` before the last `tag`, such as `</head>`. Does nothing if `tag` is missing.
+fn insert_before_last(html: &mut String, tag: &str, snippet: &str) {
+    if let Some(pos) = html.rfind(tag) {
+        html.insert_str(pos, snippet);
+    }
+}
+
+/// A comment system, exposed as `comments` to entries which enable comments.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct CommentsConfig {
+    /// e.g. "giscus"
+    provider: String,
+    /// HTML appended to the content. `{url}`, `{slug}` and `{title}` in it are replaced.
+    /// For "giscus", it's generated from the other values if omitted.
+    snippet: Option<String>,
+    /// Whether to append the snippet to the content. Otherwise, templates can use
+    /// `comments.html`.
+    #[serde(default)]
+    inject: bool,
+    /// Provider-specific values. e.g. `repo` and `category` for giscus.
+    #[serde(flatten)]
+    params: BTreeMap<String, String>,
+}
+
+impl CommentsConfig {
+    fn snippet(&self, article: &Article) -> Option<String> {
+        let snippet = match (&self.snippet, self.provider.as_str()) {
+            (Some(snippet), _) => snippet.clone(),
+            (None, "giscus") => {
+                let mut s = String::from(r#"<script src="https://giscus.app/client.js""#);
+                for (key, value) in &self.params {
+                    s.push_str(&format!(r#" data-{}="{}""#, key, html::escape(value)));
+                }
+                s.push_str(r#" crossorigin="anonymous" async></script>"#);
+                s
+            }
+            (None, _) => return None,
+        };
+        Some(
+            snippet
+                .replace("{url}", &article.url)
+                .replace("{slug}", &article.slug)
+                .replace("{title}", &html::escape(&article.title)),
+        )
+    }
+}
+
+/// Warns readers of old articles.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct StaleConfig {
+    years: u32,
+    /// HTML for a stale article, `entry.stale`. `{years}` and `{last_modified}` in it are
+    /// replaced.
+    banner: Option<String>,
+}
+
+impl StaleConfig {
+    fn is_stale(&self, last_modified: chrono::NaiveDate, today: chrono::NaiveDate) -> bool {
+        last_modified
+            .checked_add_months(chrono::Months::new(self.years * 12))
+            .is_some_and(|date| date < today)
+    }
+
+    fn banner(&self, last_modified: chrono::NaiveDate) -> Option<String> {
+        self.banner.as_ref().map(|banner| {
+            banner
+                .replace("{years}", &self.years.to_string())
+                .replace("{last_modified}", &last_modified.to_string())
+        })
+    }
+}
+
+impl Config {
+    /// Keys of typed values, i.e. the fields which are read from the config, which
+    /// `config_keys_test` checks.
+    const KEYS: &[&str] = &[
+        "raw_outputs",
+        "markdown",
+        "update_date_from_git",
+        "update_date_from_hash",
+        "tombstone",
+        "microformats",
+        "xml_autoescape",
+        "publish_schedule",
+        "protected",
+        "stale",
+        "comments",
+        "inject",
+        "csp",
+        "jobs",
+        "non_utf8_paths",
+        "slugs",
+        "templates",
+        "sort",
+        "allowed_env",
+        "defaults",
+        "cascade",
+        "terminology",
+        "abbreviations",
+        "blogroll",
+        "webmention",
+        "fediverse",
+        "humans",
+        "security",
+        "manifest",
+        "favicon",
+        "path_prefix",
+        "github_pages",
+        "profiles",
+        "remote",
+        "virtual_pages",
+        "series_pages",
+        "kinds",
+        "budgets",
+        "checksums",
+        "redirect_formats",
+    ];
+
+    /// Keys of free-form values which **Site** itself understands, with their allowed types.
+    const SITE_KEYS: &[(&str, &[&str])] = &[
+        ("author", &["string"]),
+        ("base_url", &["string"]),
+        ("description", &["string"]),
+        ("draft_secret", &["string"]),
+        ("lang", &["string"]),
+        ("params", &["table"]),
+        ("pdf_command", &["string", "array"]),
+        ("title", &["string"]),
+    ];
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("can not read config: {}", path.display()))?;
+        let s = text::normalize_line_endings(&s);
+        let context = || format!("can not parse config: {}", path.display());
+        let mut table = toml::from_str::<toml::Table>(&s).with_context(context)?;
+        for warning in
+            schema::check_keys(&s, &table, Config::KEYS, Config::SITE_KEYS).with_context(context)?
+        {
+            log::warn!("{}: {warning}", path.display());
+        }
+        // Deserialize directly, so that an error points to the line.
+        let parse = || {
+            toml::from_str::<Config>(&s).map_err(|e| anyhow!(schema::suggest_field(e.to_string())))
+        };
+        if !s.contains("${") {
+            return parse().with_context(context);
+        }
+        let mut value = toml::Value::Table(std::mem::take(&mut table));
+        interpolate_env(&mut value, &|name| std::env::var(name).ok()).with_context(context)?;
+        value
+            .try_into()
+            // Prefer the error with the line, unless it's caused by the interpolation.
+            .map_err(|e| match parse() {
+                Err(with_line) => with_line,
+                Ok(_) => anyhow!(schema::suggest_field(e.to_string())),
+            })
+            .with_context(context)
+    }
+
+    fn context(&self) -> minijinja::Value {
+        // `site.base_url` has `path_prefix`, so that absolute URLs in templates, such as in
+        // feeds and sitemaps, have it, as ones in generated files do.
+        let site = match self.site_url() {
+            Some(site_url) if !self.path_prefix().is_empty() => {
+                let mut site = self.site.clone();
+                site.insert("base_url".to_string(), site_url.into());
+                Value::from_serialize(site)
+            }
+            _ => Value::from_serialize(&self.site),
+        };
+        context! {
+            site,
+            blogroll => &self.blogroll,
+            favicon => self.favicon.as_ref().map(FaviconConfig::links),
+            path_prefix => self.path_prefix(),
+        }
+    }
+
+    /// Fill `metadata` with `[[cascade]]` rules matching `relative_path`, in order, then with
+    /// `[defaults]`.
+    fn apply_defaults(&self, relative_path: &Path, metadata: &mut Metadata) {
+        for rule in &self.cascade {
+            if rule.path.matches_path(relative_path) {
+                rule.metadata.apply(metadata);
+            }
+        }
+        if let Some(defaults) = self.defaults.as_ref() {
+            defaults.apply(metadata);
+        }
+    }
+
+    fn template_for(&self, relative_path: &Path) -> Option<&str> {
+        self.templates
+            .iter()
+            .find(|rule| rule.path.matches_path(relative_path))
+            .map(|rule| rule.template.as_str())
+    }
+
+    fn inject(&self, html: &mut String) {
+        for injection in &self.inject {
+            let tag = match injection.position {
+                InjectionPosition::Head => "</head>",
+                InjectionPosition::Body => "</body>",
+            };
+            insert_before_last(html, tag, &injection.html);
+        }
+    }
+
+    /// A free-form string value. Values of other types are `None`.
+    fn get(&self, key: &str) -> Option<&str> {
+        self.site.get(key).and_then(toml::Value::as_str)
+    }
+
+    /// The date of the build.
+    fn today(&self) -> chrono::NaiveDate {
+        self.today
+            .unwrap_or_else(|| chrono::Local::now().date_naive())
+    }
+
+    /// `pdf_command` as a program and its arguments. It's an array, or a string which is split
+    /// as a shell does, where quotes keep arguments with spaces together.
+    fn pdf_command(&self) -> Result<Option<Vec<String>>> {
+        let Some(command) = self.site.get("pdf_command") else {
+            return Ok(None);
+        };
+        let args = match command {
+            toml::Value::String(s) => shlex::split(s),
+            toml::Value::Array(args) => args
+                .iter()
+                .map(|arg| arg.as_str().map(String::from))
+                .collect(),
+            _ => None,
+        };
+        args.map(Some).ok_or_else(|| {
+            anyhow!("invalid `pdf_command`: {command} (expected an array of strings or a string)")
+        })
+    }
+
+    /// `path_prefix` without a trailing slash, such as "/blog", or "" if the site is hosted at
+    /// the root.
+    fn path_prefix(&self) -> &str {
+        self.path_prefix
+            .as_deref()
+            .unwrap_or_default()
+            .trim_end_matches('/')
+    }
+
+    /// The absolute URL of the site, with `path_prefix`, without a trailing slash, such as
+    /// "https://example.com/blog".
+    fn site_url(&self) -> Option<String> {
+        self.get("base_url")
+            .map(|base_url| format!("{}{}", base_url.trim_end_matches('/'), self.path_prefix()))
+    }
+
+    fn raw_outputs(&self) -> &[RawOutput] {
+        self.raw_outputs.as_deref().unwrap_or_default()
+    }
+
+    pub fn extend(&mut self, config: &mut Config) {
+        self.site.append(&mut config.site);
+        if config.raw_outputs.is_some() {
+            self.raw_outputs = config.raw_outputs.take();
+        }
+        if config.update_date_from_git.is_some() {
+            self.update_date_from_git = config.update_date_from_git;
+        }
+        if config.update_date_from_hash.is_some() {
+            self.update_date_from_hash = config.update_date_from_hash;
+        }
+        if config.tombstone.is_some() {
+            self.tombstone = config.tombstone.take();
+        }
+        if config.microformats.is_some() {
+            self.microformats = config.microformats;
+        }
+        if config.xml_autoescape.is_some() {
+            self.xml_autoescape = config.xml_autoescape;
+        }
+        if config.publish_schedule.is_some() {
+            self.publish_schedule = config.publish_schedule;
+        }
+        if config.protected.is_some() {
+            self.protected = config.protected.take();
+        }
+        if config.stale.is_some() {
+            self.stale = config.stale.take();
+        }
+        if config.comments.is_some() {
+            self.comments = config.comments.take();
+        }
+        self.inject.append(&mut config.inject);
+        if config.csp.is_some() {
+            self.csp = config.csp.take();
+        }
+        if config.jobs.is_some() {
+            self.jobs = config.jobs;
+        }
+        if config.webmention.is_some() {
+            self.webmention = config.webmention.take();
+        }
+        if config.fediverse.is_some() {
+            self.fediverse = config.fediverse.take();
+        }
+        if config.humans.is_some() {
+            self.humans = config.humans.take();
+        }
+        if config.security.is_some() {
+            self.security = config.security.take();
+        }
+        if config.manifest.is_some() {
+            self.manifest = config.manifest.take();
+        }
+        if config.favicon.is_some() {
+            self.favicon = config.favicon.take();
+        }
+        if config.path_prefix.is_some() {
+            self.path_prefix = config.path_prefix.take();
+        }
+        if config.github_pages.is_some() {
+            self.github_pages = config.github_pages.take();
+        }
+        for (name, profile) in std::mem::take(&mut config.profiles) {
+            let base = self.profiles.remove(&name).unwrap_or_default();
+            self.profiles.insert(name, profile.or(base));
+        }
+        self.remote.append(&mut config.remote);
+        self.virtual_pages.append(&mut config.virtual_pages);
+        if config.series_pages.is_some() {
+            self.series_pages = config.series_pages.take();
+        }
+        self.kinds.append(&mut config.kinds);
+        if config.budgets.is_some() {
+            self.budgets = config.budgets.take();
+        }
+        if config.checksums.is_some() {
+            self.checksums = config.checksums;
+        }
+        if config.redirect_formats.is_some() {
+            self.redirect_formats = config.redirect_formats.take();
+        }
+        if config.sort.is_some() {
+            self.sort = config.sort;
+        }
+        self.allowed_env.append(&mut config.allowed_env);
+        self.blogroll.append(&mut config.blogroll);
+        if config.non_utf8_paths != NonUtf8Paths::default() {
+            self.non_utf8_paths = config.non_utf8_paths;
+        }
+        if config.slugs != Slugs::default() {
+            self.slugs = config.slugs;
+        }
+        // Rules in the extending config take precedence.
+        self.templates.splice(0..0, config.templates.drain(..));
+        self.cascade.splice(0..0, config.cascade.drain(..));
+        self.terminology
+            .extend(std::mem::take(&mut config.terminology));
+        self.abbreviations
+            .extend(std::mem::take(&mut config.abbreviations));
+        if let Some(defaults) = config.defaults.take() {
+            self.defaults.get_or_insert_default().extend(defaults);
+        }
+        if let Some(markdown) = config.markdown.take() {
+            self.markdown = Some(self.markdown.unwrap_or_default().merge(&markdown));
+        }
+    }
+}
+
+/// Options for a build, usually given from the command line.
+#[derive(Default)]
+pub struct BuildOptions {
+    /// Only build markdowns whose path matches any of them, without copying other files.
+    /// Build everything if it's empty.
+    pub article_regex: Vec<Regex>,
+    /// Only render these markdowns, such as `src/notes/foo.md`, and pages listing them, while
+    /// building everything else as usual. An asset names the markdowns referencing it. Render
+    /// everything if it's empty.
+    pub only: Vec<PathBuf>,
+    pub draft_mode: DraftMode,
+    /// Also write a Gemini capsule into this directory.
+    pub gemini_out_dir: Option<PathBuf>,
+    /// The build profile, such as "dev" or "production".
+    pub profile: String,
+    /// Neither render nor list articles and pages whose `date` is in the future.
+    pub skip_future: bool,
+    /// Minify rendered HTML.
+    pub minify: bool,
+    /// Add a content hash to the names of CSS and JavaScript files, and rewrite their URLs.
+    pub fingerprint: bool,
+    /// Overrides `base_url` in the config.
+    pub base_url: Option<String>,
+    /// The number of threads, which overrides `jobs` in the config.
+    pub jobs: Option<usize>,
+    /// Cache rendered markdown in this directory across builds.
+    pub cache_dir: Option<PathBuf>,
+    /// Fail on problems which are warnings otherwise, such as duplicate titles.
+    pub strict: bool,
+    /// How problems in the content are reported.
+    pub message_format: MessageFormat,
+    /// Fetch `[remote]` sources again, instead of using the cached ones.
+    pub update_remotes: bool,
+    /// Build a full-text search index of the published articles into this directory.
+    #[cfg(feature = "search")]
+    pub search_index_dir: Option<PathBuf>,
+    /// The date of the build, e.g. for `upcoming_events`, `unpublish_date` and future
+    /// articles. Defaults to the local date.
+    pub today: Option<chrono::NaiveDate>,
+}
+
+impl BuildOptions {
+    /// Options bundled by the build profile `name`, such as "production": `[profiles.NAME]` in
+    /// the config, falling back to the built-in profile.
+    pub fn profile(config: &Config, name: &str) -> Result<BuildOptions> {
+        let profile = config.profiles.get(name).cloned();
+        let builtin = ProfileConfig::builtin(name);
+        anyhow::ensure!(
+            profile.is_some() || builtin.is_some(),
+            "unknown profile: {name} (expected `dev`, `preview`, `production` or one in [profiles])"
+        );
+        let profile = profile.unwrap_or_default().or(builtin.unwrap_or_default());
+        Ok(BuildOptions {
+            draft_mode: profile.drafts.unwrap_or_default(),
+            profile: name.to_string(),
+            skip_future: !profile.future.unwrap_or(true),
+            minify: profile.minify.unwrap_or(false),
+            fingerprint: profile.fingerprint.unwrap_or(false),
+            base_url: profile.base_url,
+            ..BuildOptions::default()
+        })
+    }
+}
+
+pub struct Site {
+    config: Config,
+    root_dir: PathBuf,
+    src_dir: PathBuf,
+    out_dir: PathBuf,
+    options: BuildOptions,
+    render_cache: Option<RenderCache>,
+    /// The state of `update_date_from_hash`, which is written after a build.
+    content_hashes: Option<Mutex<HashState>>,
+    /// Whether `template/slides.jinja` exists, for slide decks.
+    has_slides_template: bool,
+}
+
+impl Site {
+    pub fn new(
+        mut config: Config,
+        root_dir: PathBuf,
+        out_dir: PathBuf,
+        options: BuildOptions,
+    ) -> Result<Site> {
+        let root_dir = root_dir
+            .canonicalize()
+            .with_context(|| format!("can not find root dir: {}", root_dir.display()))?;
+        let src_dir = root_dir.join("src");
+        anyhow::ensure!(
+            config.path_prefix().is_empty() || config.path_prefix().starts_with('/'),
+            "`path_prefix` must start with `/`: {}",
+            config.path_prefix()
+        );
+        config
+            .inject
+            .retain(|injection| injection.profiles.contains(&options.profile));
+        if let Some(base_url) = options.base_url.as_ref() {
+            config
+                .site
+                .insert("base_url".to_string(), base_url.clone().into());
+        }
+        config.minify = options.minify;
+        config.today = Some(
+            options
+                .today
+                .unwrap_or_else(|| chrono::Local::now().date_naive()),
+        );
+        if options.fingerprint {
+            config.fingerprints = fingerprints(&src_dir)?;
+        }
+        if let Some(webmention) = config.webmention.as_ref() {
+            config.inject.push(Injection {
+                position: InjectionPosition::Head,
+                html: webmention.links(),
+                profiles: Vec::new(),
+            });
+        }
+        if let Some(fediverse) = config.fediverse.as_ref() {
+            config.inject.push(Injection {
+                position: InjectionPosition::Head,
+                html: fediverse.meta()?,
+                profiles: Vec::new(),
+            });
+        }
+        if let Some(favicon) = config.favicon.as_ref().filter(|f| f.inject) {
+            config.inject.push(Injection {
+                position: InjectionPosition::Head,
+                html: favicon.links(),
+                profiles: Vec::new(),
+            });
+        }
+        let render_cache = options
+            .cache_dir
+            .as_ref()
+            .map(|cache_dir| RenderCache::new(cache_dir, &src_dir));
+        let content_hashes = match config.update_date_from_hash {
+            Some(_) => Some(Mutex::new(HashState::read(
+                &root_dir.join(HashState::FILE),
+            )?)),
+            None => None,
+        };
+        let has_slides_template = root_dir.join("template/slides.jinja").exists();
+        Ok(Site {
+            config,
+            root_dir,
+            src_dir,
+            out_dir,
+            options,
+            render_cache,
+            content_hashes,
+            has_slides_template,
+        })
+    }
+
+    fn environment(&self) -> Environment<'static> {
+        let mut env = Environment::new();
+        env.set_loader(path_loader(self.root_dir.join("template")));
+        // With `xml_autoescape`, values in XML templates, such as `feed.xml.jinja`, are escaped,
+        // unless they are `safe`.
+        let xml_autoescape = self.config.xml_autoescape.unwrap_or(false);
+        env.set_auto_escape_callback(move |name| {
+            if xml_autoescape && name.ends_with(".xml.jinja") {
+                AutoEscape::Custom("xml")
+            } else {
+                AutoEscape::None
+            }
+        });
+        env.set_formatter(|out, state, value| match state.auto_escape() {
+            AutoEscape::Custom("xml") if !value.is_safe() => {
+                write!(out, "{}", html::escape(&value.to_string())).map_err(Into::into)
+            }
+            _ => minijinja::escape_formatter(out, state, value),
+        });
+        env.set_keep_trailing_newline(true);
+        let markdown_options = self.config.markdown.unwrap_or_default();
+        env.add_filter("markdown", move |s: &str| {
+            Markdown::render_snippet(s, &markdown_options)
+        });
+        env.add_filter("plain_text", |s: &str| text::html_to_plain(s));
+        env.add_filter("xml_escape", |s: &str| {
+            Value::from_safe_string(html::escape(s))
+        });
+        env.add_filter("cdata", |s: &str| Value::from_safe_string(xml::cdata(s)));
+        env.add_filter("rfc2822", |date: &str| {
+            chrono::NaiveDate::from_str(date)
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().to_rfc2822())
+                .with_context(|| format!("invalid date: {date}"))
+                .map_err(template_error)
+        });
+        let lang = self.config.get("lang").unwrap_or("en").to_string();
+        env.add_filter("dateformat", move |date: &str, kwargs: Kwargs| {
+            let format = kwargs.get::<Option<&str>>("format")?.unwrap_or("long");
+            let locale = kwargs.get::<Option<&str>>("locale")?.unwrap_or(&lang);
+            kwargs.assert_all_used()?;
+            locale::format_date(date, format, locale).map_err(template_error)
+        });
+        env.add_filter(
+            "shuffle",
+            |state: &State, mut items: Vec<Value>, kwargs: Kwargs| {
+                let seed = sample_seed(state, &items, &kwargs)?;
+                random::shuffle(&mut items, seed);
+                Ok::<_, minijinja::Error>(Value::from(items))
+            },
+        );
+        env.add_filter(
+            "sample",
+            |state: &State, mut items: Vec<Value>, n: usize, kwargs: Kwargs| {
+                let seed = sample_seed(state, &items, &kwargs)?;
+                random::shuffle(&mut items, seed);
+                items.truncate(n);
+                Ok::<_, minijinja::Error>(Value::from(items))
+            },
+        );
+        let root_dir = self.root_dir.clone();
+        env.add_function("read_file", move |path: &str| {
+            let path = resolve_in(&root_dir, path).map_err(template_error)?;
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("can not read: {}", path.display()))
+                .map_err(template_error)
+        });
+        let allowed_env = self.config.allowed_env.clone();
+        env.add_function("env", move |name: &str, default: Option<&str>| {
+            if !allowed_env.iter().any(|allowed| allowed == name) {
+                return Err(template_error(anyhow!(
+                    "environment variable is not allowed: {name} (add it to `allowed_env` in the config)"
+                )));
+            }
+            Ok(std::env::var(name).ok().or(default.map(String::from)))
+        });
+        let src_dir = self.src_dir.clone();
+        env.add_function("inline_asset", move |path: &str| {
+            inline_asset(&resolve_in(&src_dir, path).map_err(template_error)?)
+                .map_err(template_error)
+        });
+        let src_dir = self.src_dir.clone();
+        env.add_function("asset", move |path: &str| {
+            let path = path.trim_start_matches('/');
+            let file = resolve_in(&src_dir, path).map_err(template_error)?;
+            let content = std::fs::read(&file)
+                .with_context(|| format!("can not read: {}", file.display()))
+                .map_err(template_error)?;
+            // The URL is fingerprinted and prefixed later, as other root-relative URLs.
+            Ok(context! {
+                url => format!("/{path}"),
+                integrity => integrity::sri(&content),
+            })
+        });
+        env
+    }
+
+    /// Run `f` in a thread pool with `jobs` threads, if it's given. Otherwise, in the global one.
+    fn in_thread_pool<T: Send>(&self, f: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+        match self.options.jobs.or(self.config.jobs) {
+            Some(jobs) => {
+                log::info!("Build with {jobs} threads");
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(jobs)
+                    .build()?
+                    .install(f)
+            }
+            None => f(),
+        }
+    }
+
+    pub fn build(&self) -> Result<()> {
+        // Only a build fetches `[remote]` sources, so that other commands don't touch `src`.
+        for (name, source) in &self.config.remote {
+            source.sync(
+                name,
+                &self.root_dir,
+                &self.src_dir,
+                self.options.update_remotes,
+            )?;
+        }
+        self.in_thread_pool(|| {
+            let env = self.environment();
+            self.render_markdowns(&env)?;
+            if let Some(state) = self.content_hashes.as_ref() {
+                let mut state = state.lock().unwrap();
+                if self.options.article_regex.is_empty() {
+                    state.retain_checked();
+                }
+                state.write(&self.root_dir.join(HashState::FILE))?;
+            }
+            if self.options.article_regex.is_empty() {
+                self.copy_files(&self.out_dir)?;
+                if let Some(gemini_out_dir) = self.options.gemini_out_dir.as_ref() {
+                    self.copy_files(gemini_out_dir)?;
+                }
+                if let Some(csp_config) = self.config.csp.as_ref() {
+                    self.write_csp(csp_config)?;
+                }
+                if !self.config.blogroll.is_empty() {
+                    self.write_blogroll()?;
+                }
+                self.write_static_files()?;
+                if let Some(redirects) = Redirects::read(&self.root_dir.join(redirect::FILE))? {
+                    self.report(Path::new(""), redirects.check(&self.out_dir))?;
+                    redirects.write(
+                        &self.src_dir,
+                        &self.out_dir,
+                        self.config
+                            .redirect_formats
+                            .as_deref()
+                            .unwrap_or(&[RedirectFormat::Redirects]),
+                        self.config.path_prefix(),
+                    )?;
+                }
+                if let Some(budgets) = self.config.budgets.as_ref() {
+                    self.report(&self.out_dir, budgets.check(&self.out_dir)?)?;
+                }
+                if self.config.checksums.unwrap_or(false) {
+                    let out_file = self.out_dir.join(integrity::CHECKSUMS);
+                    log::info!("{:32} => {}", "Checksums", out_file.display());
+                    std::fs::write(&out_file, integrity::checksums(&self.out_dir)?)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Write the Content-Security-Policy for all HTML files in `out_dir`.
+    fn write_csp(&self, csp_config: &CspConfig) -> Result<()> {
+        let mut hashes = csp::InlineHashes::default();
+        for entry in walkdir::WalkDir::new(&self.out_dir) {
+            let entry = entry?;
+            if entry.path().extension().is_some_and(|ext| ext == "html") {
+                hashes.add_html(&std::fs::read_to_string(entry.path())?);
+            }
+        }
+        let policy = csp::policy(&csp_config.directives, &hashes);
+        for format in &csp_config.formats {
+            let (name, content) = match format {
+                CspFormat::Headers => ("_headers", csp::headers_file(&policy)),
+                CspFormat::Nginx => ("csp.nginx.conf", csp::nginx_snippet(&policy)),
+            };
+            log::info!(
+                "{:32} => {}",
+                "Content-Security-Policy",
+                self.out_dir.join(name).display()
+            );
+            write_generated(&self.src_dir, &self.out_dir, name, &content)?;
+        }
+        Ok(())
+    }
+
+    fn write_events(&self, articles: &[&Article]) -> Result<()> {
+        let site_url = self.config.site_url();
+        let urls = articles
+            .iter()
+            .map(|a| match site_url.as_ref() {
+                Some(site_url) => format!("{site_url}/{}", a.url),
+                None => format!("{}/{}", self.config.path_prefix(), a.url),
+            })
+            .collect::<Vec<_>>();
+        let events = articles
+            .iter()
+            .zip(&urls)
+            .map(|(a, url)| {
+                let start = a.event_start.unwrap();
+                ical::Event {
+                    uid: url,
+                    summary: &a.title,
+                    description: a.description.as_deref(),
+                    location: a.event_location.as_deref(),
+                    url: site_url.as_ref().map(|_| url.as_str()),
+                    start,
+                    end: a.event_end,
+                    stamp: a.last_modified.unwrap_or(start.date()),
+                }
+            })
+            .collect::<Vec<_>>();
+        let out_file = self.out_dir.join("events.ics");
+        log::info!("{:32} => {}", "Events", out_file.display());
+        std::fs::write(
+            out_file,
+            ical::calendar(self.config.get("title").unwrap_or("Events"), &events),
+        )?;
+        Ok(())
+    }
+
+    /// Write files generated from the config, such as `humans.txt`.
+    fn write_static_files(&self) -> Result<()> {
+        let config = &self.config;
+        let mut files = Vec::new();
+        if let Some(fediverse) = config.fediverse.as_ref().filter(|f| f.webfinger) {
+            files.push((".well-known/webfinger", fediverse.webfinger()?));
+        }
+        if let Some(humans) = config.humans.as_ref() {
+            files.push(("humans.txt", humans.humans_txt()));
+        }
+        if let Some(security) = config.security.as_ref() {
+            files.push((
+                ".well-known/security.txt",
+                security.security_txt(config.site_url().as_deref(), config.today())?,
+            ));
+        }
+        if let Some(manifest) = config.manifest.as_ref() {
+            let mut manifest = manifest.clone();
+            if let Some(favicon) = config
+                .favicon
+                .as_ref()
+                .filter(|_| manifest.icons.is_empty())
+            {
+                manifest.icons = favicon.manifest_icons();
+            }
+            manifest.prefix_urls(config.path_prefix());
+            files.push((
+                "site.webmanifest",
+                manifest.webmanifest(config.get("title"), config.get("description"))?,
+            ));
+        }
+        if let Some(favicon) = config.favicon.as_ref() {
+            favicon.generate(&self.src_dir, &self.out_dir)?;
+        }
+        if let Some(github_pages) = config.github_pages.as_ref() {
+            files.extend(github_pages.files());
+        }
+        for (name, content) in files {
+            let out_file = self.out_dir.join(name);
+            log::info!("{:32} => {}", name, out_file.display());
+            std::fs::create_dir_all(out_file.parent().unwrap())?;
+            std::fs::write(out_file, content)?;
+        }
+        // It's for CI, not for readers, so it's not deployed.
+        if config.publish_schedule.unwrap_or(false) {
+            let name = "publish-schedule.json";
+            let file = self.root_dir.join(name);
+            log::info!("{:32} => {}", name, file.display());
+            std::fs::write(file, self.publish_schedule(config.today())?)?;
+        }
+        Ok(())
+    }
+
+    fn write_blogroll(&self) -> Result<()> {
+        let title = match self.config.get("title") {
+            Some(title) => format!("{title} - Blogroll"),
+            None => "Blogroll".to_string(),
+        };
+        let out_file = self.out_dir.join("blogroll.opml");
+        log::info!("{:32} => {}", "Blogroll", out_file.display());
+        std::fs::write(out_file, opml::opml(&title, &self.config.blogroll))?;
+        Ok(())
+    }
+
+    /// Use the `slides` template for a slide deck, or the template of the article's `kind`, by
+    /// default, unless `[[templates]]` gives one.
+    fn set_default_template(&self, article: &mut Article) {
+        if article.default_template.is_some() {
+            return;
+        }
+        if article.slides {
+            if self.has_slides_template {
+                article.default_template = Some("slides".to_string());
+                return;
+            }
+            log::warn!(
+                "{}: `slides = true`, but template/slides.jinja is not found. The default \
+                 template is used",
+                article.source_path.display()
+            );
+        }
+        let Some(kind) = &article.kind else {
+            return;
+        };
+        article.default_template = match self.config.kinds.get(kind) {
+            Some(KindConfig {
+                template: Some(template),
+                ..
+            }) => Some(template.clone()),
+            _ => self
+                .root_dir
+                .join("template")
+                .join(format!("{kind}.jinja"))
+                .exists()
+                .then(|| kind.clone()),
+        };
+    }
+
+    fn new_article(&self, mut m: MarkdownFile) -> Result<Article> {
+        if let Some(content) = m.markdown.content.as_mut() {
+            *content = transclude::expand(content, &m.relative_path, &|path| {
+                let s = std::fs::read_to_string(self.src_dir.join(path))?;
+                Ok(s.parse::<Markdown>()?.content.unwrap_or_default())
+            })
+            .with_context(|| format!("invalid transclusion: {}", m.relative_path.display()))?;
+        }
+        // A cover, an audio and a PDF are relative to the markdown, or to `src` if they start
+        // with "/". They must be in `src`.
+        let resolve = |path: &String| -> Result<PathBuf> {
+            let joined = match path.strip_prefix('/') {
+                Some(path) => PathBuf::from(path),
+                None => m.relative_path.parent().unwrap_or(Path::new("")).join(path),
+            };
+            let mut resolved = PathBuf::new();
+            for component in joined.components() {
+                match component {
+                    std::path::Component::Normal(c) => resolved.push(c),
+                    std::path::Component::CurDir => {}
+                    std::path::Component::ParentDir if resolved.pop() => {}
+                    _ => return Err(anyhow!("not in src: {path}: {}", m.relative_path.display())),
+                }
+            }
+            Ok(resolved)
+        };
+        let cover = m
+            .markdown
+            .metadata
+            .cover
+            .as_ref()
+            .map(resolve)
+            .transpose()?;
+        let audio = m
+            .markdown
+            .metadata
+            .audio
+            .as_ref()
+            .map(resolve)
+            .transpose()?;
+        let slides_pdf = m
+            .markdown
+            .metadata
+            .slides_pdf
+            .as_ref()
+            .map(resolve)
+            .transpose()?;
+        let relative_path = m.relative_path.clone();
+        let mut article = Article::new(m, &self.config, self.render_cache.as_ref())?;
+        self.set_default_template(&mut article);
+        if let Some(cover) = cover {
+            article.cover = Some(
+                Image::new(&self.src_dir, &cover)
+                    .with_context(|| format!("invalid cover: {}", relative_path.display()))?,
+            );
+        }
+        if let Some(audio) = audio {
+            article.audio = Some(
+                Audio::new(&self.src_dir, &audio)
+                    .with_context(|| format!("invalid audio: {}", relative_path.display()))?,
+            );
+        }
+        if let Some(slides_pdf) = slides_pdf {
+            anyhow::ensure!(
+                self.src_dir.join(&slides_pdf).is_file(),
+                "`slides_pdf` not found: {}: {}",
+                slides_pdf.display(),
+                relative_path.display()
+            );
+            article.slides_pdf = Some(slash_path(&slides_pdf));
+        }
+        if article.update_date.is_none() && self.config.update_date_from_git.unwrap_or(false) {
+            // Only a commit after `date` counts as an update.
+            if let Some(date) = git_last_commit_date(&self.src_dir.join(&relative_path))
+                .filter(|date| article.date.is_none_or(|d| d < *date))
+            {
+                article.set_update_date(Some(date));
+            }
+        }
+        if let (Some(mode), Some(state)) = (
+            self.config.update_date_from_hash,
+            self.content_hashes.as_ref(),
+        ) {
+            if !article.page && !article.draft {
+                let change = state.lock().unwrap().check(
+                    &slash_path(&relative_path),
+                    &article.markdown,
+                    article.update_date,
+                    mode,
+                    self.config.today(),
+                );
+                match change {
+                    Change::None => {}
+                    Change::Unstamped => log::warn!(
+                        "src/{}: the content has changed, but `update_date` is not updated",
+                        slash_path(&relative_path)
+                    ),
+                    Change::Stamped(date) => {
+                        if article.date.is_none_or(|d| d < date)
+                            && article.update_date.is_none_or(|d| d < date)
+                        {
+                            article.set_update_date(Some(date));
+                        }
+                    }
+                }
+            }
+        }
+        if let (Some(stale), Some(last_modified)) = (&self.config.stale, article.last_modified) {
+            if !article.page && stale.is_stale(last_modified, self.config.today()) {
+                article.is_stale = true;
+                article.stale = stale.banner(last_modified);
+            }
+        }
+        if let Some(comments) = self.config.comments.as_ref().filter(|c| c.inject) {
+            if let Some(snippet) = comments.snippet(&article).filter(|_| article.comments) {
+                article.content.push_str(&snippet);
+            }
+        }
+        if article.protected {
+            let protected = self.config.protected.as_ref().with_context(|| {
+                format!(
+                    "{}: `protected = true` needs `[protected]` in the config",
+                    relative_path.display()
+                )
+            })?;
+            article.content = protected
+                .protect(&article.content)
+                .with_context(|| format!("can not protect: {}", relative_path.display()))?;
+            // The source would leak into raw outputs and gemtext.
+            article.markdown.clear();
+        }
+        Ok(article)
+    }
+
+    /// Add variables to the template context of every article and page, computed by `hook`,
+    /// such as star counts of GitHub repositories read from a cached JSON. They take precedence
+    /// over the built-in ones of the same names.
+    pub fn extend_context(
+        &mut self,
+        hook: impl Fn(&Article, &mut BTreeMap<String, Value>) + Send + Sync + 'static,
+    ) {
+        self.config.context_hooks.push(Box::new(hook));
+    }
+
+    /// Report which templates each markdown uses, following `{% extends %}`/`{% include %}`
+    /// chains, and which templates no markdown uses.
+    pub fn template_usage(&self) -> Result<TemplateUsage> {
+        let graph = TemplateGraph::load(&self.root_dir.join("template"))?;
+        let sources = self
+            .collect_markdown(&self.src_dir)?
+            .into_par_iter()
+            .map(|m| -> Result<(PathBuf, BTreeSet<String>)> {
+                let relative_path = m.relative_path.clone();
+                let mut article = Article::new(m, &self.config, self.render_cache.as_ref())?;
+                self.set_default_template(&mut article);
+                let templates = article
+                    .root_templates()
+                    .iter()
+                    .flat_map(|root| graph.closure(root))
+                    .filter(|name| graph.dependencies.contains_key(name))
+                    .collect();
+                Ok((relative_path, templates))
+            })
+            .collect::<Result<BTreeMap<PathBuf, BTreeSet<String>>>>()?;
+        // `toc.jinja` is looked up by name, even if no markdown uses it now.
+        let unused = graph.unused(
+            sources
+                .values()
+                .flatten()
+                .map(String::as_str)
+                .chain(["toc.jinja"]),
+        );
+        Ok(TemplateUsage {
+            graph,
+            sources,
+            unused,
+        })
+    }
+
+    /// Export the published articles which match `filter` into a single file, `out_dir/name`.
+    pub fn export(&self, format: ExportFormat, filter: &ArticleFilter, name: &str) -> Result<()> {
+        self.in_thread_pool(|| self.export_in_thread_pool(format, filter, name))
+    }
+
+    /// `publish-schedule.json`: articles, which are not drafts, to be published (`date`) or
+    /// unpublished (`unpublish_date`) after `today`, oldest first, so that a scheduled CI job can
+    /// rebuild only when `next` has come.
+    fn publish_schedule(&self, today: chrono::NaiveDate) -> Result<String> {
+        #[derive(Serialize)]
+        struct Scheduled {
+            date: chrono::NaiveDate,
+            /// "publish" or "unpublish".
+            action: &'static str,
+            title: String,
+            #[serde(serialize_with = "serialize_slash_path")]
+            source_path: PathBuf,
+        }
+
+        let mut scheduled = Vec::new();
+        for m in self.collect_markdown(&self.src_dir)? {
+            let metadata = &m.markdown.metadata;
+            if metadata.page.unwrap_or(false) || metadata.draft.unwrap_or(false) {
+                continue;
+            }
+            for (date, action) in [
+                (metadata.date, "publish"),
+                (metadata.unpublish_date, "unpublish"),
+            ] {
+                if let Some(date) = date.filter(|date| *date > today) {
+                    scheduled.push(Scheduled {
+                        date,
+                        action,
+                        title: metadata.title.clone(),
+                        source_path: m.relative_path.clone(),
+                    });
+                }
+            }
+        }
+        scheduled.sort_by(|a, b| a.date.cmp(&b.date).then(a.source_path.cmp(&b.source_path)));
+        Ok(serde_json::to_string_pretty(&serde_json::json!({
+            "next": scheduled.first().map(|s| s.date),
+            "articles": scheduled,
+        }))?)
+    }
+
+    /// Articles which a build publishes, newest first.
+    pub fn published_articles(&self) -> Result<Vec<Article>> {
+        let articles = self.in_thread_pool(|| {
+            let (_, articles) = self.collect_entries()?;
+            articles
+                .into_par_iter()
+                .map(|m| self.new_article(m))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        let (mut articles, _) = self.split_drafts(articles);
+        sort_by_date(&mut articles);
+        Ok(articles)
+    }
+
+    /// Content analytics of the published articles, for `site stats`, listing `top` entries of
+    /// rankings.
+    pub fn content_stats(&self, top: usize) -> Result<ContentStats> {
+        let articles = self.published_articles()?;
+        Ok(ContentStats::new(
+            &articles,
+            self.config.site_url().as_deref(),
+            top,
+        ))
+    }
+
+    /// Publish `out_dir`, which `build` has written, to the `[github_pages]` branch.
+    pub fn deploy_github(&self, message: &str, push: bool) -> Result<()> {
+        let github_pages = self.config.github_pages.as_ref().ok_or_else(|| {
+            anyhow!("[github_pages] must be set in the config to deploy to GitHub Pages")
+        })?;
+        github_pages.deploy(&self.root_dir, &self.out_dir, message, push)
+    }
+
+    /// (source, target) pairs of webmentions for links to other sites in the articles which the
+    /// build profile publishes. Protected articles are not public, so their links are not either.
+    #[cfg(feature = "webmention")]
+    fn webmentions(&self, site_url: &str) -> Result<Vec<(String, String)>> {
+        let mut webmentions = Vec::new();
+        for article in self.published_articles()? {
+            if article.is_protected() {
+                continue;
+            }
+            let source = format!("{site_url}/{}", article.url);
+            for target in crate::webmention::external_links(&article.content, site_url) {
+                webmentions.push((source.clone(), target));
+            }
+        }
+        Ok(webmentions)
+    }
+
+    /// Send webmentions for links in published articles to other sites, skipping ones which
+    /// have been sent, as recorded in the state file.
+    #[cfg(feature = "webmention")]
+    pub fn send_webmentions(&self, dry_run: bool) -> Result<()> {
+        let site_url = self
+            .config
+            .site_url()
+            .ok_or_else(|| anyhow!("`base_url` must be set in the config to send webmentions"))?;
+        let state_file = self.root_dir.join(
+            self.config
+                .webmention
+                .as_ref()
+                .and_then(|webmention| webmention.state_file.as_deref())
+                .unwrap_or(WebmentionConfig::DEFAULT_STATE_FILE),
+        );
+        let mut state = match std::fs::read_to_string(&state_file) {
+            Ok(s) => serde_json::from_str::<crate::webmention::State>(&s)
+                .with_context(|| format!("invalid state file: {}", state_file.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (mut sent, mut failed) = (0, 0);
+        for (source, target) in self.webmentions(&site_url)? {
+            if state.is_sent(&source, &target) {
+                continue;
+            }
+            if dry_run {
+                println!("{source} -> {target}");
+                continue;
+            }
+            match crate::webmention::send(&source, &target) {
+                Ok(true) => {
+                    log::info!("sent: {source} -> {target}");
+                    sent += 1;
+                }
+                Ok(false) => log::debug!("no endpoint: {target}"),
+                Err(e) => {
+                    // It's retried next time.
+                    log::warn!("can not send: {source} -> {target}: {e}");
+                    failed += 1;
+                    continue;
+                }
+            }
+            state.mark_sent(&source, &target);
+        }
+        if !dry_run {
+            std::fs::write(&state_file, serde_json::to_string_pretty(&state)?)?;
+            log::info!("Sent {sent} webmentions, {failed} failed");
+        }
+        Ok(())
+    }
+
+    fn export_in_thread_pool(
+        &self,
+        format: ExportFormat,
+        filter: &ArticleFilter,
+        name: &str,
+    ) -> Result<()> {
+        let mut articles = self
+            .collect_markdown(&self.src_dir)?
+            .into_par_iter()
+            .filter(|m| {
+                !m.markdown.metadata.page.unwrap_or(false)
+                    && !m.markdown.metadata.draft.unwrap_or(false)
+            })
+            .map(|m| self.new_article(m))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|a| filter.matches(a))
+            .collect::<Vec<_>>();
+        anyhow::ensure!(!articles.is_empty(), "no articles match the filter");
+        // Oldest first, as a reading order.
+        articles.sort_by(|a, b| newest_first(b, a));
+        if format == ExportFormat::Newsletter && filter.since.is_none() {
+            // Only the newest one, unless it's a digest.
+            articles.drain(..articles.len() - 1);
+        }
+        log::info!("Export {} articles", articles.len());
+
+        let out_file = self.out_dir.join(name);
+        std::fs::create_dir_all(&self.out_dir)?;
+        match format {
+            ExportFormat::Epub => self
+                .epub(&articles, filter)?
+                .write(std::io::BufWriter::new(std::fs::File::create(&out_file)?))?,
+            ExportFormat::Newsletter => std::fs::write(&out_file, self.newsletter(&articles)?)?,
+        }
+        log::info!("Exported: {}", out_file.display());
+        Ok(())
+    }
+
+    fn newsletter(&self, articles: &[Article]) -> Result<String> {
+        let site_url = self
+            .config
+            .site_url()
+            .ok_or_else(|| anyhow!("`base_url` must be set in the config for a newsletter"))?;
+        let mut body = String::new();
+        for article in articles {
+            let url = format!("{site_url}/{}", article.url);
+            let content = newsletter::strip_scripts(&article.content);
+            let content = html::prefix_urls(&content, self.config.path_prefix());
+            let content = newsletter::absolutize_urls(&content, &url);
+            body.push_str(&format!(
+                "<h1><a href=\"{}\">{}</a></h1>\n{}",
+                html::escape(&url),
+                html::escape(&article.title),
+                content
+            ));
+        }
+        let title = match articles {
+            [article] => article.title.as_str(),
+            _ => self.config.get("title").unwrap_or("Newsletter"),
+        };
+        Ok(format!(
+            r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+</head>
+<body style="margin:0;padding:16px;font-family:sans-serif;color:#222;">
+<div style="max-width:640px;margin:0 auto;">
+{body}</div>
+</body>
+</html>
+"#,
+            lang = html::escape(
+                match articles {
+                    [article] => article.lang.as_deref(),
+                    _ => self.config.get("lang"),
+                }
+                .unwrap_or("en")
+            ),
+            title = html::escape(title),
+            body = newsletter::inline_styles(&body),
+        ))
+    }
+
+    fn epub(&self, articles: &[Article], filter: &ArticleFilter) -> Result<epub::Book> {
+        static IMG_SRC: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r#"(<img\b[^>]*?\bsrc=")([^"]+)(")"#).unwrap());
+
+        let mut images = Vec::<epub::Image>::new();
+        let mut chapters = Vec::new();
+        for article in articles {
+            let mut error = None;
+            let content = IMG_SRC.replace_all(&article.content, |caps: &regex::Captures<'_>| {
+                let src = &caps[2];
+                if src.contains("://") || src.starts_with("data:") {
+                    return caps[0].to_string();
+                }
+                let path = if let Some(path) = src.strip_prefix('/') {
+                    self.src_dir.join(path)
+                } else {
+                    self.src_dir
+                        .join(text::percent_decode(&article.url))
+                        .join(src)
+                };
+                match std::fs::read(&path) {
+                    Ok(data) => {
+                        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+                        let image_path = format!("images/{}.{}", images.len(), ext);
+                        let replaced = format!("{}{}{}", &caps[1], image_path, &caps[3]);
+                        images.push(epub::Image {
+                            path: image_path,
+                            data,
+                        });
+                        replaced
+                    }
+                    Err(e) => {
+                        error.get_or_insert(anyhow!("can not read {}: {e}", path.display()));
+                        caps[0].to_string()
+                    }
+                }
+            });
+            if let Some(e) = error {
+                return Err(e.context(format!("can not embed an image in {}", article.url)));
+            }
+            chapters.push(epub::Chapter {
+                title: article.title.clone(),
+                content: format!("<h1>{}</h1>\n{}", html::escape(&article.title), content),
+            });
+        }
+
+        let title = filter
+            .series
+            .clone()
+            .or_else(|| self.config.get("title").map(str::to_string))
+            .unwrap_or_else(|| "Site".to_string());
+        Ok(epub::Book {
+            identifier: format!(
+                "urn:site:{}",
+                self.config
+                    .get("base_url")
+                    .unwrap_or(&title)
+                    .trim_end_matches('/')
+            ),
+            title,
+            language: self.config.get("lang").unwrap_or("en").to_string(),
+            author: self.config.get("author").map(str::to_string),
+            chapters,
+            images,
+        })
+    }
+
+    fn collect_markdown(&self, src_dir: impl AsRef<Path>) -> Result<Vec<MarkdownFile>> {
+        let src_dir = src_dir.as_ref();
+        let mut files = Vec::new();
+        for entry in walkdir::WalkDir::new(src_dir)
+            .follow_links(true)
+            .sort_by_file_name()
+        {
+            let entry = entry?;
+            let f = entry.path();
+            if !entry.file_type().is_file() || f.extension().is_none_or(|ext| ext != "md") {
+                continue;
+            }
+            let regexes = &self.options.article_regex;
+            if !regexes.is_empty()
+                && !regexes
+                    .iter()
+                    .any(|regex| regex.is_match(&f.to_string_lossy()))
+            {
+                continue;
+            }
+            let relative_path = f
+                .strip_prefix(src_dir)
+                .with_context(|| format!("{} is not in {}", f.display(), src_dir.display()))?;
+            let Some(relative_path) = utf8_path(relative_path, self.config.non_utf8_paths) else {
+                continue;
+            };
+            log::debug!("found: {}", relative_path.display());
+            let mut markdown = std::fs::read_to_string(f)
+                .with_context(|| format!("can not read: {}", f.display()))?
+                .parse::<Markdown>()
+                .with_context(|| format!("can not parse: {}", f.display()))?;
+            self.config
+                .apply_defaults(&relative_path, &mut markdown.metadata);
+            files.push(MarkdownFile {
+                relative_path,
+                markdown,
+            });
+        }
+        Ok(files)
+    }
+
+    /// Collect markdowns in `src`, partitioned into pages and articles.
+    fn collect_entries(&self) -> Result<(Vec<MarkdownFile>, Vec<MarkdownFile>)> {
+        log::info!("Collecting markdown: {}", self.src_dir.display());
+        let today = self.config.today();
+        let (pages, articles) = self
+            .collect_markdown(&self.src_dir)?
+            .into_iter()
+            .filter(|src| {
+                self.options.draft_mode != DraftMode::Skip
+                    || !src.markdown.metadata.draft.unwrap_or(false)
+            })
+            .filter(|src| {
+                !self.options.skip_future
+                    || src.markdown.metadata.date.is_none_or(|date| date <= today)
+            })
+            .filter(|src| {
+                self.config.tombstone.is_some()
+                    || src
+                        .markdown
+                        .metadata
+                        .unpublish_date
+                        .is_none_or(|date| date > today)
+            })
+            .partition::<Vec<MarkdownFile>, _>(|src| src.markdown.metadata.page.unwrap_or(false));
+        let mut pages = pages;
+        for virtual_page in &self.config.virtual_pages {
+            pages.push(virtual_page.markdown_file()?);
+        }
+        for (kind, kind_config) in &self.config.kinds {
+            for virtual_page in &kind_config.pages {
+                let mut virtual_page = virtual_page.clone();
+                virtual_page.query.get_or_insert_default().kind = Some(kind.clone());
+                pages.push(virtual_page.markdown_file()?);
+            }
+        }
+        log::info!(
+            "Found {} articles and {} pages",
+            articles.len(),
+            pages.len()
+        );
+
+        for article in &articles {
+            anyhow::ensure!(
+                article.markdown.metadata.date.is_some(),
+                "{} doesn't have date",
+                article.relative_path.display()
+            )
+        }
+        let listed = || {
+            pages.iter().chain(
+                articles
+                    .iter()
+                    .filter(|m| self.is_listed(&m.markdown.metadata)),
+            )
+        };
+        self.report(Path::new("src"), lint_titles(listed()))?;
+        if !self.config.terminology.is_empty() {
+            let checker = self.config.terminology.checker();
+            self.report(
+                Path::new("src"),
+                listed()
+                    .flat_map(|m| {
+                        let content = m.markdown.content.as_deref().unwrap_or_default();
+                        checker
+                            .check(content, m.markdown.content_line)
+                            .into_iter()
+                            .map(|(line, problem)| {
+                                Diagnostic::warning(
+                                    Some(m.relative_path.display().to_string()),
+                                    Some(line),
+                                    problem,
+                                )
+                            })
+                    })
+                    .collect(),
+            )?;
+        }
+        Ok((pages, articles))
+    }
+
+    /// Report `problems`, whose files are relative to `dir`, as warnings, or fail with them if
+    /// `--strict` is given.
+    fn report(&self, dir: &Path, problems: Vec<Diagnostic>) -> Result<()> {
+        let problems = problems
+            .into_iter()
+            .map(|mut problem| {
+                problem.file = problem.file.map(|file| slash_path(&dir.join(file)));
+                if self.options.strict {
+                    problem.severity = Severity::Error;
+                }
+                problem
+            })
+            .collect::<Vec<_>>();
+        if self.options.message_format == MessageFormat::Json {
+            for problem in &problems {
+                println!("{}", problem.json());
+            }
+            // They are reported already.
+            anyhow::ensure!(
+                !self.options.strict || problems.is_empty(),
+                "{} problem(s) found with --strict",
+                problems.len()
+            );
+        }
+        if self.options.strict {
+            anyhow::ensure!(
+                problems.is_empty(),
+                "{}",
+                problems
+                    .iter()
+                    .map(Diagnostic::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        if self.options.message_format == MessageFormat::Human {
+            for problem in problems {
+                log::warn!("{problem}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether an entry appears in `articles` or `pages`.
+    fn is_listed(&self, metadata: &Metadata) -> bool {
+        self.options.draft_mode == DraftMode::Publish || !metadata.draft.unwrap_or(false)
+    }
+
+    fn draft_secret(&self) -> Result<Option<&str>> {
+        Ok(match self.options.draft_mode {
+            DraftMode::Unlisted | DraftMode::Skip | DraftMode::Publish => None,
+            DraftMode::Obfuscate => Some(self.config.get("draft_secret").ok_or_else(|| {
+                anyhow!("`draft_secret` must be set in the config to obfuscate drafts")
+            })?),
+        })
+    }
+
+    /// Pages of `[series_pages]`, whose content is the content of the articles in each series,
+    /// oldest first. Each article is under a heading of its title, with its headings moved down
+    /// by one level, and its ids prefixed with its slug. The TOC is always built.
+    fn series_pages(
+        &self,
+        config: &SeriesPagesConfig,
+        articles: &[Article],
+        env: &Environment,
+    ) -> Result<Vec<Article>> {
+        let mut series = BTreeMap::<&str, Vec<&Article>>::new();
+        for article in articles {
+            if let Some(name) = article.series.as_deref() {
+                series.entry(name).or_default().push(article);
+            }
+        }
+        series
+            .into_iter()
+            .map(|(name, mut articles)| {
+                articles.sort_by(|a, b| newest_first(b, a));
+                let virtual_page = VirtualPage {
+                    url: format!(
+                        "{}/{}/",
+                        config.dir.trim_matches('/'),
+                        self.config.slugs.slug(name)
+                    ),
+                    template: config.template.clone(),
+                    title: name.to_string(),
+                    query: Some(ArticleFilter {
+                        series: Some(name.to_string()),
+                        ..ArticleFilter::default()
+                    }),
+                };
+                let mut page = self.new_article(virtual_page.markdown_file()?)?;
+                let mut content = String::new();
+                for article in articles {
+                    let id = html::escape(&article.slug);
+                    content.push_str(&format!(
+                        "<section>\n<h2 id=\"{id}\"><a class=\"self-link\" href=\"#{id}\">{}</a></h2>\n{}</section>\n",
+                        html::escape(&article.title),
+                        html::prefix_ids(&html::shift_headings(&article.content, 1), &article.slug)
+                    ));
+                }
+                page.content = content;
+                page.toc = true;
+                page.build_toc(env)?;
+                Ok(page)
+            })
+            .collect()
+    }
+
+    /// Build an article or a page, which is ready to be rendered.
+    fn prepare_article(
+        &self,
+        m: MarkdownFile,
+        env: &Environment,
+        draft_secret: Option<&str>,
+    ) -> Result<Article> {
+        let mut article = self.new_article(m)?;
+        if let Some(tombstone) = self.config.tombstone.as_ref().filter(|_| article.expired) {
+            article.template = Some(tombstone.clone());
+        }
+        article.build_toc(env)?;
+        if let (true, Some(secret)) = (article.draft, draft_secret) {
+            article.url = obfuscated_draft_url(secret, &article.url);
+            log::info!("Draft: {}", article.url);
+        }
+        Ok(article)
+    }
+
+    /// Check that all templates which `entries` are rendered with can be loaded, before
+    /// rendering anything, and report every missing or broken one with the entries using it.
+    /// Warn about templates which use variables of pages, such as `articles`, but render
+    /// articles, where they are undefined.
+    fn check_templates<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a Article>,
+        env: &Environment,
+    ) -> Result<()> {
+        // Templates are loaded lazily, e.g. by `{% include %}`, so follow static dependencies.
+        let graph = TemplateGraph::load(&self.root_dir.join("template"))?;
+        let mut users = BTreeMap::<String, Vec<&Path>>::new();
+        let mut article_users = BTreeMap::<String, Vec<&Path>>::new();
+        for entry in entries {
+            let templates = entry
+                .root_templates()
+                .into_iter()
+                .filter(|t| !t.ends_with(".gmi.jinja") || self.options.gemini_out_dir.is_some())
+                // `toc.jinja` is optional.
+                .filter(|t| t != "toc.jinja" || graph.dependencies.contains_key(t))
+                .flat_map(|root| graph.closure(&root))
+                .collect::<BTreeSet<_>>();
+            for template in templates {
+                if !entry.page {
+                    article_users
+                        .entry(template.clone())
+                        .or_default()
+                        .push(&entry.source_path);
+                }
+                users.entry(template).or_default().push(&entry.source_path);
+            }
+        }
+        let used_by = |paths: &[&Path]| {
+            let mut used_by = paths
+                .iter()
+                .take(3)
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+            if paths.len() > 3 {
+                used_by.push(format!("and {} more", paths.len() - 3));
+            }
+            used_by.join(", ")
+        };
+        let errors = users
+            .iter()
+            .filter_map(|(template, paths)| {
+                let e = env.get_template(template).err()?;
+                Some(format!("{e}\n  used by: {}", used_by(paths)))
+            })
+            .collect::<Vec<_>>();
+        anyhow::ensure!(
+            errors.is_empty(),
+            "{} template(s) can not be loaded:\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+        let mut problems = Vec::new();
+        for (template, paths) in &article_users {
+            let Ok(t) = env.get_template(template) else {
+                continue;
+            };
+            let source = unguarded_source(t.source());
+            let env = Environment::new();
+            let mut variables = env
+                .template_from_str(&source)
+                .map_or_else(
+                    |_| t.undeclared_variables(false),
+                    |t| t.undeclared_variables(false),
+                )
+                .into_iter()
+                .filter(|v| PAGE_VARIABLES.contains(&v.as_str()))
+                .collect::<Vec<_>>();
+            variables.sort();
+            for variable in variables {
+                problems.push(Diagnostic::warning(
+                    Some(template.clone()),
+                    None,
+                    format!(
+                        "`{variable}` is only available in pages, but it's undefined in articles \
+                         rendered with this template: {}",
+                        used_by(paths)
+                    ),
+                ));
+            }
+        }
+        self.report(Path::new("template"), problems)
+    }
+
+    /// Statistics of the articles which are listed, i.e. in `articles`.
+    fn site_stats(&self, articles: &[MarkdownFile]) -> SiteStats {
+        SiteStats::new(
+            articles
+                .iter()
+                .map(|m| &m.markdown.metadata)
+                .filter(|metadata| self.is_listed(metadata)),
+        )
+    }
+
+    /// Split `entries` into listed ones and unlisted ones, i.e. drafts and expired ones, which
+    /// are rendered, but don't appear in `articles` or `pages`.
+    fn split_drafts(&self, entries: Vec<Article>) -> (Vec<Article>, Vec<Article>) {
+        let publish_drafts = self.options.draft_mode == DraftMode::Publish;
+        entries
+            .into_iter()
+            .partition(|a| (publish_drafts || !a.draft) && !a.expired)
+    }
+
+    /// Build pages, sorted by weight.
+    fn prepare_pages(
+        &self,
+        pages: Vec<MarkdownFile>,
+        env: &Environment,
+        draft_secret: Option<&str>,
+    ) -> Result<Vec<Article>> {
+        let mut pages = pages
+            .into_par_iter()
+            .map(|m| self.prepare_article(m, env, draft_secret))
+            .collect::<Result<Vec<Article>>>()?;
+        sort_by_weight(&mut pages);
+        Ok(pages)
+    }
+
+    /// Resolve `path`, a markdown file, into a path relative to `src`. `path` is either a path
+    /// in the file system, such as `src/foo.md`, or relative to `src`, such as `foo.md`.
+    pub fn source_path(&self, path: &Path) -> Result<PathBuf> {
+        let path = if path.exists() {
+            path.canonicalize()?
+        } else {
+            self.src_dir
+                .join(path)
+                .canonicalize()
+                .with_context(|| format!("not found: {}", path.display()))?
+        };
+        let src_dir = self.src_dir.canonicalize()?;
+        Ok(path
+            .strip_prefix(&src_dir)
+            .map_err(|_| anyhow!("not in {}: {}", src_dir.display(), path.display()))?
+            .to_path_buf())
+    }
+
+    /// Prepare the markdown at `path`, relative to `src`, and call `f` with the entries it's
+    /// rendered with. Unlike `build`, other articles are prepared only if it's a page, and
+    /// nothing is rendered or written.
+    fn with_entry<T>(
+        &self,
+        path: &Path,
+        template: Option<&str>,
+        env: &Environment,
+        f: impl FnOnce(&Article, Entries) -> Result<T>,
+    ) -> Result<T> {
+        let draft_secret = self.draft_secret()?;
+        let (pages, mut articles) = self.collect_entries()?;
+        let mut pages = self.prepare_pages(pages, env, draft_secret)?;
+        if let (Some(page), Some(template)) = (
+            pages.iter_mut().find(|page| page.source_path == path),
+            template,
+        ) {
+            page.template = Some(template.to_string());
+        }
+        let (pages, draft_pages) = self.split_drafts(pages);
+        let stats = self.site_stats(&articles);
+        if let Some(page) = pages
+            .iter()
+            .chain(&draft_pages)
+            .find(|page| page.source_path == path)
+        {
+            let articles = articles
+                .into_par_iter()
+                .map(|m| self.prepare_article(m, env, draft_secret))
+                .collect::<Result<Vec<Article>>>()?;
+            let (mut articles, _) = self.split_drafts(articles);
+            sort_by_date(&mut articles);
+            let entries = Entries {
+                articles: Some(&articles),
+                pages: &pages,
+                stats: Some(&stats),
+            };
+            return f(page, entries);
+        }
+        let i = articles
+            .iter()
+            .position(|m| m.relative_path == path)
+            .ok_or_else(|| anyhow!("not found in src: {}", path.display()))?;
+        let mut article = self.prepare_article(articles.swap_remove(i), env, draft_secret)?;
+        if let Some(template) = template {
+            article.template = Some(template.to_string());
+        }
+        let entries = Entries {
+            articles: None,
+            pages: &pages,
+            stats: Some(&stats),
+        };
+        f(&article, entries)
+    }
+
+    /// The template context which the markdown at `path`, relative to `src`, is rendered with.
+    pub fn context(&self, path: &Path) -> Result<Value> {
+        let env = self.environment();
+        self.with_entry(path, None, &env, |entry, entries| {
+            Ok(entry.context(&self.config, entries))
+        })
+    }
+
+    /// Render the markdown at `path`, relative to `src`, into HTML in `w`, optionally with
+    /// another template, such as `article`.
+    pub fn render(&self, path: &Path, template: Option<&str>, w: impl Write) -> Result<()> {
+        let env = self.environment();
+        self.with_entry(path, template, &env, |entry, entries| {
+            entry.render(&self.config, entries, &env, w)
+        })
+    }
+
+    /// Build the site, measuring the time of each phase. See [`bench::synthesize`].
+    pub fn bench(&self) -> Result<Vec<bench::Phase>> {
+        self.in_thread_pool(|| {
+            let env = self.environment();
+            let mut phases = Vec::new();
+
+            let start = Instant::now();
+            let markdowns = self.collect_markdown(&self.src_dir)?;
+            phases.push(bench::Phase {
+                name: "parse",
+                duration: start.elapsed(),
+                entries: markdowns.len(),
+                bytes: markdowns
+                    .iter()
+                    .map(|m| m.markdown.content.as_ref().map_or(0, String::len))
+                    .sum(),
+            });
+
+            let start = Instant::now();
+            let (pages, mut articles) = markdowns
+                .into_par_iter()
+                .map(|m| self.prepare_article(m, &env, None))
+                .collect::<Result<Vec<Article>>>()?
+                .into_iter()
+                .partition::<Vec<Article>, _>(|a| a.page);
+            phases.push(bench::Phase {
+                name: "markdown",
+                duration: start.elapsed(),
+                entries: pages.len() + articles.len(),
+                bytes: pages.iter().chain(&articles).map(|a| a.content.len()).sum(),
+            });
+
+            let start = Instant::now();
+            articles.par_iter().try_for_each(|article| {
+                let entries = Entries {
+                    articles: None,
+                    pages: &pages,
+                    stats: None,
+                };
+                article.render_and_write(&self.config, entries, &env, &self.out_dir)
+            })?;
+            sort_by_date(&mut articles);
+            let entries = Entries {
+                articles: Some(&articles),
+                pages: &pages,
+                stats: None,
+            };
+            for page in &pages {
+                page.render_and_write(&self.config, entries, &env, &self.out_dir)?;
+            }
+            let mut bytes = 0;
+            for entry in walkdir::WalkDir::new(&self.out_dir) {
+                bytes += entry?.metadata()?.len() as usize;
+            }
+            phases.push(bench::Phase {
+                name: "write",
+                duration: start.elapsed(),
+                entries: pages.len() + articles.len(),
+                bytes,
+            });
+            Ok(phases)
+        })
+    }
+
+    fn render_markdowns(&self, env: &Environment) -> Result<()> {
+        let (pages, articles) = self.collect_entries()?;
+        let draft_secret = self.draft_secret()?;
+
+        // Pages are built first, so that articles can see them, e.g. for menus.
+        let (pages, draft_pages) =
+            self.split_drafts(self.prepare_pages(pages, env, draft_secret)?);
+
+        let stats = self.site_stats(&articles);
+
+        log::info!("Build articles");
+        let articles = articles
+            .into_par_iter()
+            .map(|m| self.prepare_article(m, env, draft_secret))
+            .collect::<Result<Vec<Article>>>()?;
+        let (mut articles, draft_articles) = self.split_drafts(articles);
+        sort_by_date(&mut articles);
+        // Series pages are built before anything is written, so that their URLs are checked
+        // against other entries.
+        let series_pages = match self.config.series_pages.as_ref() {
+            Some(series_pages) => self.series_pages(series_pages, &articles, env)?,
+            None => Vec::new(),
+        };
+        let all_entries = || {
+            pages
+                .iter()
+                .chain(&draft_pages)
+                .chain(&articles)
+                .chain(&draft_articles)
+                .chain(&series_pages)
+        };
+        self.check_templates(all_entries(), env)?;
+        self.report(Path::new("src"), lint_urls(all_entries()))?;
+        let only = self
+            .options
+            .only
+            .iter()
+            .map(|path| self.source_path(path))
+            .collect::<Result<BTreeSet<_>>>()?;
+        // An asset, such as an image, names the articles referencing it.
+        let is_named = |a: &Article| {
+            only.is_empty()
+                || only.contains(&a.source_path)
+                || a.assets.iter().any(|asset| only.contains(asset))
+        };
+        if !only.is_empty() {
+            log::info!(
+                "Render only {} markdowns and pages listing them",
+                only.len()
+            );
+        }
+        articles
+            .par_iter()
+            .chain(&draft_articles)
+            .filter(|a| is_named(a))
+            .try_for_each(|article| -> Result<()> {
+                let entries = Entries {
+                    articles: None,
+                    pages: &pages,
+                    stats: Some(&stats),
+                };
+                article.render_and_write(&self.config, entries, env, &self.out_dir)?;
+                if let Some(gemini_out_dir) = self.options.gemini_out_dir.as_ref() {
+                    article.render_and_write_gemtext(&self.config, entries, env, gemini_out_dir)?;
+                }
+                Ok(())
+            })?;
+
+        if !series_pages.is_empty() {
+            let entries = Entries {
+                articles: Some(&articles),
+                pages: &pages,
+                stats: Some(&stats),
+            };
+            for page in &series_pages {
+                // `query` selects the articles of the series.
+                if articles
+                    .iter()
+                    .any(|a| is_named(a) && page.query.as_ref().is_some_and(|q| q.matches(a)))
+                {
+                    page.render_and_write(&self.config, entries, env, &self.out_dir)?;
+                }
+            }
+        }
+
+        if self.options.article_regex.is_empty() {
+            let events = events(&articles);
+            if !events.is_empty() {
+                self.write_events(&events)?;
+            }
+        }
+
+        #[cfg(feature = "search")]
+        if let Some(search_index_dir) = self.options.search_index_dir.as_ref() {
+            let documents = articles
+                .iter()
+                .filter(|a| !a.protected)
+                .map(|a| crate::search::Document {
+                    title: a.title.clone(),
+                    url: a.url.clone(),
+                    date: a.date,
+                    body: a.plain_text(),
+                })
+                .collect::<Vec<_>>();
+            crate::search::build_index(search_index_dir, &documents)?;
+        }
+
+        log::info!("Build pages");
+        let entries = Entries {
+            articles: Some(&articles),
+            pages: &pages,
+            stats: Some(&stats),
+        };
+        // A page lists the named articles, unless its `query` excludes all of them.
+        let named_articles = articles.iter().filter(|a| is_named(a)).collect::<Vec<_>>();
+        let lists_named = |page: &Article| {
+            page.query
+                .as_ref()
+                .is_none_or(|query| named_articles.iter().any(|a| query.matches(a)))
+                && !named_articles.is_empty()
+        };
+        for page in pages
+            .iter()
+            .chain(&draft_pages)
+            .filter(|page| is_named(page) || lists_named(page))
+        {
+            page.render_and_write(&self.config, entries, env, &self.out_dir)?;
+            if let Some(gemini_out_dir) = self.options.gemini_out_dir.as_ref() {
+                page.render_and_write_gemtext(&self.config, entries, env, gemini_out_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_files(&self, out_dir: &Path) -> Result<()> {
+        log::info!(
+            "Copy files: {} => {}",
+            self.src_dir.display(),
+            out_dir.display()
+        );
+        let mut walker = walkdir::WalkDir::new(&self.src_dir).into_iter();
+        while let Some(entry) = walker.next() {
+            let entry = entry?;
+            let src_path = entry.path();
+            if src_path.extension().is_some_and(|ext| ext == "md")
+                || entry.file_name() == remote::MARKER
+            {
+                continue;
+            }
+
+            let Some(relative_path) = utf8_path(
+                src_path.strip_prefix(&self.src_dir)?,
+                self.config.non_utf8_paths,
+            ) else {
+                if entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                continue;
+            };
+            let out_path = out_dir.join(relative_path.as_path());
+            log::debug!("{:32} => {}", relative_path.display(), out_path.display());
+
+            if entry.file_type().is_dir() {
+                std::fs::create_dir_all(&out_path)
+                    .with_context(|| format!("can not create dir: {}", out_path.display()))?;
+            } else {
+                std::fs::copy(src_path, &out_path).with_context(|| {
+                    format!(
+                        "can not copy: {} => {}",
+                        src_path.display(),
+                        out_path.display()
+                    )
+                })?;
+                // The original is also kept, for URLs which are not rewritten, such as in CSS.
+                if let Some(fingerprinted) = self
+                    .config
+                    .fingerprints
+                    .get(&format!("/{}", relative_path.display()))
+                {
+                    std::fs::copy(src_path, out_dir.join(&fingerprinted[1..]))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slug_to_url_test() {
+        assert_eq!(slug_to_url("foo"), "foo/");
+        assert_eq!(slug_to_url("foo/"), "foo/");
+        assert_eq!(slug_to_url("feed.xml"), "feed.xml");
+        assert_eq!(slug_to_url("feed.xml/"), "feed.xml/");
+        assert_eq!(slug_to_url("index"), "");
+        assert_eq!(slug_to_url(""), "");
+        assert_eq!(slug_to_url("a/b"), "a/b/");
+        assert_eq!(slug_to_url("a/b/"), "a/b/");
+        assert_eq!(slug_to_url("a/b.html"), "a/b.html");
+        assert_eq!(slug_to_url("a/b.html/"), "a/b.html/");
+    }
+
+    #[test]
+    fn output_url_test() {
+        assert_eq!(output_url("feed", None, None).unwrap(), "feed/");
+        assert_eq!(
+            output_url("feed", Some("feed.xml"), None).unwrap(),
+            "feed.xml"
+        );
+        assert_eq!(
+            output_url("notes", Some("notes/all.json"), None).unwrap(),
+            "notes/all.json"
+        );
+        assert_eq!(output_url("a", None, Some(".txt")).unwrap(), "a.txt");
+        assert_eq!(output_url("a", None, Some("txt")).unwrap(), "a.txt");
+        assert!(output_url("a", Some("a.xml"), Some(".txt")).is_err());
+        assert!(output_url("a", Some("../a.xml"), None).is_err());
+        assert!(output_url("a", Some("/a.xml"), None).is_err());
+    }
+
+    #[test]
+    fn asset_refs_test() {
+        assert_eq!(
+            asset_refs(
+                "![A](diagram.png) [B](../bar/) [C](https://example.com/c.png) [D](/img/d%20e.jpg#x)\n<video src=\"clip.mp4\" poster=\"../poster.jpg\"></video> [E](#e) ![A](diagram.png)",
+                "notes/foo/"
+            ),
+            [
+                "img/d e.jpg",
+                "notes/foo/clip.mp4",
+                "notes/foo/diagram.png",
+                "notes/poster.jpg"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn og_locale_test() {
+        assert_eq!(og_locale("ja"), "ja_JP");
+        assert_eq!(og_locale("en-GB"), "en_GB");
+        assert_eq!(og_locale("zh-Hant-tw"), "zh_TW");
+        assert_eq!(og_locale("eo"), "eo");
+        assert!(is_language_tag("zh-Hant-TW"));
+        assert!(!is_language_tag("Japanese"));
+        assert!(!is_language_tag("en_US"));
+    }
+
+    #[test]
+    fn url_to_filename_test() {
+        assert_eq!(url_to_filename(""), "index.html");
+        assert_eq!(url_to_filename("a"), "a");
+        assert_eq!(url_to_filename("a/"), "a/index.html");
+        assert_eq!(url_to_filename("a.html"), "a.html");
+        assert_eq!(url_to_filename("a.html/"), "a.html/index.html");
+        assert_eq!(url_to_filename("a/b"), "a/b");
+        assert_eq!(url_to_filename("a/b/"), "a/b/index.html");
+        assert_eq!(url_to_filename("a/b.html"), "a/b.html");
+        assert_eq!(url_to_filename("a/b.html/"), "a/b.html/index.html");
+        assert_eq!(url_to_filename("%E6%97%A5/"), "日/index.html");
+    }
+
+    #[test]
+    fn inline_asset_test() {
+        let dir = crate::testing::TempDir::new("inline");
+        std::fs::create_dir_all(dir.join("src/css")).unwrap();
+        std::fs::write(dir.join("src/css/critical.css"), "body{}").unwrap();
+        std::fs::write(dir.join("src/dot.png"), b"\x89PNG").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let src_dir = dir.join("src");
+
+        let path = resolve_in(&src_dir, "css/critical.css").unwrap();
+        assert_eq!(inline_asset(&path).unwrap(), "body{}");
+        let path = resolve_in(&src_dir, "dot.png").unwrap();
+        assert_eq!(
+            inline_asset(&path).unwrap(),
+            "data:image/png;base64,iVBORw=="
+        );
+        assert!(resolve_in(&src_dir, "../secret.txt").is_err());
+        assert!(resolve_in(&src_dir, dir.join("secret.txt").to_str().unwrap()).is_err());
+        assert!(resolve_in(&src_dir, "missing.css").is_err());
+    }
+
+    #[test]
+    fn asset_test() {
+        let dir = crate::testing::fixture("asset", 1, 100);
+        std::fs::write(dir.join("src/style.css"), "p{}").unwrap();
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            r#"{% set css = asset("/style.css") %}<link rel="stylesheet" href="{{ css.url }}" integrity="{{ css.integrity }}">"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("config.toml"), "checksums = true\n").unwrap();
+        let options = BuildOptions {
+            fingerprint: true,
+            ..BuildOptions::default()
+        };
+        let output = crate::testing::build(&dir, options).unwrap();
+        let fingerprinted = &fingerprints(&dir.join("src")).unwrap()["/style.css"];
+        assert_eq!(
+            output.text("index.html").unwrap(),
+            format!(
+                r#"<link rel="stylesheet" href="{fingerprinted}" integrity="{}">"#,
+                integrity::sri(b"p{}")
+            )
+        );
+        let checksums = output.text(integrity::CHECKSUMS).unwrap();
+        for file in ["index.html", "style.css", &fingerprinted[1..]] {
+            assert!(checksums.contains(&format!("  {file}\n")), "{file}");
+        }
+    }
+
+    #[test]
+    fn xml_template_test() {
+        let dir = crate::testing::fixture("xml", 1, 100);
+        std::fs::write(dir.join("config.toml"), "xml_autoescape = true\n").unwrap();
+        std::fs::write(
+            dir.join("src/article-0.md"),
+            "# Q&A <1>\n\n<!--\ndate = \"2000-01-01\"\n-->\n\n## Q\n\nA & B\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("src/feed.md"),
+            "# Feed\n\n<!--\npage = true\noutput = \"feed.xml\"\ntemplate = \"feed.xml\"\n-->\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/feed.xml.jinja"),
+            "{% for a in articles %}<title>{{ a.title }}</title><d>{{ a.content | cdata }}</d><t>{{ a.title | xml_escape }}</t>{% endfor %}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("feed.xml").unwrap(),
+            "<title>Q&amp;A &lt;1&gt;</title><d><![CDATA[<h2 id=\"q\">Q</h2>\n<p>A &amp; B</p>\n]]></d><t>Q&amp;A &lt;1&gt;</t>"
+        );
+        // Other templates are not escaped.
+        assert!(output
+            .text("article-0/index.html")
+            .unwrap()
+            .contains("<h1>Q&A <1></h1>"));
+
+        // Without `xml_autoescape`, values are written as is, as before.
+        std::fs::write(dir.join("config.toml"), "").unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert!(output
+            .text("feed.xml")
+            .unwrap()
+            .starts_with("<title>Q&A <1></title>"));
+    }
+
+    #[test]
+    fn site_values_test() {
+        let dir = crate::testing::fixture("values", 1, 100);
+        std::fs::write(
+            dir.join("config.toml"),
+            "title = \"T\"\nshow_toc = false\nyear = 2024\nsocial = [\"a\", \"b\"]\n\n[params]\ncomments = true\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            r#"{{ site.title }}{% if site.show_toc %} toc{% endif %} {{ site.year + 1 }} {{ site.social | join(",") }}{% if site.params.comments is true %} comments{% endif %}"#,
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(output.text("index.html").unwrap(), "T 2025 a,b comments");
+    }
+
+    #[test]
+    fn config_keys_test() {
+        // Each key is a typed field: a value of a wrong type is an error, or it's not taken as
+        // a free-form value.
+        for key in Config::KEYS {
+            if let Ok(config) = toml::from_str::<Config>(&format!("{key} = 0")) {
+                assert!(!config.site.contains_key(*key), "{key} is not a field");
+            }
+        }
+        // Each field which is read from the config is in `KEYS`.
+        static FIELD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^    (\w+):").unwrap());
+        let source = include_str!("site.rs");
+        let start = source.find("\npub struct Config {\n").unwrap();
+        let end = start + source[start..].find("\n}\n").unwrap();
+        let mut not_read = false;
+        for line in source[start..end].lines() {
+            if let Some(caps) = FIELD.captures(line) {
+                if !not_read {
+                    assert!(
+                        Config::KEYS.contains(&&caps[1]),
+                        "{} is not in KEYS",
+                        &caps[1]
+                    );
+                }
+                not_read = false;
+            } else if line.contains("#[serde(skip)]") || line.contains("#[serde(flatten)]") {
+                not_read = true;
+            }
+        }
+    }
+
+    #[test]
+    fn series_pages_test() {
+        let dir = crate::testing::fixture("series", 0, 0);
+        for (name, date) in [("part-2", "2024-02-01"), ("part-1", "2024-01-01")] {
+            std::fs::write(
+                dir.join(format!("src/{name}.md")),
+                format!("# {name}\n\ndate = \"{date}\"\nseries = \"Tutorial\"\n\n## Setup\n\nText[^1].\n\n[^1]: Note.\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(dir.join("config.toml"), "[series_pages]\n").unwrap();
+        std::fs::write(
+            dir.join("template/series.jinja"),
+            "{{ entry.title }} {{ articles | length }}\n{{ entry.toc_html }}\n{{ entry.content }}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        let html = output.text("series/Tutorial/index.html").unwrap();
+        assert!(
+            html.starts_with("Tutorial 2\n<nav class=\"toc\">"),
+            "{html}"
+        );
+        let part1 = html.find(r#"<h2 id="part-1">"#).unwrap();
+        let part2 = html.find(r#"<h2 id="part-2">"#).unwrap();
+        assert!(part1 < part2);
+        assert!(html.contains(
+            r##"<h3 id="part-1-setup"><a class="self-link" href="#part-1-setup">Setup</a></h3>"##
+        ));
+        assert!(html.contains(r##"<a href="#part-2-1">"##));
+        assert!(html.contains(r##"<a href="#part-1-setup">Setup</a>"##));
+
+        // A series page would overwrite a page at the same URL.
+        std::fs::create_dir_all(dir.join("src/series/Tutorial")).unwrap();
+        std::fs::write(
+            dir.join("src/series/Tutorial/index.md"),
+            "# Tutorial\n\npage = true\n",
+        )
+        .unwrap();
+        let options = BuildOptions {
+            strict: true,
+            ..BuildOptions::default()
+        };
+        let e = crate::testing::build(&dir, options).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains("entries are written to the same series/Tutorial/index.html"));
+    }
+
+    #[test]
+    fn sample_filter_test() {
+        let dir = crate::testing::fixture("sample", 10, 10);
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            r#"{% for a in articles | sample(3) %}{{ a.slug }} {% endfor %}|{{ [1, 2, 3] | shuffle(seed=1) | sort | join(",") }}"#,
+        )
+        .unwrap();
+        let build = || {
+            crate::testing::build(&dir, BuildOptions::default())
+                .unwrap()
+                .text("index.html")
+                .unwrap()
+                .to_string()
+        };
+        let html = build();
+        assert_eq!(html.split_whitespace().count(), 4);
+        assert!(html.ends_with("|1,2,3"));
+        assert_eq!(build(), html);
+    }
+
+    #[test]
+    fn kinds_test() {
+        let dir = crate::testing::fixture("kinds", 1, 100);
+        std::fs::write(
+            dir.join("src/note.md"),
+            "# Hello\n\ndate = \"2024-01-01\"\nkind = \"note\"\n\nShort.\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("config.toml"),
+            "[kinds.note]\npages = [{ url = \"notes/\", template = \"notes\", title = \"Notes\" }]\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("template/note.jinja"), "NOTE {{ entry.title }}").unwrap();
+        std::fs::write(
+            dir.join("template/notes.jinja"),
+            "{% for a in articles %}{{ a.slug }}:{{ a.kind }} {% endfor %}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(output.text("note/index.html").unwrap(), "NOTE Hello");
+        assert_eq!(output.text("notes/index.html").unwrap(), "note:note ");
+        // Other articles use `article.jinja`.
+        assert!(output
+            .text("article-0/index.html")
+            .unwrap()
+            .starts_with("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn link_test() {
+        let dir = crate::testing::fixture("link", 0, 0);
+        std::fs::write(
+            dir.join("src/bookmark.md"),
+            "# Read this\n\ndate = \"2024-01-01\"\nkind = \"bookmark\"\nlink = \"https://www.example.com/post\"\n\nGood.\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            "{% for a in articles %}{{ a.link or a.url }} ({{ a.link_host }}){% endfor %}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("index.html").unwrap(),
+            "https://www.example.com/post (example.com)"
+        );
+
+        std::fs::write(
+            dir.join("src/bookmark.md"),
+            "# Read this\n\ndate = \"2024-01-01\"\nlink = \"example.com\"\n",
+        )
+        .unwrap();
+        assert!(crate::testing::build(&dir, BuildOptions::default()).is_err());
+    }
+
+    #[test]
+    fn slides_test() {
+        let dir = crate::testing::fixture("slides", 0, 0);
+        std::fs::write(
+            dir.join("src/talk.md"),
+            "# My talk\n\ndate = \"2024-01-01\"\nslides = true\nslides_pdf = \"talk.pdf\"\nevent = \"RustConf\"\n\nHello\n\n---\n\n## Bye\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("src/talk.pdf"), "%PDF").unwrap();
+        std::fs::write(
+            dir.join("template/slides.jinja"),
+            "{{ entry.event }} {{ entry.slide_count }} {{ entry.slides_pdf }}\n{{ entry.content }}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        let html = output.text("talk/index.html").unwrap();
+        assert!(html.starts_with("RustConf 2 talk.pdf\n<section class=\"slide\" id=\"slide-1\">\n<p>Hello</p>\n</section>\n<section class=\"slide\" id=\"slide-2\">"));
+
+        // Talks are listed apart from posts.
+        std::fs::write(dir.join("src/post.md"), "# Post\n\ndate = \"2024-02-01\"\n").unwrap();
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            "{% for a in talks %}{{ a.title }} at {{ a.event }} on {{ a.date }}\n{% endfor %}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("index.html").unwrap(),
+            "My talk at RustConf on 2024-01-01\n"
+        );
+
+        std::fs::remove_file(dir.join("src/talk.pdf")).unwrap();
+        assert!(crate::testing::build(&dir, BuildOptions::default()).is_err());
+
+        // A PDF outside of `src` is not published.
+        std::fs::write(dir.join("talk.pdf"), "%PDF").unwrap();
+        std::fs::write(
+            dir.join("src/talk.md"),
+            "# My talk\n\ndate = \"2024-01-01\"\nslides = true\nslides_pdf = \"../talk.pdf\"\n",
+        )
+        .unwrap();
+        let e = crate::testing::build(&dir, BuildOptions::default()).unwrap_err();
+        assert!(format!("{e:#}").contains("not in src: ../talk.pdf: talk.md"));
+    }
+
+    #[test]
+    fn render_snippet_test() {
+        assert_eq!(
+            Markdown::render_snippet("## A\n\n日本\n語 *b*", &MarkdownOptions::default()),
+            "<h2 id=\"a\"><a class=\"self-link\" href=\"#a\">A</a></h2>\n<p>日本語 <em>b</em></p>\n"
+        );
+    }
+
+    #[test]
+    fn markdown_options_test() {
+        let s = r#"title = "Hello"
+markdown = { tables = false, smart_punctuation = true }
+
+| a | b |
+|---|---|
+| 1 | 2 |
+
+"quoted"
+"#;
+        let markdown = s.parse::<Markdown>().unwrap();
+        let html = markdown.render(&MarkdownOptions::default(), None, &[]);
+        assert!(!html.contains("<table>"));
+        assert!(html.contains("“quoted”"));
+
+        let site = MarkdownOptions {
+            tables: Some(false),
+            math: Some(true),
+            ..Default::default()
+        };
+        let article = MarkdownOptions {
+            tables: Some(true),
+            ..Default::default()
+        };
+        let merged = site.merge(&article).pulldown_cmark_options();
+        assert!(merged.contains(pulldown_cmark::Options::ENABLE_TABLES));
+        assert!(merged.contains(pulldown_cmark::Options::ENABLE_MATH));
+        assert!(merged.contains(pulldown_cmark::Options::ENABLE_FOOTNOTES));
+        assert!(!merged.contains(pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION));
+    }
+
+    #[test]
+    fn image_test() {
+        let src_dir = crate::testing::TempDir::new("image");
+        std::fs::create_dir_all(src_dir.join("img")).unwrap();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend(640u32.to_be_bytes());
+        png.extend(480u32.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0]);
+        std::fs::write(src_dir.join("img/hero.png"), png).unwrap();
+
+        assert_eq!(
+            Image::new(&src_dir, Path::new("img/hero.png")).unwrap(),
+            Image {
+                url: "img/hero.png".to_string(),
+                width: Some(640),
+                height: Some(480),
+            }
+        );
+        assert!(Image::new(&src_dir, Path::new("img/missing.png")).is_err());
+    }
+
+    #[test]
+    fn audio_test() {
+        let src_dir = crate::testing::TempDir::new("audio");
+        std::fs::create_dir_all(src_dir.join("episodes")).unwrap();
+        std::fs::write(src_dir.join("episodes/1.MP3"), [0; 100]).unwrap();
+        std::fs::write(src_dir.join("episodes/1.xyz"), [0; 100]).unwrap();
+
+        assert_eq!(
+            Audio::new(&src_dir, Path::new("episodes/1.MP3")).unwrap(),
+            Audio {
+                url: "episodes/1.MP3".to_string(),
+                length: 100,
+                mime_type: "audio/mpeg",
+            }
+        );
+        assert!(Audio::new(&src_dir, Path::new("episodes/1.xyz")).is_err());
+        assert!(Audio::new(&src_dir, Path::new("episodes/2.mp3")).is_err());
+    }
+
+    #[test]
+    fn changes_test() {
+        let dir = crate::testing::fixture("changes", 0, 0);
+        for (name, metadata) in [
+            ("a", "date = \"2024-01-01\"\nupdate_date = \"2024-04-01\""),
+            ("b", "date = \"2024-02-01\""),
+            (
+                "about",
+                "page = true\ndate = \"2023-01-01\"\nupdate_date = \"2024-03-01\"",
+            ),
+        ] {
+            std::fs::write(
+                dir.join(format!("src/{name}.md")),
+                format!("# {name}\n\n{metadata}\n\nHello.\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            "{% for c in changes %}{{ c.date }} {{ c.entry.title }}{% if c.updated %} (updated){% endif %}\n{% endfor %}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("index.html").unwrap(),
+            "2024-04-01 a (updated)\n2024-03-01 about (updated)\n2024-02-01 b\n2024-01-01 a\n2023-01-01 about\n"
+        );
+    }
+
+    #[test]
+    fn page_variables_in_articles_test() {
+        let dir = crate::testing::fixture("page-variables", 1, 100);
+        let options = || BuildOptions {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(crate::testing::build(&dir, options()).is_ok());
+
+        std::fs::write(
+            dir.join("template/article.jinja"),
+            "{% set n = 1 %}{{ n }}{{ entry.title }}{% for a in articles_by_year %}{% endfor %}",
+        )
+        .unwrap();
+        let e = crate::testing::build(&dir, options()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "template/article.jinja: `articles_by_year` is only available in pages, but it's undefined in articles rendered with this template: article-0.md"
+        );
+
+        // Templates which articles extend or include are checked, and guarded uses are not
+        // warned.
+        std::fs::write(
+            dir.join("template/base.jinja"),
+            "{% if talks is defined %}{{ talks | length }}{% endif %}{{ events | default([]) | length }}{% block main %}{% endblock %}{% for a in upcoming_events %}{% endfor %}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/article.jinja"),
+            "{% extends \"base.jinja\" %}{% block main %}{{ entry.title }}{% endblock %}",
+        )
+        .unwrap();
+        let e = crate::testing::build(&dir, options()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "template/base.jinja: `upcoming_events` is only available in pages, but it's undefined in articles rendered with this template: article-0.md"
+        );
+
+        // A guard only covers its own body, and the variable which it tests, except for
+        // `entry.page`.
+        std::fs::write(
+            dir.join("template/base.jinja"),
+            "{% if entry.page %}{{ articles | length }}{% else %}{{ entry.title }}{% endif %}\
+             {% if talks is undefined %}{% else %}{{ talks | length }}{% endif %}\
+             {% if events is defined %}{{ changes | length }}{% endif %}\
+             {{ recently_updated | length }}{% if recently_updated is defined %}{% endif %}\
+             {% block main %}{% endblock %}",
+        )
+        .unwrap();
+        let e = crate::testing::build(&dir, options()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "template/base.jinja: `changes` is only available in pages, but it's undefined in articles rendered with this template: article-0.md\n\
+             template/base.jinja: `recently_updated` is only available in pages, but it's undefined in articles rendered with this template: article-0.md"
+        );
+    }
+
+    #[test]
+    fn index_md_test() {
+        let dir = crate::testing::fixture("index-md", 0, 0);
+        std::fs::create_dir_all(dir.join("src/Rust Notes")).unwrap();
+        std::fs::write(dir.join("config.toml"), "slugs = \"transliterate\"\n").unwrap();
+        std::fs::write(
+            dir.join("src/Rust Notes/index.md"),
+            "# Rust\n\npage = true\n\nHello.\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            "{% for p in pages %}{{ p.url }}:{{ p.slug }} {% endfor %}",
+        )
+        .unwrap();
+        let options = || BuildOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let output = crate::testing::build(&dir, options()).unwrap();
+        assert_eq!(
+            output.text("Rust Notes/index.html").unwrap(),
+            ":index Rust Notes/:index "
+        );
+
+        // An explicit `slug` is honored, as in other markdowns.
+        std::fs::write(
+            dir.join("src/Rust Notes/index.md"),
+            "# Rust\n\npage = true\nslug = \"rust\"\n\nHello.\n",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, options()).unwrap();
+        assert!(output.text("Rust Notes/index.html").is_none());
+        assert!(output.text("Rust Notes/rust/index.html").is_some());
+
+        std::fs::write(
+            dir.join("src/Rust Notes/index.md"),
+            "# Rust\n\npage = true\n\nHello.\n",
+        )
+        .unwrap();
+
+        std::fs::write(
+            dir.join("src/rust.md"),
+            "# Rust\n\npage = true\nslug = \"Rust Notes\"\n",
+        )
+        .unwrap();
+        let e = crate::testing::build(&dir, options()).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains("entries are written to the same Rust Notes/index.html"));
+    }
+
+    #[test]
+    fn pdf_command_test() {
+        let dir = crate::testing::fixture("pdf", 0, 0);
+        std::fs::write(
+            dir.join("src/doc.md"),
+            "# Doc\n\ndate = \"2024-01-01\"\npdf = true\n\nHello.\n",
+        )
+        .unwrap();
+        // The script is an argument with spaces.
+        for command in [
+            r#"["sh", "-c", "cp \"$0\" \"$1\"", "{input}", "{output}"]"#,
+            r#"'sh -c "cp \"\$0\" \"\$1\"" {input} {output}'"#,
+        ] {
+            std::fs::write(
+                dir.join("config.toml"),
+                format!("pdf_command = {command}\n"),
+            )
+            .unwrap();
+            let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+            assert_eq!(
+                output.text("doc/index.pdf").unwrap(),
+                output.text("doc/index.html").unwrap()
+            );
+        }
+        std::fs::write(dir.join("config.toml"), "pdf_command = 1\n").unwrap();
+        assert!(crate::testing::build(&dir, BuildOptions::default()).is_err());
+    }
+
+    #[test]
+    fn csp_headers_test() {
+        let dir = crate::testing::fixture("csp", 0, 0);
+        std::fs::write(dir.join("config.toml"), "[csp]\n").unwrap();
+        std::fs::write(
+            dir.join("src/_headers"),
+            "/fonts/*\n  Cache-Control: max-age=31536000",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        let headers = output.text("_headers").unwrap();
+        assert!(headers.starts_with("/fonts/*\n  Cache-Control: max-age=31536000\n/*\n  Content-Security-Policy: default-src 'self'"));
+    }
+
+    #[test]
+    fn render_error_test() {
+        let dir = crate::testing::fixture("render-error", 1, 100);
+        std::fs::write(
+            dir.join("template/article.jinja"),
+            "{{ entry.title }}{{ undefined_function() }}",
+        )
+        .unwrap();
+        let out_dir = dir.join("out");
+        let site = crate::Site::new(
+            Config::default(),
+            dir.to_path_buf(),
+            out_dir.clone(),
+            BuildOptions::default(),
+        )
+        .unwrap();
+        assert!(site.build().is_err());
+        let article_dir = out_dir.join("article-0");
+        assert_eq!(std::fs::read_dir(&article_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn set_update_date_test() {
+        let mut article = Article {
+            date: Some("2024-01-01".parse().unwrap()),
+            ..Default::default()
+        };
+        article.set_update_date(None);
+        assert_eq!(article.last_modified, article.date);
+        assert!(!article.updated);
+
+        article.set_update_date(Some("2024-02-01".parse().unwrap()));
+        assert_eq!(article.last_modified, Some("2024-02-01".parse().unwrap()));
+        assert!(article.updated);
+
+        article.set_update_date(Some("2024-01-01".parse().unwrap()));
+        assert!(!article.updated);
+    }
+
+    #[test]
+    fn stale_test() {
+        let stale = StaleConfig {
+            years: 2,
+            banner: Some("<p>Older than {years} years ({last_modified})</p>".to_string()),
+        };
+        let today = "2024-06-01".parse().unwrap();
+        assert!(stale.is_stale("2022-05-31".parse().unwrap(), today));
+        assert!(!stale.is_stale("2022-06-01".parse().unwrap(), today));
+        assert!(!stale.is_stale("2024-01-01".parse().unwrap(), today));
+        assert_eq!(
+            stale.banner("2022-05-31".parse().unwrap()).unwrap(),
+            "<p>Older than 2 years (2022-05-31)</p>"
+        );
+
+        // The banner is `entry.stale`, and the content is kept as it is.
+        let dir = crate::testing::fixture("stale", 1, 100);
+        std::fs::write(
+            dir.join("config.toml"),
+            "[stale]\nyears = 2\nbanner = \"<p>Old</p>\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/article.jinja"),
+            "{{ entry.stale }}|{{ entry.content }}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        let html = output.text("article-0/index.html").unwrap();
+        assert!(html.starts_with("<p>Old</p>|"), "{html}");
+        assert!(!html[1..].contains("<p>Old</p>"), "{html}");
+    }
+
+    #[test]
+    fn h_entry_test() {
+        let article = Article {
+            title: "A & B".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2024, 1, 2),
+            author: Some("Me".to_string()),
+            tags: vec!["rust".to_string()],
+            link: Some("https://example.org/?a&b".to_string()),
+            content: "<p>Hello</p>\n".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            article.h_entry("https://example.com/a-b/"),
+            r#"<div class="h-entry">
+<data class="p-name" value="A &amp; B"></data>
+<a class="u-url" href="https://example.com/a-b/" hidden></a>
+<a class="u-bookmark-of" href="https://example.org/?a&amp;b" hidden></a>
+<time class="dt-published" datetime="2024-01-02" hidden></time>
+<data class="p-author h-card" value="Me"></data>
+<data class="p-category" value="rust"></data>
+<div class="e-content">
+<p>Hello</p>
+</div>
+</div>
+"#
+        );
+    }
+
+    #[test]
+    fn microformats_test() {
+        let dir = crate::testing::fixture("mf2", 1, 10);
+        std::fs::write(
+            dir.join("config.toml"),
+            "microformats = true\n\n[[virtual_pages]]\nurl = \"feed.xml\"\ntemplate = \"feed\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/feed.jinja"),
+            "{% for a in articles %}{{ a.content }}{% endfor %}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        let html = output.text("article-0/index.html").unwrap();
+        assert!(html.contains("<div class=\"h-entry\">"), "{html}");
+        assert!(html.contains("<a class=\"u-url\" href=\"/article-0/\" hidden></a>"));
+        // Feeds embed the content as is.
+        assert!(!output.text("feed.xml").unwrap().contains("h-entry"));
+    }
+
+    #[test]
+    fn comments_snippet_test() {
+        let article = Article {
+            title: "A & B".to_string(),
+            slug: "a-b".to_string(),
+            url: "a-b/".to_string(),
+            ..Default::default()
+        };
+        let comments: CommentsConfig = toml::from_str(
+            r#"provider = "giscus"
+repo = "me/site"
+mapping = "pathname"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            comments.snippet(&article).unwrap(),
+            r#"<script src="https://giscus.app/client.js" data-mapping="pathname" data-repo="me/site" crossorigin="anonymous" async></script>"#
+        );
+
+        let comments: CommentsConfig = toml::from_str(
+            r#"provider = "custom"
+snippet = '<div data-url="{url}" data-title="{title}"></div>'
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            comments.snippet(&article).unwrap(),
+            r#"<div data-url="a-b/" data-title="A &amp; B"></div>"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn utf8_path_test() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"photos/\xffa.jpg"));
+        assert_eq!(utf8_path(path, NonUtf8Paths::Skip), None);
+        assert_eq!(
+            utf8_path(path, NonUtf8Paths::Lossy),
+            Some(PathBuf::from("photos/\u{FFFD}a.jpg"))
+        );
+        assert_eq!(
+            utf8_path(Path::new("a/b.md"), NonUtf8Paths::Skip),
+            Some(PathBuf::from("a/b.md"))
+        );
+    }
+
+    #[test]
+    fn template_for_test() {
+        let mut config: Config = toml::from_str(
+            r#"
+[[templates]]
+path = "notes/**"
+template = "note"
+"#,
+        )
+        .unwrap();
+        config.extend(
+            &mut toml::from_str(
+                r#"
+[[templates]]
+path = "notes/draft/*"
+template = "draft"
+"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(config.template_for(Path::new("notes/a.md")), Some("note"));
+        assert_eq!(config.template_for(Path::new("notes/b/c.md")), Some("note"));
+        assert_eq!(
+            config.template_for(Path::new("notes/draft/d.md")),
+            Some("draft")
+        );
+        assert_eq!(config.template_for(Path::new("e.md")), None);
+        assert!(toml::from_str::<Config>("[[templates]]\npath = \"[\"\ntemplate = \"x\"").is_err());
+    }
+
+    #[test]
+    fn abbreviations_config_test() {
+        let config = toml::from_str::<Config>("[abbreviations]\nHTML = \"HyperText\"").unwrap();
+        assert_eq!(config.abbreviations.len(), 1);
+        for key in ["\"\"", "\" \""] {
+            let Err(e) = toml::from_str::<Config>(&format!("[abbreviations]\n{key} = \"x\""))
+            else {
+                panic!("{key} is accepted");
+            };
+            assert!(e.to_string().contains("must not be empty"));
+        }
+    }
+
+    #[test]
+    fn apply_defaults_test() {
+        let mut config = toml::from_str::<Config>(
+            r#"
+[defaults]
+author = "Me"
+tags = ["blog"]
+math = false
+
+[[cascade]]
+path = "notes/**"
+tags = ["note"]
+template = "note"
+"#,
+        )
+        .unwrap();
+        config.extend(
+            &mut toml::from_str(
+                r#"
+[defaults]
+math = true
+
+[[cascade]]
+path = "notes/rust/**"
+tags = ["rust"]
+"#,
+            )
+            .unwrap(),
+        );
+
+        let apply = |path: &str, metadata: &str| {
+            let mut metadata = format!("title = \"T\"\n{metadata}")
+                .parse::<Metadata>()
+                .unwrap();
+            config.apply_defaults(Path::new(path), &mut metadata);
+            metadata
+        };
+        let metadata = apply("foo.md", "");
+        assert_eq!(metadata.author.as_deref(), Some("Me"));
+        assert_eq!(metadata.tags, Some(vec!["blog".to_string()]));
+        assert_eq!(metadata.math, Some(true));
+        assert_eq!(metadata.template, None);
 
-impl Config {
-    pub fn read(path: impl AsRef<Path>) -> Result<Config> {
-        let s = std::fs::read_to_string(path.as_ref())?;
-        Ok(Config(toml::from_str(&s)?))
+        let metadata = apply("notes/foo.md", "author = \"You\"");
+        assert_eq!(metadata.author.as_deref(), Some("You"));
+        assert_eq!(metadata.tags, Some(vec!["note".to_string()]));
+        assert_eq!(metadata.template.as_deref(), Some("note"));
+
+        let metadata = apply("notes/rust/foo.md", "");
+        assert_eq!(metadata.tags, Some(vec!["rust".to_string()]));
+        assert_eq!(metadata.template.as_deref(), Some("note"));
+
+        assert!(toml::from_str::<Config>("[defaults]\nauthr = \"Me\"").is_err());
+        assert!(toml::from_str::<Config>("[[cascade]]\npath = \"a/**\"\nauthr = \"Me\"").is_err());
     }
 
-    fn context(&self) -> minijinja::Value {
-        context! { site => &self.0}
+    #[test]
+    fn interpolate_env_test() {
+        let var = |name: &str| (name == "ID").then(|| "G-123".to_string());
+        let mut value = toml::from_str::<toml::Value>(
+            r#"
+analytics = "id=${ID}"
+api = "${API_URL:-http://localhost:8080}"
+raw = "$ID {ID}"
+[[inject]]
+position = "head"
+html = "<script>track('${ID}')</script>"
+"#,
+        )
+        .unwrap();
+        interpolate_env(&mut value, &var).unwrap();
+        let config = value.try_into::<Config>().unwrap();
+        assert_eq!(config.get("analytics"), Some("id=G-123"));
+        assert_eq!(config.get("api"), Some("http://localhost:8080"));
+        assert_eq!(config.get("raw"), Some("$ID {ID}"));
+        assert_eq!(config.inject[0].html, "<script>track('G-123')</script>");
+
+        let mut value = toml::from_str::<toml::Value>(r#"a = "${MISSING}""#).unwrap();
+        assert_eq!(
+            interpolate_env(&mut value, &var).unwrap_err().to_string(),
+            "environment variable is not set: MISSING"
+        );
     }
 
-    pub fn extend(&mut self, config: &mut Config) {
-        self.0.append(&mut config.0);
+    #[test]
+    fn inject_test() {
+        let mut config: Config = toml::from_str(
+            r#"
+[[inject]]
+position = "head"
+html = "<script>analytics()</script>"
+
+[[inject]]
+position = "body"
+html = "<p>dev</p>"
+profiles = ["dev"]
+"#,
+        )
+        .unwrap();
+        config
+            .inject
+            .retain(|injection| injection.profiles.contains(&"production".to_string()));
+        let mut html = "<html><head></head><body></body></html>".to_string();
+        config.inject(&mut html);
+        assert_eq!(
+            html,
+            "<html><head><script>analytics()</script></head><body></body></html>"
+        );
     }
-}
 
-pub struct Site {
-    config: Config,
-    root_dir: PathBuf,
-    src_dir: PathBuf,
-    out_dir: PathBuf,
-    article_regex: Option<Regex>,
-}
+    #[test]
+    fn build_profile_test() {
+        let config: Config = toml::from_str(
+            r#"
+[profiles.production]
+minify = false
+base_url = "https://example.com"
 
-impl Site {
-    pub fn new(
-        config: Config,
-        root_dir: PathBuf,
-        out_dir: PathBuf,
-        article_regex: Option<Regex>,
-    ) -> Site {
-        let src_dir = root_dir.join("src");
-        Site {
-            config,
-            root_dir: root_dir.canonicalize().unwrap(),
-            src_dir,
-            out_dir,
-            article_regex,
-        }
+[profiles.staging]
+drafts = "obfuscate"
+"#,
+        )
+        .unwrap();
+        let options = BuildOptions::profile(&config, "production").unwrap();
+        assert_eq!(options.draft_mode, DraftMode::Skip);
+        assert!(options.skip_future);
+        assert!(!options.minify);
+        assert!(options.fingerprint);
+        assert_eq!(options.base_url.as_deref(), Some("https://example.com"));
+
+        let options = BuildOptions::profile(&config, "staging").unwrap();
+        assert_eq!(options.draft_mode, DraftMode::Obfuscate);
+        assert!(!options.skip_future);
+        assert!(!options.minify);
+        assert!(BuildOptions::profile(&config, "prod").is_err());
     }
 
-    pub fn build(&self) -> Result<()> {
-        let src_dir = self.root_dir.join("src");
-        let template_dir = self.root_dir.join("template");
+    #[test]
+    fn lint_titles_test() {
+        let markdown = |path: &str, s: &str| MarkdownFile {
+            relative_path: PathBuf::from(path),
+            markdown: s.parse().unwrap(),
+        };
+        let markdowns = [
+            markdown("a.md", "# Hello\n"),
+            markdown("b.md", "title = \" \"\n"),
+            markdown("c/d.md", "# Hello\n"),
+            markdown("e.md", "# Hello\n\n<!--\npage = true\n-->\n"),
+            markdown("f.md", "# World\n"),
+        ];
+        assert_eq!(
+            lint_titles(&markdowns)
+                .iter()
+                .map(Diagnostic::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "b.md: the title is empty",
+                "a.md: articles have the same title \"Hello\": a.md, c/d.md"
+            ]
+        );
+    }
 
-        let mut env = Environment::new();
-        env.set_loader(path_loader(template_dir));
-        env.set_auto_escape_callback(|_name| minijinja::AutoEscape::None);
-        env.set_keep_trailing_newline(true);
+    #[test]
+    fn sort_by_date_test() {
+        let article = |slug: &str, date: &str| Article {
+            slug: slug.to_string(),
+            date: Some(date.parse().unwrap()),
+            ..Default::default()
+        };
+        let mut articles = vec![
+            article("b", "2024-01-01"),
+            article("c", "2023-01-01"),
+            article("a", "2024-01-01"),
+            article("d", "2024-02-01"),
+        ];
+        sort_by_date(&mut articles);
+        assert_eq!(
+            articles.iter().map(|a| a.slug.as_str()).collect::<Vec<_>>(),
+            vec!["d", "a", "b", "c"]
+        );
+    }
 
-        self.render_markdowns(&env, src_dir)?;
-        if self.article_regex.is_none() {
-            self.copy_files()?;
-        }
-        Ok(())
+    #[test]
+    fn site_stats_test() {
+        let metadata = |date: &str, tags: &[&str]| Metadata {
+            date: Some(date.parse().unwrap()),
+            tags: Some(tags.iter().map(|t| t.to_string()).collect()),
+            ..Default::default()
+        };
+        let articles = [
+            metadata("2023-05-01", &["rust"]),
+            metadata("2024-01-02", &["rust", "web"]),
+            metadata("2022-12-31", &[]),
+        ];
+        assert_eq!(
+            SiteStats::new(&articles),
+            SiteStats {
+                articles: 3,
+                years: BTreeMap::from([(2022, 1), (2023, 1), (2024, 1)]),
+                tags: BTreeMap::from([("rust".to_string(), 2), ("web".to_string(), 1)]),
+                first_date: Some("2022-12-31".parse().unwrap()),
+                last_date: Some("2024-01-02".parse().unwrap()),
+            }
+        );
     }
 
-    fn collect_markdown(&self, src_dir: impl AsRef<Path>) -> Result<Vec<MarkdownFile>> {
-        glob::glob(&format!("{}/**/*.md", src_dir.as_ref().display()))?
-            .filter_map(std::result::Result::ok)
-            .flat_map(|f| {
-                if let Some(ref regex) = self.article_regex {
-                    if regex.is_match(f.as_os_str().to_str().unwrap()) {
-                        Some(f)
-                    } else {
-                        None
-                    }
-                } else {
-                    Some(f)
-                }
-            })
-            .map(|f| -> Result<MarkdownFile> {
-                let relative_path = f.strip_prefix(&src_dir).expect("prefix does not match");
-                log::debug!("found: {}", relative_path.display());
-                Ok(MarkdownFile {
-                    relative_path: PathBuf::from(relative_path),
-                    markdown: std::fs::read_to_string(&f)
-                        .with_context(|| format!("can not read: {}", f.display()))?
-                        .parse()
-                        .with_context(|| format!("can not parse: {}", f.display()))?,
-                })
-            })
-            .collect::<Vec<Result<MarkdownFile>>>()
-            .into_iter()
-            .collect()
+    #[test]
+    fn ancestors_test() {
+        assert_eq!(ancestors(Path::new("foo.md")), Vec::<String>::new());
+        assert_eq!(ancestors(Path::new("notes/foo.md")), vec!["notes"]);
+        assert_eq!(
+            ancestors(Path::new("notes/rust/foo.md")),
+            vec!["notes", "notes/rust"]
+        );
     }
 
-    fn render_markdowns(&self, env: &Environment, src_dir: impl AsRef<Path>) -> Result<()> {
-        let src_dir = src_dir.as_ref().canonicalize().unwrap();
-        log::info!("Collecting markdown: {}", src_dir.display());
-        let (pages, articles) = self
-            .collect_markdown(&src_dir)?
-            .into_iter()
-            .partition::<Vec<MarkdownFile>, _>(|src| src.markdown.metadata.page.unwrap_or(false));
-        log::info!(
-            "Found {} articles and {} pages",
-            articles.len(),
-            pages.len()
+    #[test]
+    fn virtual_pages_test() {
+        let dir = crate::testing::fixture("virtual", 12, 100);
+        std::fs::write(
+            dir.join("config.toml"),
+            r#"
+[[virtual_pages]]
+url = "tags/tag1/"
+template = "page"
+title = "Tag 1"
+query = { tag = "tag1" }
+
+[[virtual_pages]]
+url = "feeds/all.xml"
+template = "page"
+"#,
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, crate::BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("tags/tag1/index.html").unwrap(),
+            "<!DOCTYPE html>\n<a href=\"/article-11/\">Article 11</a>\n<a href=\"/article-1/\">Article 1</a>\n"
+        );
+        assert_eq!(
+            output.text("feeds/all.xml").unwrap().matches("<a ").count(),
+            12
         );
 
-        for article in &articles {
-            anyhow::ensure!(
-                article.markdown.metadata.date.is_some(),
-                "{} doesn't have date",
-                article.relative_path.display()
+        // A virtual page would overwrite a markdown at the same URL.
+        std::fs::write(
+            dir.join("config.toml"),
+            "[[virtual_pages]]\nurl = \"tags/tag1/\"\ntemplate = \"page\"\ntitle = \"Tag 1\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("src/tags/tag1")).unwrap();
+        std::fs::write(
+            dir.join("src/tags/tag1/index.md"),
+            "# Tag 1\n\npage = true\n",
+        )
+        .unwrap();
+        let options = crate::BuildOptions {
+            strict: true,
+            ..crate::BuildOptions::default()
+        };
+        let e = crate::testing::build(&dir, options).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("entries are written to the same tags/tag1/index.html"),
+            "{e:#}"
+        );
+    }
+
+    #[test]
+    fn extend_context_test() {
+        let dir = crate::testing::TempDir::new("hook");
+        let (root_dir, out_dir) = (dir.join("root"), dir.join("out"));
+        crate::bench::synthesize(&root_dir, 2, 100).unwrap();
+        std::fs::write(
+            root_dir.join("template/article.jinja"),
+            "{{ entry.title }}: {{ stars }}",
+        )
+        .unwrap();
+        let mut site = Site::new(
+            Config::default(),
+            root_dir,
+            out_dir.clone(),
+            BuildOptions::default(),
+        )
+        .unwrap();
+        site.extend_context(|article, context| {
+            let stars = if article.url() == "article-1/" { 42 } else { 0 };
+            context.insert("stars".to_string(), Value::from(stars));
+        });
+        site.build().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out_dir.join("article-1/index.html")).unwrap(),
+            "Article 1: 42"
+        );
+    }
+
+    #[test]
+    fn only_test() {
+        let dir = crate::testing::TempDir::new("only");
+        let (root_dir, out_dir) = (dir.join("root"), dir.join("out"));
+        crate::bench::synthesize(&root_dir, 2, 100).unwrap();
+        std::fs::write(
+            root_dir.join("src/tag9.md"),
+            "# Tag 9\n\n<!--\npage = true\nquery = { tag = \"tag9\" }\n-->\n",
+        )
+        .unwrap();
+        let build = |only: Vec<PathBuf>| {
+            let options = BuildOptions {
+                only,
+                ..BuildOptions::default()
+            };
+            Site::new(
+                Config::default(),
+                root_dir.clone(),
+                out_dir.clone(),
+                options,
             )
+            .unwrap()
+            .build()
+            .unwrap();
+        };
+        build(Vec::new());
+        for file in ["article-0/index.html", "tag9/index.html"] {
+            std::fs::write(out_dir.join(file), "stale").unwrap();
         }
+        std::fs::write(
+            root_dir.join("src/article-1.md"),
+            "# Changed\n\n<!--\ndate = \"2000-01-02\"\n-->\n",
+        )
+        .unwrap();
+        build(vec![root_dir.join("src/article-1.md")]);
+        let read = |file: &str| std::fs::read_to_string(out_dir.join(file)).unwrap();
+        assert!(read("article-1/index.html").contains("<h1>Changed</h1>"));
+        assert!(read("index.html").contains(">Changed</a>"));
+        assert_eq!(read("article-0/index.html"), "stale");
+        // It doesn't list article-1.
+        assert_eq!(read("tag9/index.html"), "stale");
 
-        log::info!("Build articles");
-        let mut articles = articles
-            .into_par_iter()
-            .map(|m| -> Result<Article> {
-                let article = Article::new(m);
-                article.render_and_write(&self.config, None, env, &self.out_dir)?;
-                Ok(article)
-            })
-            .collect::<Vec<Result<Article>>>()
-            .into_iter()
-            .collect::<Result<Vec<Article>>>()?;
+        // An asset names the articles referencing it.
+        std::fs::write(root_dir.join("src/diagram.png"), "").unwrap();
+        std::fs::write(
+            root_dir.join("src/article-0.md"),
+            "# Diagram\n\n<!--\ndate = \"2000-01-01\"\n-->\n\n![Diagram](/diagram.png)\n",
+        )
+        .unwrap();
+        build(vec![root_dir.join("src/diagram.png")]);
+        assert!(read("article-0/index.html").contains("<h1>Diagram</h1>"));
+    }
+
+    #[test]
+    fn publish_schedule_test() {
+        let dir = crate::testing::fixture("schedule", 3, 100);
+        std::fs::write(
+            dir.join("src/draft.md"),
+            "# Draft\n\n<!--\ndate = \"2000-01-05\"\ndraft = true\n-->\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("src/sale.md"),
+            "# Sale\n\n<!--\ndate = \"2000-01-01\"\nunpublish_date = \"2000-01-04\"\n-->\n",
+        )
+        .unwrap();
+        let site = Site::new(
+            Config::default(),
+            dir.to_path_buf(),
+            dir.join("out"),
+            BuildOptions::default(),
+        )
+        .unwrap();
+        let today = chrono::NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        assert_eq!(
+            site.publish_schedule(today).unwrap(),
+            r#"{
+  "articles": [
+    {
+      "action": "publish",
+      "date": "2000-01-02",
+      "source_path": "article-1.md",
+      "title": "Article 1"
+    },
+    {
+      "action": "publish",
+      "date": "2000-01-03",
+      "source_path": "article-2.md",
+      "title": "Article 2"
+    },
+    {
+      "action": "unpublish",
+      "date": "2000-01-04",
+      "source_path": "sale.md",
+      "title": "Sale"
+    }
+  ],
+  "next": "2000-01-02"
+}"#
+        );
 
-        // Remove draft articles.
-        articles.retain(|a| !a.draft);
+        std::fs::write(dir.join("config.toml"), "publish_schedule = true\n").unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert!(output.text("publish-schedule.json").is_none());
+        assert!(dir.join("publish-schedule.json").exists());
+    }
 
-        articles.sort_by_key(|a| a.date);
-        articles.reverse();
+    #[test]
+    fn unpublish_date_test() {
+        let dir = crate::testing::fixture("unpublish", 2, 100);
+        std::fs::write(
+            dir.join("src/article-1.md"),
+            "# Sale\n\n<!--\ndate = \"2000-01-02\"\nunpublish_date = \"2000-02-01\"\n-->\n",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert!(output.text("article-1/index.html").is_none());
+        assert!(!output.text("index.html").unwrap().contains("Sale"));
 
-        log::info!("Build pages");
-        for m in pages {
-            let page = Article::new(m);
-            page.render_and_write(&self.config, Some(&articles), env, &self.out_dir)?;
-        }
-        Ok(())
+        std::fs::write(dir.join("config.toml"), "tombstone = \"tombstone\"\n").unwrap();
+        std::fs::write(
+            dir.join("template/tombstone.jinja"),
+            "{{ entry.title }} ended on {{ entry.unpublish_date }}",
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("article-1/index.html").unwrap(),
+            "Sale ended on 2000-02-01"
+        );
+        assert!(!output.text("index.html").unwrap().contains("Sale"));
     }
 
-    fn copy_files(&self) -> Result<()> {
-        log::info!(
-            "Copy files: {} => {}",
-            self.src_dir.display(),
-            self.out_dir.display()
+    #[cfg(feature = "webmention")]
+    #[test]
+    fn webmentions_test() {
+        let dir = crate::testing::fixture("webmentions", 0, 0);
+        for (name, metadata) in [
+            ("past", "date = \"2000-01-01\""),
+            ("future", "date = \"2000-02-01\""),
+            ("draft", "date = \"2000-01-01\"\ndraft = true"),
+        ] {
+            std::fs::write(
+                dir.join(format!("src/{name}.md")),
+                format!("# {name}\n\n<!--\n{metadata}\n-->\n\n[a](https://other.example/{name}) [b](https://me.example/b)\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            dir.join("config.toml"),
+            "base_url = \"https://me.example\"\n",
+        )
+        .unwrap();
+        let config = Config::read(dir.join("config.toml")).unwrap();
+        let options = BuildOptions {
+            today: Some("2000-01-15".parse().unwrap()),
+            ..BuildOptions::profile(&config, "production").unwrap()
+        };
+        let site = Site::new(config, dir.to_path_buf(), dir.join("out"), options).unwrap();
+        assert_eq!(
+            site.webmentions("https://me.example").unwrap(),
+            [(
+                "https://me.example/past/".to_string(),
+                "https://other.example/past".to_string()
+            )]
         );
-        for entry in walkdir::WalkDir::new(&self.src_dir) {
-            let entry = entry?;
-            let src_path = entry.path();
-            if let Some("md") = src_path.extension().map(|ext| ext.to_str().unwrap()) {
-                continue;
-            }
+    }
 
-            let relative_path = src_path.strip_prefix(&self.src_dir).expect("");
-            let out_path = self.out_dir.join(relative_path);
-            log::debug!("{:32} => {}", relative_path.display(), out_path.display());
+    #[test]
+    fn upcoming_events_test() {
+        let dir = crate::testing::fixture("events", 0, 0);
+        for (name, start) in [("past", "2024-04-30T19:00:00"), ("today", "2024-05-01")] {
+            std::fs::write(
+                dir.join(format!("src/{name}.md")),
+                format!("# {name}\n\ndate = \"2024-01-01\"\nevent_start = \"{start}\"\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            "{% for a in events %}{{ a.title }} {% endfor %}|{% for a in upcoming_events %}{{ a.title }} {% endfor %}",
+        )
+        .unwrap();
+        let options = BuildOptions {
+            today: Some("2024-05-01".parse().unwrap()),
+            ..Default::default()
+        };
+        let output = crate::testing::build(&dir, options).unwrap();
+        assert_eq!(output.text("index.html").unwrap(), "past today |today ");
+    }
 
-            if src_path.is_dir() {
-                std::fs::create_dir_all(&out_path).expect("create_dir_all failed")
-            } else {
-                std::fs::copy(src_path, out_path).expect("copy failed");
-            }
+    #[test]
+    fn path_prefix_test() {
+        let dir = crate::testing::fixture("path-prefix", 0, 0);
+        std::fs::write(
+            dir.join("config.toml"),
+            "base_url = \"https://example.com\"\npath_prefix = \"/blog\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("template/page.jinja"),
+            r#"<a href="/about/"></a><a href="{{ path_prefix }}/about/"></a>{{ site.base_url }}/about/"#,
+        )
+        .unwrap();
+        let output = crate::testing::build(&dir, BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.text("index.html").unwrap(),
+            r#"<a href="/blog/about/"></a><a href="/blog/about/"></a>https://example.com/blog/about/"#
+        );
+    }
+
+    #[test]
+    fn group_by_date_test() {
+        let article = |slug: &str, date: Option<&str>| Article {
+            slug: slug.to_string(),
+            date: date.map(|d| d.parse().unwrap()),
+            ..Default::default()
+        };
+        let articles = vec![
+            article("a", Some("2023-05-01")),
+            article("b", None),
+            article("c", Some("2024-01-02")),
+            article("d", Some("2023-05-31")),
+            article("e", Some("2024-03-01")),
+        ];
+        fn groups<K>(groups: Vec<(K, Vec<&Article>)>) -> Vec<(K, Vec<&str>)> {
+            groups
+                .into_iter()
+                .map(|(key, articles)| (key, articles.iter().map(|a| a.slug.as_str()).collect()))
+                .collect()
         }
-        Ok(())
+        assert_eq!(
+            groups(group_by_date(&articles, |date| date.year())),
+            vec![(2024, vec!["e", "c"]), (2023, vec!["d", "a"])]
+        );
+        assert_eq!(
+            groups(group_by_date(&articles, |date| (date.year(), date.month()))),
+            vec![
+                ((2024, 3), vec!["e"]),
+                ((2024, 1), vec!["c"]),
+                ((2023, 5), vec!["d", "a"])
+            ]
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn sort_order_test() {
+        let article = |title: &str, date: &str, update_date: Option<&str>, weight| {
+            let mut article = Article {
+                title: title.to_string(),
+                slug: title.to_lowercase(),
+                date: Some(date.parse().unwrap()),
+                weight,
+                ..Default::default()
+            };
+            article.set_update_date(update_date.map(|d| d.parse().unwrap()));
+            article
+        };
+        let mut articles = vec![
+            article("b", "2024-01-01", None, None),
+            article("C", "2023-01-01", Some("2024-06-01"), Some(1)),
+            article("a", "2024-02-01", None, Some(2)),
+            article("d", "2022-01-01", None, Some(1)),
+        ];
+        let sorted = |articles: &mut Vec<Article>, order: SortOrder| {
+            articles.sort_by(|a, b| order.compare(a, b));
+            articles.iter().map(|a| a.title.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(&mut articles, SortOrder::Date), ["a", "b", "C", "d"]);
+        assert_eq!(
+            sorted(&mut articles, SortOrder::UpdateDate),
+            ["C", "a", "b", "d"]
+        );
+        assert_eq!(
+            sorted(&mut articles, SortOrder::Weight),
+            ["C", "d", "a", "b"]
+        );
+        assert_eq!(
+            sorted(&mut articles, SortOrder::Title),
+            ["a", "b", "C", "d"]
+        );
+    }
 
     #[test]
-    fn slug_to_url_test() {
-        assert_eq!(slug_to_url("foo"), "foo/");
-        assert_eq!(slug_to_url("foo/"), "foo/");
-        assert_eq!(slug_to_url("feed.xml"), "feed.xml");
-        assert_eq!(slug_to_url("feed.xml/"), "feed.xml/");
-        assert_eq!(slug_to_url("index"), "");
-        assert_eq!(slug_to_url(""), "");
-        assert_eq!(slug_to_url("a/b"), "a/b/");
-        assert_eq!(slug_to_url("a/b/"), "a/b/");
-        assert_eq!(slug_to_url("a/b.html"), "a/b.html");
-        assert_eq!(slug_to_url("a/b.html/"), "a/b.html/");
+    fn sort_by_weight_test() {
+        let page = |title: &str, weight| Article {
+            title: title.to_string(),
+            weight,
+            ..Default::default()
+        };
+        let mut pages = vec![
+            page("b", None),
+            page("z", Some(2)),
+            page("a", None),
+            page("y", Some(-1)),
+            page("x", Some(2)),
+        ];
+        sort_by_weight(&mut pages);
+        assert_eq!(
+            pages.iter().map(|p| p.title.as_str()).collect::<Vec<_>>(),
+            vec!["y", "x", "z", "a", "b"]
+        );
     }
 
     #[test]
-    fn url_to_filename_test() {
-        assert_eq!(url_to_filename(""), "index.html");
-        assert_eq!(url_to_filename("a"), "a");
-        assert_eq!(url_to_filename("a/"), "a/index.html");
-        assert_eq!(url_to_filename("a.html"), "a.html");
-        assert_eq!(url_to_filename("a.html/"), "a.html/index.html");
-        assert_eq!(url_to_filename("a/b"), "a/b");
-        assert_eq!(url_to_filename("a/b/"), "a/b/index.html");
-        assert_eq!(url_to_filename("a/b.html"), "a/b.html");
-        assert_eq!(url_to_filename("a/b.html/"), "a/b.html/index.html");
+    fn obfuscated_draft_url_test() {
+        let url = obfuscated_draft_url("secret", "foo/");
+        assert!(url.starts_with("drafts/"));
+        assert!(url.ends_with('/'));
+        assert_eq!(url.len(), "drafts/".len() + 32 + 1);
+        assert_eq!(url, obfuscated_draft_url("secret", "foo/"));
+        assert_ne!(url, obfuscated_draft_url("secret", "bar/"));
+        assert_ne!(url, obfuscated_draft_url("other", "foo/"));
     }
 
     #[test]
@@ -491,6 +5901,20 @@ date = "2018-01-11"
                 ..Default::default()
             }
         );
+
+        let s = r#"title = "Hello"
+description = "A greeting"
+keywords = ["hello", "world"]
+"#;
+        assert_eq!(
+            s.parse::<Metadata>().unwrap(),
+            Metadata {
+                title: "Hello".to_string(),
+                description: Some("A greeting".to_string()),
+                keywords: Some(vec!["hello".to_string(), "world".to_string()]),
+                ..Default::default()
+            }
+        );
     }
 
     #[test]
@@ -511,7 +5935,8 @@ hello world
                     date: Some("2018-01-11".parse().unwrap()),
                     ..Default::default()
                 },
-                content: "hello world\n".to_string(),
+                content: Some("hello world\n".to_string()),
+                content_line: 5,
             }
         );
 
@@ -528,7 +5953,8 @@ hello world
                     title: "Hello".to_string(),
                     ..Default::default()
                 },
-                content: "hello world\n".to_string(),
+                content: Some("hello world\n".to_string()),
+                content_line: 5,
             }
         );
 
@@ -544,7 +5970,8 @@ hello world
                     title: "Hello".to_string(),
                     ..Default::default()
                 },
-                content: "hello world\n".to_string(),
+                content: Some("hello world\n".to_string()),
+                content_line: 4,
             }
         );
 
@@ -564,7 +5991,8 @@ hello world
                     date: Some("2018-01-11".parse().unwrap()),
                     ..Default::default()
                 },
-                content: "hello world\n".to_string(),
+                content: Some("hello world\n".to_string()),
+                content_line: 6,
             }
         );
 
@@ -586,8 +6014,142 @@ hello world
                     date: Some("2018-01-11".parse().unwrap()),
                     ..Default::default()
                 },
-                content: "hello world\n".to_string(),
+                content: Some("hello world\n".to_string()),
+                content_line: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_markdown_without_blank_line_test() {
+        let metadata = || Metadata {
+            title: "Hello".to_string(),
+            date: Some("2018-01-11".parse().unwrap()),
+            ..Default::default()
+        };
+
+        // The content starts right after the fence.
+        let s = "# Hello\n<!--\ndate = \"2018-01-11\"\n-->\nhello world\n";
+        assert_eq!(
+            s.parse::<Markdown>().unwrap(),
+            Markdown {
+                metadata: metadata(),
+                content: Some("hello world\n".to_string()),
+                content_line: 5,
+            }
+        );
+
+        // The content starts at the first line which doesn't look like TOML.
+        let s = "title = \"Hello\"\ndate = \"2018-01-11\"\nhello world\n";
+        assert_eq!(
+            s.parse::<Markdown>().unwrap(),
+            Markdown {
+                metadata: metadata(),
+                content: Some("hello world\n".to_string()),
+                content_line: 3,
+            }
+        );
+
+        // Metadata only.
+        let s = "# Hello\n\n<!--\ndate = \"2018-01-11\"\n-->\n";
+        assert_eq!(
+            s.parse::<Markdown>().unwrap(),
+            Markdown {
+                metadata: metadata(),
+                content: None,
+                content_line: 7,
+            }
+        );
+
+        // A blank line and an array spanning lines in a fence.
+        let s = "# Hello\n\n<!--\ndate = \"2018-01-11\"\n\ntags = [\n  \"a]\",\n  \"b\",\n]\n-->\n\nhello\n";
+        let markdown = s.parse::<Markdown>().unwrap();
+        assert_eq!(
+            markdown.metadata.tags,
+            Some(vec!["a]".to_string(), "b".to_string()])
+        );
+        assert_eq!(markdown.content, Some("hello\n".to_string()));
+
+        // An array spanning lines without a fence.
+        let s = "title = \"Hello\"\ntags = [\n\"a\",\n]\nhello\n";
+        let markdown = s.parse::<Markdown>().unwrap();
+        assert_eq!(markdown.metadata.tags, Some(vec!["a".to_string()]));
+        assert_eq!(markdown.content, Some("hello\n".to_string()));
+
+        let e = "# Hello\n\n<!--\ndate = \"2018-01-11\"\n\nhello\n"
+            .parse::<Markdown>()
+            .unwrap_err();
+        assert_eq!(e.to_string(), "line 3: metadata is not closed by \"-->\"");
+    }
+
+    #[test]
+    fn parse_markdown_title_test() {
+        for title in [
+            r#"Say "hello""#,
+            r"C:\Users\foo",
+            r"\u0041 is not A",
+            "It's # not a comment",
+            r#"Tricky ''' and """ quotes"#,
+            "[section] = {inline}",
+        ] {
+            let s = format!("# {title}\n\ndate = \"2018-01-11\"\n\nhello\n");
+            let markdown = s.parse::<Markdown>().unwrap();
+            assert_eq!(markdown.metadata.title, title);
+            assert_eq!(markdown.metadata.date, Some("2018-01-11".parse().unwrap()));
+        }
+    }
+
+    #[test]
+    fn metadata_error_test() {
+        let s = r#"# Hello
+
+<!--
+date = "2018-01-11"
+tags = "rust
+-->
+
+hello
+"#;
+        let e = s.parse::<Markdown>().unwrap_err();
+        let e = e.downcast_ref::<MetadataError>().unwrap();
+        assert_eq!(e.line, Some(5));
+        assert_eq!(e.column, Some(13));
+        assert_eq!(
+            e.to_string(),
+            r#"line 5, column 13: invalid metadata: invalid basic string
+  |
+5 | tags = "rust
+  |             ^"#
+        );
+
+        let s = "title = \"a\"\ndate = 1\n\nhello\n";
+        let e = s.parse::<Markdown>().unwrap_err();
+        let e = e.downcast_ref::<MetadataError>().unwrap();
+        assert_eq!((e.line, e.column), (Some(2), Some(8)));
+        assert!(e.snippet.ends_with("2 | date = 1\n  |        ^"));
+    }
+
+    #[test]
+    fn parse_markdown_crlf_test() {
+        let s =
+            "\u{feff}# Hello\r\n\r\n<!--\r\ndate = \"2018-01-11\"\r\n-->\r\n\r\nhello\r\nworld\r\n";
+        assert_eq!(
+            s.parse::<Markdown>().unwrap(),
+            Markdown {
+                metadata: Metadata {
+                    title: "Hello".to_string(),
+                    date: Some("2018-01-11".parse().unwrap()),
+                    ..Default::default()
+                },
+                content: Some("hello\nworld\n".to_string()),
+                content_line: 7,
             }
         );
+
+        let s = "<!-- -*- mode: markdown -*- -->\r\n\r\ntitle = \"Hello\"\r\n\r\nhello\r\n";
+        assert_eq!(
+            s.parse::<Markdown>().unwrap().metadata.title,
+            "Hello".to_string()
+        );
     }
 }
@@ -0,0 +1,79 @@
+//! Slide decks: the content of an article with `slides = true`, split by `---` (thematic
+//! breaks), is wrapped into `<section class="slide">`s, which a template pages through.
+
+use pulldown_cmark::Event;
+
+/// Split markdown `events` into slides at thematic breaks at the top level, i.e. not in a list
+/// or a block quote. Empty slides are skipped.
+fn split<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Vec<Event<'a>>> {
+    let mut slides = vec![Vec::new()];
+    let mut depth = 0;
+    for event in events {
+        match event {
+            Event::Rule if depth == 0 => {
+                slides.push(Vec::new());
+                continue;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+        slides.last_mut().unwrap().push(event);
+    }
+    slides.retain(|slide| !slide.is_empty());
+    slides
+}
+
+/// Wrap each slide in `events` into a section with the id `slide-N`, from 1.
+pub fn wrap<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut wrapped = Vec::new();
+    for (i, slide) in split(events).into_iter().enumerate() {
+        wrapped.push(Event::Html(
+            format!("<section class=\"slide\" id=\"slide-{}\">\n", i + 1).into(),
+        ));
+        wrapped.extend(slide);
+        wrapped.push(Event::Html("</section>\n".into()));
+    }
+    wrapped
+}
+
+/// The number of slides in `events`.
+pub fn count<'a>(events: impl Iterator<Item = Event<'a>>) -> usize {
+    split(events).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_test() {
+        let markdown =
+            "# Title\n\n---\n\nOne\n\n---\n\n---\n\nTwo\n\n<hr />\n\n> Three\n>\n> ***\n";
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(
+            &mut html,
+            wrap(pulldown_cmark::Parser::new(markdown)).into_iter(),
+        );
+        assert_eq!(
+            html,
+            r#"<section class="slide" id="slide-1">
+<h1>Title</h1>
+</section>
+<section class="slide" id="slide-2">
+<p>One</p>
+</section>
+<section class="slide" id="slide-3">
+<p>Two</p>
+<hr />
+<blockquote>
+<p>Three</p>
+<hr />
+</blockquote>
+</section>
+"#
+        );
+        assert_eq!(count(pulldown_cmark::Parser::new(markdown)), 3);
+        assert_eq!(count(pulldown_cmark::Parser::new("")), 0);
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// `[humans]`: the people behind the site, for `humans.txt`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HumansConfig {
+    #[serde(default)]
+    pub team: Vec<Human>,
+    #[serde(default)]
+    pub thanks: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Human {
+    pub name: String,
+    /// e.g. "Author"
+    pub role: Option<String>,
+    pub contact: Option<String>,
+    pub location: Option<String>,
+}
+
+impl HumansConfig {
+    pub fn humans_txt(&self) -> String {
+        let mut s = String::from("/* TEAM */\n");
+        for human in &self.team {
+            s.push_str(&format!(
+                "    {}: {}\n",
+                human.role.as_deref().unwrap_or("Name"),
+                human.name
+            ));
+            if let Some(contact) = human.contact.as_ref() {
+                s.push_str(&format!("    Contact: {contact}\n"));
+            }
+            if let Some(location) = human.location.as_ref() {
+                s.push_str(&format!("    Location: {location}\n"));
+            }
+            s.push('\n');
+        }
+        if !self.thanks.is_empty() {
+            s.push_str("/* THANKS */\n");
+            for name in &self.thanks {
+                s.push_str(&format!("    {name}\n"));
+            }
+            s.push('\n');
+        }
+        s.push_str("/* SITE */\n    Software: site\n");
+        s
+    }
+}
+
+/// `[security]`: how to report security issues, for `.well-known/security.txt` (RFC 9116).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// e.g. `["mailto:security@example.com"]`
+    pub contact: Vec<String>,
+    /// The date when the file should be considered stale.
+    pub expires: chrono::NaiveDate,
+    pub encryption: Option<String>,
+    pub acknowledgments: Option<String>,
+    pub policy: Option<String>,
+    pub hiring: Option<String>,
+    #[serde(default)]
+    pub preferred_languages: Vec<String>,
+}
+
+impl SecurityConfig {
+    /// `base_url` is used for `Canonical`, if it's given. `expires` is warned if it's not after
+    /// `today`.
+    pub fn security_txt(&self, base_url: Option<&str>, today: chrono::NaiveDate) -> Result<String> {
+        anyhow::ensure!(
+            !self.contact.is_empty(),
+            "`contact` in [security] must not be empty"
+        );
+        if self.expires <= today {
+            log::warn!("`expires` in [security] has passed: {}", self.expires);
+        }
+        let mut s = String::new();
+        for contact in &self.contact {
+            s.push_str(&format!("Contact: {contact}\n"));
+        }
+        s.push_str(&format!(
+            "Expires: {}T00:00:00Z\n",
+            self.expires.format("%Y-%m-%d")
+        ));
+        for (name, value) in [
+            ("Encryption", &self.encryption),
+            ("Acknowledgments", &self.acknowledgments),
+            ("Policy", &self.policy),
+            ("Hiring", &self.hiring),
+        ] {
+            if let Some(value) = value {
+                s.push_str(&format!("{name}: {value}\n"));
+            }
+        }
+        if !self.preferred_languages.is_empty() {
+            s.push_str(&format!(
+                "Preferred-Languages: {}\n",
+                self.preferred_languages.join(", ")
+            ));
+        }
+        if let Some(base_url) = base_url {
+            s.push_str(&format!(
+                "Canonical: {}/.well-known/security.txt\n",
+                base_url.trim_end_matches('/')
+            ));
+        }
+        Ok(s)
+    }
+}
+
+/// `[manifest]`: the web app manifest, for `site.webmanifest`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestConfig {
+    /// Defaults to `title` in the config.
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_name: Option<String>,
+    /// Defaults to `description` in the config.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default = "ManifestConfig::default_start_url")]
+    pub start_url: String,
+    #[serde(default = "ManifestConfig::default_display")]
+    pub display: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
+    #[serde(default)]
+    pub icons: Vec<ManifestIcon>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestIcon {
+    /// e.g. "/icon-192.png"
+    pub src: String,
+    /// e.g. "192x192"
+    pub sizes: String,
+    /// e.g. "image/png"
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl ManifestConfig {
+    fn default_start_url() -> String {
+        "/".to_string()
+    }
+
+    fn default_display() -> String {
+        "standalone".to_string()
+    }
+
+    /// Prefix root-relative URLs with `prefix`, such as "/blog".
+    pub fn prefix_urls(&mut self, prefix: &str) {
+        for url in std::iter::once(&mut self.start_url)
+            .chain(self.icons.iter_mut().map(|icon| &mut icon.src))
+        {
+            if url.starts_with('/') && !url.starts_with("//") {
+                url.insert_str(0, prefix);
+            }
+        }
+    }
+
+    /// `title` and `description` are the site's ones, used if they are not given.
+    pub fn webmanifest(&self, title: Option<&str>, description: Option<&str>) -> Result<String> {
+        let mut manifest = self.clone();
+        manifest.name = manifest.name.or(title.map(String::from));
+        anyhow::ensure!(
+            manifest.name.is_some(),
+            "`name` in [manifest], or `title` in the config, must be set"
+        );
+        manifest.description = manifest.description.or(description.map(String::from));
+        serde_json::to_string_pretty(&manifest).map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn static_files_test() {
+        let config = toml::from_str::<toml::Table>(
+            r#"
+[humans]
+team = [{ name = "Hayato Ito", role = "Author", location = "Tokyo" }]
+thanks = ["Rust"]
+
+[security]
+contact = ["mailto:security@example.com"]
+expires = "2099-01-01"
+preferred_languages = ["en", "ja"]
+
+[manifest]
+short_name = "Site"
+icons = [{ src = "/icon-192.png", sizes = "192x192", type = "image/png" }]
+"#,
+        )
+        .unwrap();
+        let humans = config["humans"].clone().try_into::<HumansConfig>().unwrap();
+        assert_eq!(
+            humans.humans_txt(),
+            "/* TEAM */
+    Author: Hayato Ito
+    Location: Tokyo
+
+/* THANKS */
+    Rust
+
+/* SITE */
+    Software: site
+"
+        );
+
+        let security = config["security"]
+            .clone()
+            .try_into::<SecurityConfig>()
+            .unwrap();
+        assert_eq!(
+            security
+                .security_txt(
+                    Some("https://example.com/"),
+                    chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+                )
+                .unwrap(),
+            "Contact: mailto:security@example.com
+Expires: 2099-01-01T00:00:00Z
+Preferred-Languages: en, ja
+Canonical: https://example.com/.well-known/security.txt
+"
+        );
+
+        let manifest = config["manifest"]
+            .clone()
+            .try_into::<ManifestConfig>()
+            .unwrap();
+        assert_eq!(
+            manifest.webmanifest(Some("My Site"), None).unwrap(),
+            r#"{
+  "name": "My Site",
+  "short_name": "Site",
+  "start_url": "/",
+  "display": "standalone",
+  "icons": [
+    {
+      "src": "/icon-192.png",
+      "sizes": "192x192",
+      "type": "image/png"
+    }
+  ]
+}"#
+        );
+        assert!(manifest.webmanifest(None, None).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! `site stats`: content analytics of the published articles, such as posts per year, tags,
+//! gaps between posts, and domains which articles link to.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::site::Article;
+use crate::text;
+use crate::webmention;
+
+#[derive(Debug, Default, Serialize)]
+pub struct ContentStats {
+    pub articles: usize,
+    /// Year => the statistics of the articles dated in the year.
+    pub years: BTreeMap<i32, YearStats>,
+    /// Tags by the number of articles, most used first.
+    pub tags: Vec<(String, usize)>,
+    /// The longest gaps between consecutive articles, longest first.
+    pub gaps: Vec<Gap>,
+    /// Domains of external links by the number of links, most linked first.
+    pub domains: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct YearStats {
+    pub articles: usize,
+    /// Of the articles which are not protected.
+    pub average_words: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Gap {
+    pub days: i64,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// The title of the article which ended the gap.
+    pub title: String,
+}
+
+impl ContentStats {
+    /// Links under `site_url` are not counted as external links. Rankings have `top` entries at
+    /// most. Protected articles are counted, but their content is not, such as their words.
+    pub fn new(articles: &[Article], site_url: Option<&str>, top: usize) -> ContentStats {
+        let mut stats = ContentStats {
+            articles: articles.len(),
+            ..ContentStats::default()
+        };
+        // Year => (words, articles) of the articles which are not protected.
+        let mut words = BTreeMap::<i32, (usize, usize)>::new();
+        let mut tags = BTreeMap::<&str, usize>::new();
+        let mut domains = BTreeMap::<String, usize>::new();
+        for article in articles {
+            for tag in article.tags() {
+                *tags.entry(tag).or_default() += 1;
+            }
+            let year = article.date().map(|date| {
+                use chrono::Datelike as _;
+
+                date.year()
+            });
+            if let Some(year) = year {
+                stats.years.entry(year).or_default().articles += 1;
+            }
+            if article.is_protected() {
+                continue;
+            }
+            if let Some(year) = year {
+                let (words, articles) = words.entry(year).or_default();
+                *words += text::word_count(&article.plain_text());
+                *articles += 1;
+            }
+            for link in webmention::external_links(article.content(), site_url.unwrap_or_default())
+            {
+                if let Some(domain) = domain(&link) {
+                    *domains.entry(domain.to_string()).or_default() += 1;
+                }
+            }
+        }
+        for (year, (words, articles)) in words {
+            stats.years.get_mut(&year).unwrap().average_words = words / articles;
+        }
+        stats.tags = ranking(tags.into_iter().map(|(tag, n)| (tag.to_string(), n)), top);
+        stats.domains = ranking(domains, top);
+
+        let mut dated = articles
+            .iter()
+            .filter_map(|article| Some((article.date()?, article.title())))
+            .collect::<Vec<_>>();
+        dated.sort_by_key(|(date, _)| *date);
+        let mut gaps = dated
+            .windows(2)
+            .map(|pair| Gap {
+                days: (pair[1].0 - pair[0].0).num_days(),
+                from: pair[0].0,
+                to: pair[1].0,
+                title: pair[1].1.to_string(),
+            })
+            .collect::<Vec<_>>();
+        gaps.sort_by(|a, b| b.days.cmp(&a.days).then(a.from.cmp(&b.from)));
+        gaps.truncate(top);
+        stats.gaps = gaps;
+        stats
+    }
+}
+
+/// Counts, sorted by the count descending, then by the key.
+fn ranking(counts: impl IntoIterator<Item = (String, usize)>, top: usize) -> Vec<(String, usize)> {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.truncate(top);
+    counts
+}
+
+/// The host of `url`, without `www.`, such as "example.com" for "https://www.example.com/a".
+pub(crate) fn domain(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next()?;
+    Some(host.strip_prefix("www.").unwrap_or(host)).filter(|host| !host.is_empty())
+}
+
+impl fmt::Display for ContentStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Articles: {}", self.articles)?;
+        writeln!(f, "\nPer year:")?;
+        for (year, stats) in self.years.iter().rev() {
+            writeln!(
+                f,
+                "  {year}  {:4} articles  {:6} words on average",
+                stats.articles, stats.average_words
+            )?;
+        }
+        writeln!(f, "\nTags:")?;
+        for (tag, n) in &self.tags {
+            writeln!(f, "  {n:4}  {tag}")?;
+        }
+        writeln!(f, "\nLongest gaps:")?;
+        for gap in &self.gaps {
+            writeln!(
+                f,
+                "  {:4} days  {} .. {}  {}",
+                gap.days, gap.from, gap.to, gap.title
+            )?;
+        }
+        writeln!(f, "\nLinked domains:")?;
+        for (domain, n) in &self.domains {
+            writeln!(f, "  {n:4}  {domain}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BuildOptions;
+
+    #[test]
+    fn content_stats_test() {
+        assert_eq!(text::word_count("Hello, world! It's 日本語."), 6);
+        assert_eq!(
+            domain("https://www.example.com:8080/a?b"),
+            Some("example.com")
+        );
+
+        let dir = crate::testing::fixture("stats", 0, 0);
+        for (name, date, tags, body) in [
+            ("a", "2023-01-01", r#"["rust"]"#, "One two [x](https://github.com/a)."),
+            ("b", "2023-03-02", r#"["rust", "web"]"#, "One two three four."),
+            ("c", "2024-01-01", "[]", "[y](https://www.github.com/b) [z](https://example.com/c) [me](https://me.example/d)"),
+            ("d", "2099-01-01", "[]", "A future article."),
+        ] {
+            std::fs::write(
+                dir.join(format!("src/{name}.md")),
+                format!("# {name}\n\ndate = \"{date}\"\ntags = {tags}\n\n{body}\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            dir.join("config.toml"),
+            "base_url = \"https://me.example\"\n",
+        )
+        .unwrap();
+        let content_stats = || {
+            let config = crate::Config::read(dir.join("config.toml")).unwrap();
+            let options = BuildOptions::profile(&config, "production").unwrap();
+            crate::Site::new(config, dir.to_path_buf(), dir.join("out"), options)
+                .unwrap()
+                .content_stats(1)
+                .unwrap()
+        };
+        // The future article is not published.
+        let stats = content_stats();
+        assert_eq!(stats.articles, 3);
+        assert_eq!(stats.years[&2023].articles, 2);
+        assert_eq!(stats.years[&2023].average_words, 3);
+        assert_eq!(stats.tags, [("rust".to_string(), 2)]);
+        assert_eq!(stats.gaps.len(), 1);
+        assert_eq!(stats.gaps[0].days, 305);
+        assert_eq!(stats.gaps[0].title, "c");
+        assert_eq!(stats.domains, [("github.com".to_string(), 2)]);
+        assert!(stats.to_string().contains("  2023     2 articles"));
+
+        // A protected article is counted, but its words and links are not.
+        #[cfg(feature = "protected")]
+        {
+            std::fs::write(
+                dir.join("src/e.md"),
+                "# e\n\ndate = \"2023-06-01\"\nprotected = true\n\nOne two three four five six [x](https://github.com/c).\n",
+            )
+            .unwrap();
+            std::fs::write(
+                dir.join("config.toml"),
+                "base_url = \"https://me.example\"\n[protected]\npassword = \"secret\"\n",
+            )
+            .unwrap();
+            let stats = content_stats();
+            assert_eq!(stats.articles, 4);
+            assert_eq!(stats.years[&2023].articles, 3);
+            assert_eq!(stats.years[&2023].average_words, 3);
+            assert_eq!(stats.domains, [("github.com".to_string(), 2)]);
+        }
+    }
+}
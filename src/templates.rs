@@ -0,0 +1,128 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Static dependencies between templates, by `{% extends %}`, `{% include %}`, `{% import %}`
+/// and `{% from ... import %}` with a string literal. A template name is a path relative to
+/// the template directory, such as `partials/header.jinja`.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct TemplateGraph {
+    /// Template name => the templates it refers to directly.
+    pub dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl TemplateGraph {
+    /// Load all files in `template_dir`.
+    pub fn load(template_dir: &Path) -> Result<TemplateGraph> {
+        let mut graph = TemplateGraph::default();
+        if !template_dir.exists() {
+            return Ok(graph);
+        }
+        for entry in walkdir::WalkDir::new(template_dir) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .strip_prefix(template_dir)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let source = std::fs::read_to_string(entry.path())?;
+            graph.dependencies.insert(name, dependencies(&source));
+        }
+        Ok(graph)
+    }
+
+    /// `name` and all the templates it depends on, transitively.
+    pub fn closure(&self, name: &str) -> BTreeSet<String> {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![name.to_string()];
+        while let Some(name) = stack.pop() {
+            if let Some(dependencies) = self.dependencies.get(&name) {
+                stack.extend(
+                    dependencies
+                        .iter()
+                        .filter(|d| !visited.contains(*d))
+                        .cloned(),
+                );
+            }
+            visited.insert(name);
+        }
+        visited
+    }
+
+    /// Templates which are not reachable from any of `roots`.
+    pub fn unused<'a>(&self, roots: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let used = roots
+            .into_iter()
+            .flat_map(|root| self.closure(root))
+            .collect::<BTreeSet<_>>();
+        self.dependencies
+            .keys()
+            .filter(|name| !used.contains(*name))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The templates which `source` refers to directly.
+fn dependencies(source: &str) -> BTreeSet<String> {
+    static TAG: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"\{%-?\s*(?:extends|include|import|from)\s+(?:"([^"]+)"|'([^']+)')"#).unwrap()
+    });
+    TAG.captures_iter(source)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_graph_test() {
+        assert_eq!(
+            dependencies(
+                r#"{% extends "base.jinja" %}{%- include 'nav.jinja' %}{% from "macros.jinja" import m %}{% include name %}"#
+            ),
+            BTreeSet::from([
+                "base.jinja".to_string(),
+                "macros.jinja".to_string(),
+                "nav.jinja".to_string()
+            ])
+        );
+
+        let graph = TemplateGraph {
+            dependencies: BTreeMap::from([
+                (
+                    "article.jinja".to_string(),
+                    BTreeSet::from(["base.jinja".to_string()]),
+                ),
+                (
+                    "base.jinja".to_string(),
+                    BTreeSet::from(["nav.jinja".to_string()]),
+                ),
+                ("nav.jinja".to_string(), BTreeSet::new()),
+                (
+                    "old.jinja".to_string(),
+                    BTreeSet::from(["base.jinja".to_string()]),
+                ),
+            ]),
+        };
+        assert_eq!(
+            graph.closure("article.jinja"),
+            BTreeSet::from([
+                "article.jinja".to_string(),
+                "base.jinja".to_string(),
+                "nav.jinja".to_string()
+            ])
+        );
+        assert_eq!(graph.unused(["article.jinja"]), vec!["old.jinja"]);
+    }
+}
@@ -0,0 +1,142 @@
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// `[terminology]`: terms to check in the text of markdowns, excluding code.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Terminology {
+    /// Terms which must be written in this way, such as `JavaScript`. Other casings, such as
+    /// `Javascript`, are reported.
+    #[serde(default)]
+    dictionary: Vec<String>,
+    /// Banned terms => a replacement, or an empty string if there is none. Case-insensitive.
+    #[serde(default)]
+    banned: BTreeMap<String, String>,
+}
+
+impl Terminology {
+    pub fn is_empty(&self) -> bool {
+        self.dictionary.is_empty() && self.banned.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Terminology) {
+        self.dictionary.extend(other.dictionary);
+        self.banned.extend(other.banned);
+    }
+
+    pub fn checker(&self) -> Checker {
+        let regex = |term: &str| Regex::new(&format!("(?i){}", regex::escape(term))).unwrap();
+        Checker {
+            dictionary: self
+                .dictionary
+                .iter()
+                .map(|term| (regex(term), term.clone()))
+                .collect(),
+            banned: self
+                .banned
+                .iter()
+                .map(|(term, replacement)| (regex(term), replacement.clone()))
+                .collect(),
+        }
+    }
+}
+
+pub struct Checker {
+    dictionary: Vec<(Regex, String)>,
+    banned: Vec<(Regex, String)>,
+}
+
+impl Checker {
+    /// Check the text of `markdown`, and return problems with their line numbers, where
+    /// `first_line` is the line number of `markdown` in its file.
+    pub fn check(&self, markdown: &str, first_line: usize) -> Vec<(usize, String)> {
+        let mut problems = Vec::new();
+        let mut in_code_block = false;
+        for (event, range) in Parser::new(markdown).into_offset_iter() {
+            let text = match event {
+                Event::Start(Tag::CodeBlock(_)) => {
+                    in_code_block = true;
+                    continue;
+                }
+                Event::End(TagEnd::CodeBlock) => {
+                    in_code_block = false;
+                    continue;
+                }
+                Event::Text(text) if !in_code_block => text,
+                _ => continue,
+            };
+            let line = first_line + markdown[..range.start].matches('\n').count();
+            for (regex, term) in &self.dictionary {
+                for m in words(regex, &text) {
+                    if m.as_str() != term {
+                        problems.push((line, format!("`{}` should be `{term}`", m.as_str())));
+                    }
+                }
+            }
+            for (regex, replacement) in &self.banned {
+                for m in words(regex, &text) {
+                    problems.push((
+                        line,
+                        if replacement.is_empty() {
+                            format!("`{}` should not be used", m.as_str())
+                        } else {
+                            format!("`{}` should be `{replacement}`", m.as_str())
+                        },
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Matches of `regex` which are whole words, e.g. not `Java` in `JavaScript`.
+fn words<'a>(regex: &'a Regex, text: &'a str) -> impl Iterator<Item = regex::Match<'a>> {
+    regex.find_iter(text).filter(|m| {
+        !text[..m.start()]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric)
+            && !text[m.end()..]
+                .chars()
+                .next()
+                .is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_test() {
+        let terminology = toml::from_str::<Terminology>(
+            r#"
+dictionary = ["JavaScript", "C++"]
+banned = { utilize = "use", simply = "" }
+"#,
+        )
+        .unwrap();
+        let markdown = r#"Javascript and JavaScript, not Java.
+
+Simply utilize c++.
+
+```js
+// javascript in code is fine.
+```
+
+`javascript` too.
+"#;
+        assert_eq!(
+            terminology.checker().check(markdown, 10),
+            vec![
+                (10, "`Javascript` should be `JavaScript`".to_string()),
+                (12, "`c++` should be `C++`".to_string()),
+                (12, "`Simply` should not be used".to_string()),
+                (12, "`utilize` should be `use`".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Golden-file tests for sites, using this crate as a library.
+//!
+//! ```no_run
+//! let output = site::testing::build("tests/fixture", site::BuildOptions::default()).unwrap();
+//! assert!(output.text("index.html").unwrap().contains("<h1>"));
+//! // Set `SITE_UPDATE_GOLDEN=1` to write the output into the golden directory instead.
+//! output.assert_golden("tests/golden");
+//! ```
+
+use anyhow::{Context as _, Result};
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::site::{Config, Site};
+
+/// The environment variable which makes `Output::assert_golden` update the golden directory.
+pub const UPDATE_GOLDEN: &str = "SITE_UPDATE_GOLDEN";
+
+/// The lines of context around changes in a diff.
+const CONTEXT: usize = 3;
+
+/// The files which a build wrote, by slash-separated paths relative to `out-dir`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Output {
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// A directory for a test, which is removed when dropped, even if the test fails.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// A new empty directory, such as `site-NAME-test-PID-N` in the system's temp dir.
+    pub fn new(name: &str) -> TempDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "site-{name}-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A site with `articles` markdowns of about `size` bytes each and minimal templates, made by
+/// `bench::synthesize`, in a `TempDir`.
+pub fn fixture(name: &str, articles: usize, size: usize) -> TempDir {
+    let dir = TempDir::new(name);
+    crate::bench::synthesize(&dir, articles, size).unwrap();
+    dir
+}
+
+/// Build the site in `root_dir`, with its `config.toml` if any, and read the output into memory.
+pub fn build(root_dir: impl AsRef<Path>, options: crate::BuildOptions) -> Result<Output> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let root_dir = root_dir.as_ref();
+    let config_file = root_dir.join("config.toml");
+    let config = if config_file.exists() {
+        Config::read(config_file)?
+    } else {
+        Config::default()
+    };
+    let out_dir = std::env::temp_dir().join(format!(
+        "site-testing-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = Site::new(config, root_dir.to_path_buf(), out_dir.clone(), options)
+        .and_then(|site| site.build())
+        .and_then(|_| Output::read(&out_dir));
+    if out_dir.exists() {
+        std::fs::remove_dir_all(&out_dir)?;
+    }
+    result
+}
+
+impl Output {
+    /// Read all files in `dir`.
+    pub fn read(dir: &Path) -> Result<Output> {
+        let mut files = BTreeMap::new();
+        for entry in walkdir::WalkDir::new(dir) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                let path = entry.path().strip_prefix(dir)?;
+                files.insert(
+                    crate::site::slash_path(path),
+                    std::fs::read(entry.path())
+                        .with_context(|| format!("can not read: {}", entry.path().display()))?,
+                );
+            }
+        }
+        Ok(Output { files })
+    }
+
+    /// The content of the file at `path`, such as `about/index.html`, if it's UTF-8.
+    pub fn text(&self, path: &str) -> Option<&str> {
+        self.files
+            .get(path)
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+
+    /// Write the files into `dir`, replacing its content.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        for (path, content) in &self.files {
+            let file = dir.join(path);
+            std::fs::create_dir_all(file.parent().unwrap())?;
+            std::fs::write(file, content)?;
+        }
+        Ok(())
+    }
+
+    /// Differences from the files in `golden_dir`, or `None` if they are the same.
+    pub fn diff(&self, golden_dir: &Path) -> Result<Option<String>> {
+        let golden = Output::read(golden_dir)
+            .with_context(|| format!("can not read golden dir: {}", golden_dir.display()))?;
+        let mut report = String::new();
+        let paths = golden
+            .files
+            .keys()
+            .chain(self.files.keys())
+            .collect::<std::collections::BTreeSet<_>>();
+        for path in paths {
+            match (golden.files.get(path), self.files.get(path)) {
+                (Some(expected), Some(actual)) if expected != actual => {
+                    report.push_str(&format!("--- {path}\n"));
+                    match (std::str::from_utf8(expected), std::str::from_utf8(actual)) {
+                        (Ok(expected), Ok(actual)) => {
+                            report.push_str(&diff_lines(expected, actual))
+                        }
+                        _ => report.push_str("binary files differ\n"),
+                    }
+                }
+                (Some(_), None) => report.push_str(&format!("--- {path}\nmissing in the output\n")),
+                (None, Some(_)) => report.push_str(&format!("--- {path}\nnot in the golden dir\n")),
+                _ => {}
+            }
+        }
+        Ok((!report.is_empty()).then_some(report))
+    }
+
+    /// Panic with the differences from the files in `golden_dir`, if any. If
+    /// `SITE_UPDATE_GOLDEN` is set, write the files into `golden_dir` instead.
+    pub fn assert_golden(&self, golden_dir: impl AsRef<Path>) {
+        let golden_dir = golden_dir.as_ref();
+        if std::env::var_os(UPDATE_GOLDEN).is_some() {
+            self.write(golden_dir).unwrap();
+            return;
+        }
+        if let Some(diff) = self.diff(golden_dir).unwrap() {
+            panic!(
+                "the output differs from {} (set {UPDATE_GOLDEN}=1 to update it):\n{diff}",
+                golden_dir.display()
+            );
+        }
+    }
+}
+
+/// A line diff of `expected` and `actual`, where removed lines start with `-` and added lines
+/// start with `+`, with a few lines of context.
+pub fn diff_lines(expected: &str, actual: &str) -> String {
+    let a = expected.lines().collect::<Vec<_>>();
+    let b = actual.lines().collect::<Vec<_>>();
+    // The length of the longest common subsequence of `a[i..]` and `b[j..]`.
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    // (marker, line number in `actual`, line)
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push((' ', j + 1, a[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', j + 1, a[i]));
+            i += 1;
+        } else {
+            lines.push(('+', j + 1, b[j]));
+            j += 1;
+        }
+    }
+
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (marker, _, _))| *marker != ' ')
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+    let mut last = None;
+    for (k, &i) in changed.iter().enumerate() {
+        let start = i
+            .saturating_sub(CONTEXT)
+            .max(last.map_or(0, |last| last + 1));
+        if last.is_none_or(|last| start > last + 1) {
+            out.push_str(&format!("@@ line {} @@\n", lines[start].1));
+        }
+        let end = match changed.get(k + 1) {
+            Some(&next) if next <= i + 2 * CONTEXT + 1 => i,
+            _ => (i + CONTEXT).min(lines.len() - 1),
+        };
+        for (marker, _, line) in &lines[start..=end] {
+            out.push_str(&format!("{marker}{line}\n"));
+        }
+        last = Some(end);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_test() {
+        let dir = TempDir::new("testing");
+        let (root_dir, golden_dir) = (dir.join("root"), dir.join("golden"));
+        crate::bench::synthesize(&root_dir, 2, 100).unwrap();
+
+        let output = build(&root_dir, crate::BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.files.keys().collect::<Vec<_>>(),
+            vec!["article-0/index.html", "article-1/index.html", "index.html"]
+        );
+        output.write(&golden_dir).unwrap();
+        assert_eq!(output.diff(&golden_dir).unwrap(), None);
+
+        std::fs::write(
+            root_dir.join("src/article-1.md"),
+            "# Changed\n\n<!--\ndate = \"2000-01-02\"\n-->\n",
+        )
+        .unwrap();
+        std::fs::write(golden_dir.join("stale.html"), "").unwrap();
+        let output = build(&root_dir, crate::BuildOptions::default()).unwrap();
+        assert_eq!(
+            output.diff(&golden_dir).unwrap().unwrap(),
+            r##"--- article-1/index.html
+@@ line 1 @@
+ <!DOCTYPE html>
+-<title>Article 1</title>
+-<h1>Article 1</h1>
+-<h2 id="section"><a class="self-link" href="#section">Section</a></h2>
+-<p>Lorem ipsum dolor sit amet, <em>consectetur</em> adipiscing elit, sed do <strong>eiusmod</strong> tempor incididunt ut labore et dolore magna aliqua. See <a href="https://example.com/docs">the docs</a>.</p>
++<title>Changed</title>
++<h1>Changed</h1>
+ 
+--- index.html
+@@ line 1 @@
+ <!DOCTYPE html>
+-<a href="/article-1/">Article 1</a>
++<a href="/article-1/">Changed</a>
+ <a href="/article-0/">Article 0</a>
+--- stale.html
+missing in the output
+"##
+        );
+    }
+
+    #[test]
+    fn diff_lines_test() {
+        let expected = (1..=20).map(|i| format!("{i}\n")).collect::<String>();
+        let actual = expected
+            .lines()
+            .filter(|line| *line != "18")
+            .map(|line| {
+                if line == "3" {
+                    "three\n".to_string()
+                } else {
+                    format!("{line}\n")
+                }
+            })
+            .collect::<String>();
+        assert_eq!(
+            diff_lines(&expected, &actual),
+            "@@ line 1 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n@@ line 15 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
 /// For pretieer: wrapping: "proseWrap": "always"
 /// e.g. "あいう\nえお" -> "あいうえお"
 /// See the test.
@@ -86,10 +89,601 @@ pub fn remove_deno_fmt_ignore(s: &str) -> String {
     s.replace("\n<!-- deno-fmt-ignore -->\n", "\n")
 }
 
+/// Remove a UTF-8 byte order mark, and convert CRLF line endings into LF, for files saved by
+/// Windows editors.
+pub fn normalize_line_endings(s: &str) -> String {
+    s.strip_prefix('\u{feff}')
+        .unwrap_or(s)
+        .replace("\r\n", "\n")
+}
+
+/// Latin letters with diacritics and ligatures, and their ASCII forms.
+const FOLDS: &[(&str, &str)] = &[
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("àáâãäåāăą", "a"),
+    ("ÇĆĈĊČ", "C"),
+    ("çćĉċč", "c"),
+    ("ÐĎĐ", "D"),
+    ("ðďđ", "d"),
+    ("ÈÉÊËĒĔĖĘĚ", "E"),
+    ("èéêëēĕėęě", "e"),
+    ("ĜĞĠĢ", "G"),
+    ("ĝğġģ", "g"),
+    ("ĤĦ", "H"),
+    ("ĥħ", "h"),
+    ("ÌÍÎÏĨĪĬĮİ", "I"),
+    ("ìíîïĩīĭįı", "i"),
+    ("Ĵ", "J"),
+    ("ĵ", "j"),
+    ("Ķ", "K"),
+    ("ķ", "k"),
+    ("ĹĻĽĿŁ", "L"),
+    ("ĺļľŀł", "l"),
+    ("ÑŃŅŇ", "N"),
+    ("ñńņň", "n"),
+    ("ÒÓÔÕÖØŌŎŐ", "O"),
+    ("òóôõöøōŏő", "o"),
+    ("ŔŖŘ", "R"),
+    ("ŕŗř", "r"),
+    ("ŚŜŞŠ", "S"),
+    ("śŝşš", "s"),
+    ("ŢŤŦ", "T"),
+    ("ţťŧ", "t"),
+    ("ÙÚÛÜŨŪŬŮŰŲ", "U"),
+    ("ùúûüũūŭůűų", "u"),
+    ("Ŵ", "W"),
+    ("ŵ", "w"),
+    ("ÝŶŸ", "Y"),
+    ("ýÿŷ", "y"),
+    ("ŹŻŽ", "Z"),
+    ("źżž", "z"),
+    ("Æ", "AE"),
+    ("æ", "ae"),
+    ("Œ", "OE"),
+    ("œ", "oe"),
+    ("Þ", "TH"),
+    ("þ", "th"),
+    ("ß", "ss"),
+];
+
+/// Make a slug from `s`: Latin letters lose diacritics, full-width ASCII becomes ASCII, letters
+/// are lowercased, and runs of spaces and symbols other than `.` become a `-`. Letters which
+/// can't be folded into ASCII, such as kanji, are kept.
+pub fn transliterate(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut dash = false;
+    for c in s.chars() {
+        let c = match c {
+            // Full-width ASCII, such as `Ａ`.
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap(),
+            '\u{3000}' => ' ',
+            // Combining diacritical marks, e.g. in decomposed file names on macOS.
+            '\u{300}'..='\u{36f}' => continue,
+            _ => c,
+        };
+        let folded = FOLDS
+            .iter()
+            .find(|(from, _)| from.contains(c))
+            .map(|(_, to)| *to);
+        let mut push = |c: char| {
+            if c.is_alphanumeric() || c == '.' {
+                if dash && !out.is_empty() {
+                    out.push('-');
+                }
+                dash = false;
+                out.extend(c.to_lowercase());
+            } else {
+                dash = true;
+            }
+        };
+        match folded {
+            Some(to) => to.chars().for_each(&mut push),
+            None => push(c),
+        }
+    }
+    out
+}
+
+/// Percent-encode `s`, except for unreserved characters in URLs.
+pub fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+/// Decode `%XX` sequences in `s`.
+pub fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let decoded = (b == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Extract the plain text from HTML, such as rendered content, keeping a blank line between
+/// blocks. Tags are removed, with the content of `<script>` and `<style>`, and entities are
+/// decoded.
+pub fn html_to_plain(html: &str) -> String {
+    static TAG: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->|<(/?)([a-zA-Z0-9]+)[^>]*>").unwrap());
+    const BLOCKS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "dd",
+        "div",
+        "dl",
+        "dt",
+        "figcaption",
+        "figure",
+        "footer",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hr",
+        "li",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "table",
+        "tr",
+        "ul",
+    ];
+
+    let mut out = String::with_capacity(html.len());
+    let mut skip = None::<String>;
+    let mut pre_depth = 0;
+    let mut last = 0;
+    for caps in TAG.captures_iter(html) {
+        let m = caps.get(0).unwrap();
+        if skip.is_none() {
+            push_text(&mut out, &html[last..m.start()], pre_depth > 0);
+        }
+        last = m.end();
+        let Some(name) = caps.get(2) else {
+            continue;
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        let closing = !caps[1].is_empty();
+        if let Some(skipped) = skip.as_ref() {
+            if closing && *skipped == name {
+                skip = None;
+            }
+            continue;
+        }
+        match name.as_str() {
+            "script" | "style" if !closing => {
+                skip = Some(name);
+                continue;
+            }
+            "br" => out.push('\n'),
+            "pre" if closing => pre_depth -= 1,
+            "pre" => pre_depth += 1,
+            "td" | "th" if closing => out.push(' '),
+            _ => {}
+        }
+        if BLOCKS.contains(&name.as_str()) {
+            out.push_str("\n\n");
+        }
+    }
+    if skip.is_none() {
+        push_text(&mut out, &html[last..], false);
+    }
+
+    // Trim lines, and keep at most one blank line between blocks.
+    let mut plain = String::with_capacity(out.len());
+    let mut blank = false;
+    for line in out.lines().map(|line| line.trim_end()) {
+        if line.trim().is_empty() {
+            blank = !plain.is_empty();
+            continue;
+        }
+        if blank {
+            plain.push('\n');
+            blank = false;
+        }
+        plain.push_str(line);
+        plain.push('\n');
+    }
+    plain
+}
+
+/// Push text in HTML, where whitespace is collapsed unless it's preformatted.
+fn push_text(out: &mut String, text: &str, preformatted: bool) {
+    let text = decode_entities(text);
+    if preformatted {
+        out.push_str(&text);
+        return;
+    }
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !out.is_empty() && !out.ends_with([' ', '\n']) {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+/// Convert HTML, such as a post in a WordPress export, into markdown. Paragraphs, headings,
+/// emphasis, links, images, lists, quotes and code blocks become markdown, and other elements,
+/// such as tables and iframes, are kept as HTML. A blank line in text is a paragraph break, as
+/// WordPress does. `map_url` rewrites the URLs of links and images.
+pub fn html_to_markdown(html: &str, map_url: &mut dyn FnMut(&str) -> String) -> String {
+    static TAG: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?s)<!--.*?-->|<(/?)([a-zA-Z0-9]+)((?:[^>"']|"[^"]*"|'[^']*')*)>"#).unwrap()
+    });
+    static ATTR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"([a-zA-Z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+    // Elements kept as HTML, with their content.
+    const RAW: &[&str] = &[
+        "audio", "iframe", "object", "script", "style", "svg", "table", "video",
+    ];
+
+    let attr = |attrs: &str, name: &str| {
+        ATTR.captures_iter(attrs)
+            .find(|caps| caps[1].eq_ignore_ascii_case(name))
+            .map(|caps| decode_entities(caps.get(2).or(caps.get(3)).unwrap().as_str()).into_owned())
+    };
+    let block = |out: &mut String| {
+        let trimmed = out.trim_end_matches([' ', '\n']).len();
+        out.truncate(trimmed);
+        if !out.is_empty() {
+            out.push_str("\n\n");
+        }
+    };
+
+    let mut out = String::with_capacity(html.len());
+    // (name, depth, start) of a raw element.
+    let mut raw = None::<(String, usize, usize)>;
+    let mut pre = false;
+    let mut heading = false;
+    // `None` for `<ul>`, or the last number for `<ol>`.
+    let mut lists = Vec::<Option<usize>>::new();
+    let mut links = Vec::<Option<String>>::new();
+    let mut quotes = Vec::<usize>::new();
+    let mut last = 0;
+    for caps in TAG.captures_iter(html) {
+        let m = caps.get(0).unwrap();
+        if raw.is_none() {
+            let inline = heading || !lists.is_empty();
+            push_markdown_text(&mut out, &html[last..m.start()], pre, inline);
+        }
+        last = m.end();
+        let Some(name) = caps.get(2) else {
+            continue;
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        let closing = !caps[1].is_empty();
+        let attrs = &caps[3];
+        if let Some((raw_name, depth, start)) = raw.as_mut() {
+            if *raw_name == name {
+                if closing {
+                    *depth -= 1;
+                } else {
+                    *depth += 1;
+                }
+            }
+            if *depth == 0 {
+                let start = *start;
+                block(&mut out);
+                out.push_str(&html[start..m.end()]);
+                block(&mut out);
+                raw = None;
+            }
+            continue;
+        }
+        match (name.as_str(), closing) {
+            (name, false) if RAW.contains(&name) => {
+                if attrs.trim_end().ends_with('/') {
+                    block(&mut out);
+                    out.push_str(m.as_str());
+                    block(&mut out);
+                } else {
+                    raw = Some((name.to_string(), 1, m.start()));
+                }
+            }
+            // Blocks in a list item are joined into the item.
+            ("p" | "div" | "figure" | "figcaption" | "section" | "article", _)
+                if lists.is_empty() =>
+            {
+                block(&mut out)
+            }
+            ("br", _) => out.push_str("<br>\n"),
+            ("hr", _) => {
+                block(&mut out);
+                out.push_str("---");
+                block(&mut out);
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", _) => {
+                block(&mut out);
+                if !closing {
+                    let level = name[1..].parse().unwrap();
+                    out.push_str(&"#".repeat(level));
+                    out.push(' ');
+                }
+                heading = !closing;
+            }
+            ("strong" | "b", _) => out.push_str("**"),
+            ("em" | "i", _) => out.push('*'),
+            ("del" | "s", _) => out.push_str("~~"),
+            ("code", _) if !pre => out.push('`'),
+            ("pre", false) => {
+                block(&mut out);
+                out.push_str("```\n");
+                pre = true;
+            }
+            ("pre", true) => {
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str("```");
+                block(&mut out);
+                pre = false;
+            }
+            ("a", false) => {
+                let href = attr(attrs, "href").map(|href| map_url(&href));
+                if href.is_some() {
+                    out.push('[');
+                }
+                links.push(href);
+            }
+            ("a", true) => {
+                if let Some(Some(href)) = links.pop() {
+                    out.push_str(&format!("]({href})"));
+                }
+            }
+            ("img", false) => {
+                if let Some(src) = attr(attrs, "src") {
+                    let alt = attr(attrs, "alt")
+                        .unwrap_or_default()
+                        .replace(['[', ']'], "");
+                    out.push_str(&format!("![{alt}]({})", map_url(&src)));
+                }
+            }
+            ("ul" | "ol", false) => {
+                if lists.is_empty() {
+                    block(&mut out);
+                }
+                lists.push((name == "ol").then_some(0));
+            }
+            ("ul" | "ol", true) => {
+                lists.pop();
+                if lists.is_empty() {
+                    block(&mut out);
+                }
+            }
+            ("li", false) => {
+                let trimmed = out.trim_end_matches(' ').len();
+                out.truncate(trimmed);
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&"    ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        out.push_str(&format!("{n}. "));
+                    }
+                    _ => out.push_str("- "),
+                }
+            }
+            ("blockquote", false) => {
+                block(&mut out);
+                quotes.push(out.len());
+            }
+            ("blockquote", true) => {
+                if let Some(start) = quotes.pop() {
+                    let quoted = out.split_off(start);
+                    let quoted = quoted
+                        .trim()
+                        .lines()
+                        .map(|line| format!("> {line}").trim_end().to_string())
+                        .collect::<Vec<_>>();
+                    out.push_str(&quoted.join("\n"));
+                    block(&mut out);
+                }
+            }
+            _ => {}
+        }
+    }
+    if raw.is_none() {
+        push_markdown_text(&mut out, &html[last..], pre, false);
+    }
+
+    // Trim lines, and keep at most one blank line between blocks, except in code blocks.
+    let mut markdown = String::with_capacity(out.len());
+    let mut fence = false;
+    let mut blank = false;
+    for line in out.lines() {
+        if fence {
+            markdown.push_str(line);
+            markdown.push('\n');
+            fence = line != "```";
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = !markdown.is_empty();
+            continue;
+        }
+        if blank {
+            markdown.push('\n');
+            blank = false;
+        }
+        markdown.push_str(line);
+        markdown.push('\n');
+        fence = line == "```";
+    }
+    markdown
+}
+
+/// Push text in HTML as markdown, escaping characters which are special in markdown. Unless
+/// `inline`, newlines are kept, so that a blank line is a paragraph break.
+fn push_markdown_text(out: &mut String, text: &str, preformatted: bool, inline: bool) {
+    let text = decode_entities(text);
+    if preformatted {
+        out.push_str(&text);
+        return;
+    }
+    for c in text.chars() {
+        match c {
+            '\n' if !inline => {
+                let trimmed = out.trim_end_matches(' ').len();
+                out.truncate(trimmed);
+                out.push('\n');
+            }
+            c if c.is_whitespace() => {
+                if !out.is_empty() && !out.ends_with([' ', '\n']) {
+                    out.push(' ');
+                }
+            }
+            '\\' | '*' | '_' | '`' | '[' | ']' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '<' => out.push_str("&lt;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Decode character references, such as `&amp;` and `&#x3042;`.
+pub(crate) fn decode_entities(s: &str) -> std::borrow::Cow<'_, str> {
+    static ENTITY: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+    ENTITY.replace_all(s, |caps: &regex::Captures<'_>| {
+        let entity = &caps[1];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "hellip" => Some('…'),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            "copy" => Some('©'),
+            _ => match entity.strip_prefix('#') {
+                Some(n) => match n.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => n.parse().ok(),
+                }
+                .and_then(char::from_u32),
+                None => None,
+            },
+        };
+        c.map_or_else(|| caps[0].to_string(), String::from)
+    })
+}
+
+/// The number of words in plain text, where each wide character, such as a kanji, counts as a
+/// word, since CJK text isn't separated by spaces.
+pub fn word_count(s: &str) -> usize {
+    use unicode_width::UnicodeWidthChar;
+
+    let mut count = 0;
+    let mut in_word = false;
+    for c in s.chars() {
+        if c.width().is_some_and(|w| w >= 2) {
+            in_word = false;
+            if c.is_alphanumeric() {
+                count += 1;
+            }
+        } else if c.is_whitespace() {
+            in_word = false;
+        } else if c.is_alphanumeric() && !in_word {
+            in_word = true;
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Extract the plain text from markdown, keeping a blank line between blocks.
+pub fn markdown_to_plain(s: &str) -> String {
+    use pulldown_cmark::{Event, Parser, TagEnd};
+
+    let mut out = String::with_capacity(s.len());
+    for event in Parser::new(s) {
+        match event {
+            Event::Text(s) | Event::Code(s) | Event::InlineMath(s) | Event::DisplayMath(s) => {
+                out.push_str(&s)
+            }
+            Event::SoftBreak | Event::HardBreak => out.push('\n'),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::CodeBlock
+                | TagEnd::Item
+                | TagEnd::BlockQuote(_),
+            ) => {
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+    out.push('\n');
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn slug_test() {
+        assert_eq!(transliterate("Crème Brûlée"), "creme-brulee");
+        assert_eq!(transliterate("Straße_und  Œuvre!"), "strasse-und-oeuvre");
+        assert_eq!(transliterate("Ｒｕｓｔ　入門 (1)"), "rust-入門-1");
+        assert_eq!(transliterate("Cre\u{300}me"), "creme");
+        assert_eq!(transliterate("feed.xml"), "feed.xml");
+        assert_eq!(transliterate("--a--"), "a");
+
+        assert_eq!(percent_encode("日本 a-b"), "%E6%97%A5%E6%9C%AC%20a-b");
+        assert_eq!(percent_decode("%E6%97%A5%E6%9C%AC%20a-b/"), "日本 a-b/");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
     #[test]
     fn remove_prettier_ignore_preceeding_code_block_test() {
         let s = r"foo
@@ -147,4 +741,106 @@ ab";
 えお";
         assert_eq!(remove_newline_between_cjk(s), "あいう\n\nえお");
     }
+
+    #[test]
+    fn normalize_line_endings_test() {
+        assert_eq!(normalize_line_endings("\u{feff}a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(normalize_line_endings("a\rb\n"), "a\rb\n");
+        assert_eq!(normalize_line_endings("a\u{feff}"), "a\u{feff}");
+    }
+
+    #[test]
+    fn html_to_plain_test() {
+        let html = r##"<h2 id="a"><a class="self-link" href="#a">Tom &amp; Jerry</a></h2>
+<p>Hello <em>world</em>,
+and&nbsp;&#x3042;&#12354;.<br>Next line.</p>
+<script>alert("x")</script><style>p { color: red; }</style>
+<!-- <p>comment</p> -->
+<ul>
+<li>a</li>
+<li>b</li>
+</ul>
+<pre><code>fn main() {
+    1 &lt; 2
+}
+</code></pre>
+<table><tr><td>1</td><td>2</td></tr></table>"##;
+        assert_eq!(
+            html_to_plain(html),
+            "Tom & Jerry\n\nHello world, and ああ.\nNext line.\n\na\n\nb\n\nfn main() {\n    1 < 2\n}\n\n1 2\n"
+        );
+    }
+
+    #[test]
+    fn html_to_markdown_test() {
+        let html = r##"<!-- wp:paragraph -->
+<h2>Tom &amp; <em>Jerry</em></h2>
+First line
+second line with <strong>bold</strong>, *stars* and <a href="/about">a link</a>.
+
+<img src="https://example.com/a.png" alt="A [cat]">
+<ul>
+<li>one
+<ol><li>two</li><li>three</li></ol></li>
+<li>four</li>
+</ul>
+<blockquote><p>Quote</p><p>More</p></blockquote>
+<pre><code>fn main() {
+
+    1 &lt; 2
+}</code></pre>
+<table>
+<tr><td>1</td></tr>
+</table>
+Done."##;
+        let mut map_url = |url: &str| url.replace("https://example.com/", "/images/");
+        assert_eq!(
+            html_to_markdown(html, &mut map_url),
+            r##"## Tom & *Jerry*
+
+First line
+second line with **bold**, \*stars\* and [a link](/about).
+
+![A cat](/images/a.png)
+
+- one
+    1. two
+    2. three
+- four
+
+> Quote
+>
+> More
+
+```
+fn main() {
+
+    1 < 2
+}
+```
+
+<table>
+<tr><td>1</td></tr>
+</table>
+
+Done.
+"##
+        );
+    }
+
+    #[test]
+    fn markdown_to_plain_test() {
+        let s = r"# Title
+
+Hello *world* and `code`.
+Next [link](https://example.com).
+
+- a
+- b
+";
+        assert_eq!(
+            markdown_to_plain(s),
+            "Title\n\nHello world and code.\nNext link.\n\na\n\nb\n"
+        );
+    }
 }
@@ -0,0 +1,107 @@
+use regex::Regex;
+use serde::Serialize;
+use std::sync::LazyLock;
+
+use crate::html;
+
+/// A heading in the table of contents.
+#[derive(PartialEq, Eq, Debug, Serialize)]
+pub struct TocEntry {
+    pub level: usize,
+    pub id: String,
+    /// HTML, such as `Hello <code>world</code>`.
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// Extract the heading tree from HTML which is processed by [`html::build_header_links`].
+pub fn from_html(html: &str) -> Vec<TocEntry> {
+    static HEADING: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r##"<h(\d) id="(.*?)"><a class="self-link" href="#.*?">(.*?)</a></h\d>"##)
+            .unwrap()
+    });
+
+    let headings = HEADING
+        .captures_iter(html)
+        .map(|caps| {
+            (
+                caps[1].parse().unwrap(),
+                caps[2].to_string(),
+                caps[3].to_string(),
+            )
+        })
+        .collect::<Vec<(usize, String, String)>>();
+    build_tree(&headings)
+}
+
+fn build_tree(headings: &[(usize, String, String)]) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while i < headings.len() {
+        let (level, id, title) = &headings[i];
+        let end = headings[i + 1..]
+            .iter()
+            .position(|(l, _, _)| l <= level)
+            .map_or(headings.len(), |pos| i + 1 + pos);
+        entries.push(TocEntry {
+            level: *level,
+            id: id.clone(),
+            title: title.clone(),
+            children: build_tree(&headings[i + 1..end]),
+        });
+        i = end;
+    }
+    entries
+}
+
+/// The default TOC markup, used if `toc.jinja` doesn't exist.
+pub fn to_html(entries: &[TocEntry]) -> String {
+    fn list(entries: &[TocEntry], out: &mut String) {
+        out.push_str("<ul>\n");
+        for entry in entries {
+            out.push_str(&format!(
+                "<li><a href=\"#{}\">{}</a>",
+                html::escape(&entry.id),
+                entry.title
+            ));
+            if !entry.children.is_empty() {
+                out.push('\n');
+                list(&entry.children, out);
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str("</ul>\n");
+    }
+
+    let mut out = String::from("<nav class=\"toc\">\n");
+    list(entries, &mut out);
+    out.push_str("</nav>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toc_test() {
+        let html = html::build_header_links(
+            "<h2>A</h2>\n<p>x</p>\n<h3>A1</h3>\n<h3>A2</h3>\n<h4>A2a</h4>\n<h2>B <code>b</code></h2>\n",
+            &mut html::IdAllocator::default(),
+        );
+        let toc = from_html(&html);
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].id, "a");
+        assert_eq!(toc[0].children.len(), 2);
+        assert_eq!(toc[0].children[1].id, "a2");
+        assert_eq!(toc[0].children[1].children[0].title, "A2a");
+        assert_eq!(toc[1].level, 2);
+        assert_eq!(toc[1].title, "B <code>b</code>");
+        assert!(toc[1].children.is_empty());
+
+        assert_eq!(
+            to_html(&toc[1..]),
+            "<nav class=\"toc\">\n<ul>\n<li><a href=\"#b-b\">B <code>b</code></a></li>\n</ul>\n</nav>\n"
+        );
+    }
+}
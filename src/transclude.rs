@@ -0,0 +1,181 @@
+//! `{{< transclude setup-guide.md heading="Install" >}}`: embed another markdown, or a section of
+//! it, at build time, so that content shared by several articles stays in sync.
+
+use anyhow::{anyhow, Context as _, Result};
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::shortcode::parse_args;
+use crate::site::slash_path;
+
+/// Expand transclusions in `markdown` of the markdown at `path`, relative to `src`, recursively.
+/// `load` returns the content of a markdown, without its title and metadata.
+pub fn expand(
+    markdown: &str,
+    path: &Path,
+    load: &impl Fn(&Path) -> Result<String>,
+) -> Result<String> {
+    expand_in(markdown, &mut vec![path.to_path_buf()], load)
+}
+
+fn expand_in(
+    markdown: &str,
+    stack: &mut Vec<PathBuf>,
+    load: &impl Fn(&Path) -> Result<String>,
+) -> Result<String> {
+    static TRANSCLUDE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\s*\{\{<\s*transclude\s(.*?)\s*>\}\}\s*$").unwrap());
+
+    if !markdown.contains("transclude") {
+        return Ok(markdown.to_string());
+    }
+    let mut out = String::with_capacity(markdown.len());
+    let mut fence = None::<String>;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(f) = fence.as_ref() {
+            if trimmed.starts_with(f.as_str()) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(trimmed[..3].to_string());
+        } else if let Some(caps) = TRANSCLUDE.captures(line.trim_end()) {
+            let args = parse_args(&caps[1]);
+            let target = args
+                .get("0")
+                .ok_or_else(|| anyhow!("transclude needs a path: {}", line.trim()))?;
+            let target = resolve(stack.last().unwrap(), target)?;
+            if stack.contains(&target) {
+                let cycle = stack
+                    .iter()
+                    .chain([&target])
+                    .map(|path| slash_path(path))
+                    .collect::<Vec<_>>();
+                return Err(anyhow!("transclusion cycle: {}", cycle.join(" -> ")));
+            }
+            let content = load(&target)
+                .with_context(|| format!("can not transclude: {}", slash_path(&target)))?;
+            let content = match args.get("heading") {
+                Some(heading) => section(&content, heading).ok_or_else(|| {
+                    anyhow!("no heading \"{heading}\" in {}", slash_path(&target))
+                })?,
+                None => content,
+            };
+            stack.push(target);
+            let content = expand_in(&content, stack, load)?;
+            stack.pop();
+            out.push_str(content.trim_end());
+            out.push_str("\n\n");
+            continue;
+        }
+        out.push_str(line);
+    }
+    Ok(out)
+}
+
+/// `target` relative to the directory of `from`, or to `src` if it starts with "/".
+fn resolve(from: &Path, target: &str) -> Result<PathBuf> {
+    let joined = match target.strip_prefix('/') {
+        Some(target) => PathBuf::from(target),
+        None => from.parent().unwrap_or(Path::new("")).join(target),
+    };
+    let mut path = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::Normal(c) => path.push(c),
+            Component::CurDir => {}
+            Component::ParentDir if path.pop() => {}
+            _ => return Err(anyhow!("not in src: {target}")),
+        }
+    }
+    Ok(path)
+}
+
+/// The lines under the heading whose text is `heading`, until the next heading of the same or a
+/// higher level. The heading itself is not included.
+fn section(markdown: &str, heading: &str) -> Option<String> {
+    static HEADING: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(#{1,6})\s+(.*?)(?:\s+#+)?\s*$").unwrap());
+
+    let mut level = None;
+    let mut out = String::new();
+    let mut fence = None::<String>;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(f) = fence.as_ref() {
+            if trimmed.starts_with(f.as_str()) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(trimmed[..3].to_string());
+        } else if let Some(caps) = HEADING.captures(line.trim_end()) {
+            match level {
+                Some(level) if caps[1].len() <= level => break,
+                None if &caps[2] == heading => {
+                    level = Some(caps[1].len());
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if level.is_some() {
+            out.push_str(line);
+        }
+    }
+    level.map(|_| out.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn expand_test() {
+        let files = BTreeMap::from([
+            (
+                "guides/setup.md",
+                "## Install\n\nRun `cargo install site`.\n\n### Linux\n\nSame.\n\n## Usage\n\nRun it.\n",
+            ),
+            ("a.md", "{{< transclude b.md >}}\n"),
+            ("b.md", "{{< transclude /a.md >}}\n"),
+        ]);
+        let load = |path: &Path| -> Result<String> {
+            files
+                .get(slash_path(path).as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow!("not found"))
+        };
+        assert_eq!(
+            expand(
+                "Intro.\n\n{{< transclude ../guides/setup.md heading=\"Install\" >}}\n\n```\n{{< transclude setup.md >}}\n```\n",
+                Path::new("tutorials/first.md"),
+                &load
+            )
+            .unwrap(),
+            "Intro.\n\nRun `cargo install site`.\n\n### Linux\n\nSame.\n\n\n```\n{{< transclude setup.md >}}\n```\n"
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                expand("{{< transclude a.md >}}\n", Path::new("a.md"), &load).unwrap_err()
+            ),
+            "transclusion cycle: a.md -> a.md"
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                expand("{{< transclude b.md >}}\n", Path::new("a.md"), &load).unwrap_err()
+            ),
+            "transclusion cycle: a.md -> b.md -> a.md"
+        );
+        assert!(expand(
+            "{{< transclude setup.md heading=\"Uninstall\" >}}\n",
+            Path::new("guides/x.md"),
+            &load
+        )
+        .is_err());
+        assert!(expand("{{< transclude ../../x.md >}}\n", Path::new("a.md"), &load).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+use crate::html::escape;
+
+/// `[webmention]`: where this site receives webmentions and pingbacks, and where sent ones are
+/// recorded.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WebmentionConfig {
+    /// The webmention endpoint, such as "https://webmention.io/example.com/webmention".
+    pub endpoint: Option<String>,
+    /// The pingback endpoint, such as "https://webmention.io/example.com/xmlrpc".
+    pub pingback: Option<String>,
+    /// The file recording sent webmentions, relative to `root-dir`.
+    pub state_file: Option<String>,
+}
+
+impl WebmentionConfig {
+    pub const DEFAULT_STATE_FILE: &'static str = "webmention.json";
+
+    /// `<link>` elements for endpoint discovery, which are injected into `<head>`.
+    pub fn links(&self) -> String {
+        let mut links = String::new();
+        if let Some(endpoint) = self.endpoint.as_ref() {
+            links.push_str(&format!(
+                "<link rel=\"webmention\" href=\"{}\">\n",
+                escape(endpoint)
+            ));
+        }
+        if let Some(pingback) = self.pingback.as_ref() {
+            links.push_str(&format!(
+                "<link rel=\"pingback\" href=\"{}\">\n",
+                escape(pingback)
+            ));
+        }
+        links
+    }
+}
+
+/// Sent webmentions: source URL => target URLs.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct State {
+    pub sent: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl State {
+    pub fn is_sent(&self, source: &str, target: &str) -> bool {
+        self.sent
+            .get(source)
+            .is_some_and(|targets| targets.contains(target))
+    }
+
+    pub fn mark_sent(&mut self, source: &str, target: &str) {
+        self.sent
+            .entry(source.to_string())
+            .or_default()
+            .insert(target.to_string());
+    }
+}
+
+/// Links in `html` to other sites, in order, without duplicates. Links under `base_url` are
+/// excluded, unless it's empty. `https://example.com.evil` is not under `https://example.com`.
+pub fn external_links(html: &str, base_url: &str) -> Vec<String> {
+    static HREF: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"<a\s[^>]*?href="(https?://[^"]+)""#).unwrap());
+    let mut seen = BTreeSet::new();
+    HREF.captures_iter(html)
+        .map(|caps| caps[1].replace("&amp;", "&"))
+        .filter(|url| base_url.is_empty() || !is_under(url, base_url))
+        .filter(|url| seen.insert(url.clone()))
+        .collect()
+}
+
+/// Whether `url` is `base_url` or under it, such as `https://example.com/blog/a?b` under
+/// `https://example.com/blog`.
+fn is_under(url: &str, base_url: &str) -> bool {
+    url.strip_prefix(base_url.trim_end_matches('/'))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']))
+}
+
+/// Resolve `href` relative to `base`, such as a page's URL.
+pub fn resolve_url(base: &str, href: &str) -> String {
+    let scheme_end = base.find("://").map_or(0, |i| i + 3);
+    let origin_end = base[scheme_end..]
+        .find('/')
+        .map_or(base.len(), |i| scheme_end + i);
+    if href.is_empty() {
+        base.to_string()
+    } else if href.contains("://") {
+        href.to_string()
+    } else if href.starts_with("//") {
+        format!("{}{href}", &base[..scheme_end - 2])
+    } else if href.starts_with('/') {
+        format!("{}{href}", &base[..origin_end])
+    } else {
+        let path = base.split(['?', '#']).next().unwrap();
+        let dir_end = path[origin_end..]
+            .rfind('/')
+            .map_or(path.len(), |i| origin_end + i);
+        format!("{}/{href}", &path[..dir_end])
+    }
+}
+
+/// Discover the webmention endpoint of `target` from the `Link` header, then from `<link>` and
+/// `<a>` elements in the HTML.
+pub fn discover_endpoint(target: &str, link_header: Option<&str>, html: &str) -> Option<String> {
+    static LINK_HEADER: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"<([^>]*)>\s*;\s*rel\s*=\s*"?([^";,]*)"?"#).unwrap());
+    static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<(?:link|a)\s[^>]*>").unwrap());
+    static ATTR: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"([a-zA-Z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap()
+    });
+    let is_webmention = |rel: &str| rel.split_whitespace().any(|rel| rel == "webmention");
+
+    let from_header = link_header.and_then(|header| {
+        LINK_HEADER
+            .captures_iter(header)
+            .find(|caps| is_webmention(&caps[2]))
+            .map(|caps| caps[1].to_string())
+    });
+    let href = from_header.or_else(|| {
+        TAG.find_iter(html).find_map(|tag| {
+            let attrs = ATTR
+                .captures_iter(tag.as_str())
+                .map(|caps| {
+                    let value = caps.get(2).or(caps.get(3)).or(caps.get(4)).unwrap();
+                    (caps[1].to_ascii_lowercase(), value.as_str().to_string())
+                })
+                .collect::<BTreeMap<_, _>>();
+            attrs
+                .get("rel")
+                .filter(|rel| is_webmention(rel))
+                .and(attrs.get("href"))
+                .map(|href| href.replace("&amp;", "&"))
+        })
+    })?;
+    Some(resolve_url(target, &href))
+}
+
+/// Send a webmention from `source` to `target`, if `target` has an endpoint. Returns whether it
+/// was sent.
+#[cfg(feature = "webmention")]
+pub fn send(source: &str, target: &str) -> crate::Result<bool> {
+    use std::io::Read as _;
+
+    let response = ureq::get(target).call()?;
+    let link_header = response.header("Link").map(String::from);
+    let mut html = String::new();
+    // The endpoint should be in `<head>`.
+    response
+        .into_reader()
+        .take(1024 * 1024)
+        .read_to_string(&mut html)?;
+    let Some(endpoint) = discover_endpoint(target, link_header.as_deref(), &html) else {
+        return Ok(false);
+    };
+    ureq::post(&endpoint).send_form(&[("source", source), ("target", target)])?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discover_endpoint_test() {
+        let html = r#"<p>See <a href="https://example.com/a">a</a>, <a href="/b">b</a>,
+<a class="x" href="https://other.example/?p=1&amp;q=2">c</a>
+and <a href="https://example.com/a">a again</a>.</p>"#;
+        assert_eq!(
+            external_links(html, "https://example.org"),
+            vec!["https://example.com/a", "https://other.example/?p=1&q=2"]
+        );
+        assert_eq!(
+            external_links(html, "https://example.com"),
+            vec!["https://other.example/?p=1&q=2"]
+        );
+        let html = r#"<a href="https://example.com.evil/a">a</a> <a href="https://example.com/blog">b</a>
+<a href="https://example.com/blogroll">c</a> <a href="https://example.com/blog/d?e">d</a>"#;
+        assert_eq!(
+            external_links(html, "https://example.com/blog/"),
+            vec!["https://example.com.evil/a", "https://example.com/blogroll"]
+        );
+
+        let target = "https://example.com/posts/1?x=y";
+        assert_eq!(
+            discover_endpoint(
+                target,
+                Some(r#"<https://example.com/>; rel="home", </wm>; rel="webmention""#),
+                ""
+            ),
+            Some("https://example.com/wm".to_string())
+        );
+        assert_eq!(
+            discover_endpoint(
+                target,
+                None,
+                r#"<link rel="stylesheet" href="a.css"><link href="endpoint" rel="webmention">"#
+            ),
+            Some("https://example.com/posts/endpoint".to_string())
+        );
+        assert_eq!(
+            discover_endpoint(target, None, r#"<a rel='webmention' href=''>"#),
+            Some(target.to_string())
+        );
+        assert_eq!(
+            discover_endpoint(
+                target,
+                None,
+                r#"<a href="//wm.example/e" rel="me webmention">"#
+            ),
+            Some("https://wm.example/e".to_string())
+        );
+        assert_eq!(discover_endpoint(target, None, "<p>no</p>"), None);
+    }
+}
@@ -0,0 +1,231 @@
+//! A minimal XML parser for importing exports, such as WordPress's WXR. DTDs and namespaces
+//! are not supported. Prefixed names, such as `wp:post_name`, are kept as they are. Also,
+//! `cdata` for XML templates.
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::text::decode_entities;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Child elements named `name`.
+    pub fn elements<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter_map(move |node| match node {
+            Node::Element(e) if e.name == name => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn element(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find_map(|node| match node {
+            Node::Element(e) if e.name == name => Some(e),
+            _ => None,
+        })
+    }
+
+    /// The text of the child element `name`, trimmed, if it's not empty.
+    pub fn child_text(&self, name: &str) -> Option<String> {
+        self.element(name)
+            .map(|e| e.text().trim().to_string())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// The concatenated text of descendants.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Element(e) => text.push_str(&e.text()),
+                Node::Text(t) => text.push_str(t),
+            }
+        }
+        text
+    }
+}
+
+/// A CDATA section of `s`, which is split where `s` contains `]]>`, so that it can hold any text,
+/// such as HTML in `<content:encoded>`.
+pub fn cdata(s: &str) -> String {
+    format!("<![CDATA[{}]]>", s.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Parse `s` into the root element.
+pub fn parse(s: &str) -> Result<Element> {
+    static ATTR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"([^\s=/>]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+    // The root is a virtual element holding the document element.
+    let mut stack = vec![Element::default()];
+    let mut rest = s;
+    let line = |rest: &str| s[..s.len() - rest.len()].lines().count().max(1);
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            push_text(&mut stack, &decode_entities(rest));
+            break;
+        };
+        push_text(&mut stack, &decode_entities(&rest[..start]));
+        rest = &rest[start..];
+        let skip = |rest: &str, end: &str| {
+            rest.find(end)
+                .map(|i| i + end.len())
+                .ok_or_else(|| anyhow!("line {}: missing \"{end}\"", line(rest)))
+        };
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = skip(cdata, "]]>")?;
+            push_text(&mut stack, &cdata[..end - 3]);
+            rest = &cdata[end..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[skip(rest, "-->")?..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[skip(rest, "?>")?..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip(rest, ">")?..];
+        } else if let Some(close) = rest.strip_prefix("</") {
+            let end = skip(close, ">")?;
+            let name = close[..end - 1].trim();
+            let element = stack
+                .pop()
+                .filter(|e| e.name == name && !stack.is_empty())
+                .ok_or_else(|| anyhow!("line {}: unexpected </{name}>", line(rest)))?;
+            stack
+                .last_mut()
+                .unwrap()
+                .children
+                .push(Node::Element(element));
+            rest = &close[end..];
+        } else {
+            let end = tag_end(rest).ok_or_else(|| anyhow!("line {}: missing \">\"", line(rest)))?;
+            let tag = &rest[1..end];
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+            let element = Element {
+                name: tag[..name_end].to_string(),
+                attrs: ATTR
+                    .captures_iter(&tag[name_end..])
+                    .map(|caps| {
+                        let value = caps.get(2).or(caps.get(3)).unwrap().as_str();
+                        (caps[1].to_string(), decode_entities(value).into_owned())
+                    })
+                    .collect(),
+                children: Vec::new(),
+            };
+            if empty {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .children
+                    .push(Node::Element(element));
+            } else {
+                stack.push(element);
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+    if stack.len() > 1 {
+        return Err(anyhow!("<{}> is not closed", stack.last().unwrap().name));
+    }
+    stack
+        .pop()
+        .unwrap()
+        .children
+        .into_iter()
+        .find_map(|node| match node {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+        .ok_or_else(|| anyhow!("no element"))
+}
+
+/// The index of `>` which ends the tag at the beginning of `s`, outside of quoted attributes.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let children = &mut stack.last_mut().unwrap().children;
+    if let Some(Node::Text(last)) = children.last_mut() {
+        last.push_str(text);
+    } else {
+        children.push(Node::Text(text.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cdata_test() {
+        assert_eq!(cdata("<p>a</p>"), "<![CDATA[<p>a</p>]]>");
+        assert_eq!(cdata("a]]>b"), "<![CDATA[a]]]]><![CDATA[>b]]>");
+    }
+
+    #[test]
+    fn parse_test() {
+        let root = parse(
+            r#"<?xml version="1.0"?>
+<!-- comment -->
+<rss version="2.0"><channel>
+  <item><title>A &amp; B</title><wp:post_name>a-b</wp:post_name>
+    <content:encoded><![CDATA[<p>Hello</p>]]></content:encoded>
+    <category domain="post_tag" nicename="rust">Rust</category>
+    <empty />
+  </item>
+</channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(root.name, "rss");
+        assert_eq!(root.attr("version"), Some("2.0"));
+        let item = root.element("channel").unwrap().element("item").unwrap();
+        assert_eq!(item.child_text("title").as_deref(), Some("A & B"));
+        assert_eq!(item.child_text("wp:post_name").as_deref(), Some("a-b"));
+        assert_eq!(
+            item.child_text("content:encoded").as_deref(),
+            Some("<p>Hello</p>")
+        );
+        let category = item.element("category").unwrap();
+        assert_eq!(category.attr("nicename"), Some("rust"));
+        assert!(item.element("empty").is_some());
+
+        assert_eq!(
+            parse("<a>\n<b></a>").unwrap_err().to_string(),
+            "line 2: unexpected </a>"
+        );
+    }
+}